@@ -0,0 +1,53 @@
+//! How long the field of view takes to compute at different torch radii, on
+//! a map the size of the game's with a grid of rooms and doorways in it.
+//!
+//! Run with `cargo bench --bench fov`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tcod::map::{FovAlgorithm, Map as FovMap};
+
+// the same as in the game
+const MAP_WIDTH: i32 = 100;
+const MAP_HEIGHT: i32 = 50;
+const TORCH_RADIUS: i32 = 10;
+
+/// rooms 10 tiles apart, their walls broken by a doorway in the middle of each
+fn house() -> FovMap {
+    let mut fov = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            let wall_x = x % 10 == 0 && y % 10 != 5;
+            let wall_y = y % 10 == 0 && x % 10 != 5;
+            let open = !(wall_x || wall_y);
+            fov.set(x, y, open, open);
+        }
+    }
+    fov
+}
+
+fn compute_fov(c: &mut Criterion) {
+    let mut fov = house();
+    let mut group = c.benchmark_group("compute_fov");
+    // 0 is no limit at all
+    for &radius in &[4, 8, TORCH_RADIUS, 16, 0] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(radius),
+            &radius,
+            |b, &radius| {
+                b.iter(|| {
+                    fov.compute_fov(
+                        MAP_WIDTH / 2 + 2,
+                        MAP_HEIGHT / 2 + 2,
+                        radius,
+                        true,
+                        FovAlgorithm::Basic,
+                    )
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, compute_fov);
+criterion_main!(benches);
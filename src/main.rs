@@ -1,16 +1,25 @@
 #[macro_use]
 extern crate serde_derive;
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
 use tcod::colors::{self, Color};
 use tcod::console::*;
-use tcod::input::{self, Event, Key, Mouse};
+use tcod::input::{self, Event, Key, KeyCode, Mouse};
 use tcod::map::{FovAlgorithm, Map as FovMap};
 
+use std::cell::RefCell;
 use std::cmp;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::fs::File;
-use std::io::{Read, Write};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 // actual size of the window
 const SCREEN_WIDTH: i32 = 100;
@@ -20,28 +29,128 @@ const SCREEN_HEIGHT: i32 = 60;
 const MAP_WIDTH: i32 = 100;
 const MAP_HEIGHT: i32 = 50;
 
+// width of the part of the map shown on screen; the camera scrolls over maps
+// bigger than this. The view goes down to the panel, see `PanelParams`
+const VIEW_WIDTH: i32 = SCREEN_WIDTH;
+
 // sizes and coordinates relevant for the GUI
 const BAR_WIDTH: i32 = 20;
 const BAR_TOP_PADDING: i32 = 1;
 const BAR_LEFT_PADDING: i32 = 3;
-const PANEL_HEIGHT: i32 = 8;
-const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+const BARS_PER_COLUMN: i32 = 4;
+const PANEL_MIN_HEIGHT: i32 = BAR_TOP_PADDING + BARS_PER_COLUMN + 3; // fits the bars and the clock
 const MSG_X: i32 = BAR_WIDTH + 2;
-const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
-const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
 const INVENTORY_WIDTH: i32 = 50;
+const INVENTORY_CAPACITY: usize = 26; // one item per letter of the inventory menu
+const HELP_WIDTH: i32 = 60;
+const HELP_HEIGHT: i32 = 16; // rows of key bindings visible at once
 const CHARACTER_SCREEN_WIDTH: i32 = 30;
-
-// parameters for house generator
-const ROOM_MAX_SIZE: i32 = 10;
-const ROOM_MIN_SIZE: i32 = 6;
-const MAX_ROOMS: i32 = 30;
+const LIST_WIDTH: i32 = 70;
+const LIST_HEIGHT: i32 = 20; // rows of the message history or object list visible at once
 
 const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic; // default FOV algorithm
 const FOV_LIGHT_WALLS: bool = true; // light walls or not
 const TORCH_RADIUS: i32 = 10;
+const TORCH_FLICKER: f32 = 0.15; // how much of its brightness the torch can lose when flickering
+const TORCH_FALLOFF: f32 = 0.6; // how much dimmer the edge of the torchlight is than its middle
+
+// parameters for social interactions
+const TALK_SOCIAL_GAIN: i32 = 15;
+const TALK_COOLDOWN: i32 = 20; // turns before the same NPC will chat again
+const STINK_HYGIENE: i32 = 30; // below this much hygiene, people start to notice
+const STINK_MAX_RADIUS: f32 = 6.0; // how far the smell carries at zero hygiene
+
+// how NPCs feel about the player, from -100 to 100
+const RELATIONSHIP_START: i32 = 30;
+const RELATIONSHIP_FRIENDLY: i32 = 20; // at or above this they come over for a chat
+const RELATIONSHIP_HOSTILE: i32 = -20; // at or below this they come over to pick on the player
+const RELATIONSHIP_TALK: i32 = 5; // a chat
+const RELATIONSHIP_PESTER: i32 = -10; // bumping into someone who doesn't want to talk
+const RELATIONSHIP_SINGED: i32 = -25; // getting caught in a fireball
+const INSULT_SOCIAL_LOSS: i32 = 10;
+const INSULT_COOLDOWN: i32 = 15; // turns between a hostile NPC's jibes
+
+// broken furniture makes the place feel worse
+const BROKEN_FURNITURE_RADIUS: f32 = 8.0;
+const BROKEN_FURNITURE_ROOM_DRAIN: f64 = 5.0; // extra room points lost per 100 turns, per piece
+const REPAIR_ENERGY_COST: i32 = 10;
+
+// NPCs using the furniture too
+const NPC_FURNITURE_CHANCE: f64 = 0.1; // chance per turn a wandering NPC sits down next to something
+const NPC_FURNITURE_TURNS: i32 = 30; // how long they keep it for
+const NPC_LOW_NEED: i32 = 30; // below this an NPC goes looking for furniture to take care of it
+const NPC_NEED_DRAIN: f64 = 0.5; // NPCs' needs drain at this fraction of the player's rate
+const FURNITURE_WAIT_LIMIT: i32 = 2 * 60; // turns the player waits for a piece before giving up
+
+// sprinting
+const SPRINT_ENERGY_COST: i32 = 2; // energy each extra tile of a sprint costs
+
+// the fireball scroll
+const FIREBALL_RANGE: f32 = 8.0; // how far away the player can aim it
+const FIREBALL_RADIUS: f32 = 3.0;
+const FIREBALL_COMFORT_LOSS: i32 = 25; // taken from everyone caught in a blast
+const FIREBALL_FURNITURE_DAMAGE: i32 = 3; // durability burnt off furniture caught in a blast
+
+// sampling of the needs for the history graph
+const HISTORY_SAMPLE_INTERVAL: u32 = 10; // turns between samples
+const HISTORY_CAPACITY: usize = 60; // samples kept, oldest are dropped first
+const HISTORY_GRAPH_HEIGHT: i32 = 4;
 
 const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
+const ANIMATION_FRAME_TICKS: u32 = 5; // screen frames each frame of an animation lasts
+
+// where settings and saves live
+const CONFIG_PATH: &str = "lardum.json";
+const FONT_PATH: &str = "assets/consolas12x12_gs_tc.png";
+const SAVE_PATH: &str = "game.sav";
+const AUTOSAVE_PATH: &str = "autosave.sav";
+const SAVE_ATTEMPTS: u32 = 3; // tries at writing a save before giving up
+const SAVE_RETRY_DELAY_MS: u64 = 50; // wait before the first retry, doubled after each
+const DESCRIBE_PATH: &str = "objects.json"; // where the debug console's `describe` writes to
+const DIAG_LOG_PATH: &str = "lardum.log"; // diagnostics for bug reports, if turned on
+const THUMBNAIL_SCALE: i32 = 4; // map tiles per thumbnail character, each way
+const LANG_DIR: &str = "lang";
+
+// holding a movement key repeats the step after a short delay
+const KEY_REPEAT_DELAY: i32 = 6; // frames before the first repeat
+const KEY_REPEAT_INTERVAL: i32 = 2; // frames between repeats after that
+const KEY_QUEUE_CAPACITY: usize = 8; // key presses remembered between frames
+
+const UNDO_DEPTH: usize = 10; // turns the player can take back
+
+// the clock: every turn is a minute
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const START_TIME: u32 = 8 * 60; // a new game starts at 08:00
+const NIGHT_START: u32 = 22 * 60;
+const NIGHT_END: u32 = 6 * 60;
+const SLEEP_ENERGY_RATE: f64 = 30.0; // energy regained per 100 turns of sleep
+const WEATHER_CHANGE_INTERVAL: u32 = 4 * 60; // turns between chances for the weather to change
+
+// random events
+const POWER_OUTAGE_TURNS: i32 = 2 * 60;
+const LEAK_TURNS: i32 = 3 * 60;
+const LEAK_DRAIN_INTERVAL: u32 = 10; // turns between each point of hygiene a leak costs
+
+// guests dropping by during the day
+const GUEST_CHANCE: f64 = 0.005; // chance per daytime turn that someone comes to visit
+const GUEST_VISIT_TURNS: i32 = 60; // how long a guest stays once they've reached the player
+const GUEST_ARRIVE_TURNS: i32 = 100; // how long a guest looks for the player before giving up
+const GUEST_SOCIAL_BONUS: i32 = 10; // extra social for chatting with a guest
+
+// the pet
+const PET_FOLLOW_DISTANCE: f32 = 2.0; // it catches up once the player is further away than this
+const PET_CHEER_INTERVAL: u32 = 10; // turns between each bit of cheer from a pet close by, so it adds up slowly
+const PET_CHEER: i32 = 1; // fun and social gained each time
+
+// screen feedback for dramatic moments
+const CRITICAL_NEED: i32 = 15; // warn (and shake) when a need drops below this
+const SHAKE_FRAMES: i32 = 10;
+const SHAKE_MAGNITUDE: i32 = 2; // cells the map jumps by at the start of a shake
+const FLASH_FRAMES: i32 = 8;
+
+const HINT_LOW_NEED: i32 = 50; // the tutorial explains how to look after needs below this
+const ADVICE_LOW_NEED: i32 = 35; // the advisor speaks up about needs below this
+const ADVICE_COOLDOWN: u32 = 40; // turns between two pieces of advice
 
 const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
 const COLOR_LIGHT_WALL: Color = Color {
@@ -60,9 +169,40 @@ const COLOR_LIGHT_GROUND: Color = Color {
     b: 50,
 };
 
+const COLOR_CONFUSED: Color = colors::LIGHT_VIOLET;
+
+// high-contrast mode: strongly distinct colors, and walls drawn as solid blocks
+const HIGH_CONTRAST_WALL_CHAR: char = '\u{db}'; // full block in the code page 437 font
+const HC_DARK_WALL: Color = Color {
+    r: 90,
+    g: 90,
+    b: 90,
+};
+const HC_LIGHT_WALL: Color = colors::WHITE;
+const HC_DARK_GROUND: Color = colors::BLACK;
+const HC_LIGHT_GROUND: Color = Color {
+    r: 30,
+    g: 30,
+    b: 30,
+};
+
 // player will always be the first object
 const PLAYER: usize = 0;
 
+// tags that mark objects for systems to find them by
+const TAG_STAIRS: &str = "stairs";
+const TAG_FURNITURE: &str = "furniture";
+const TAG_INTERACTIVE: &str = "interactive"; // something the player can bump into to use
+
+// named countdowns on objects
+const TIMER_TALK: &str = "talk"; // until an NPC is up for another chat
+const TIMER_INSULT: &str = "insult"; // until a hostile NPC picks on the player again
+
+// names given to the friendly NPCs living around the house
+const NPC_NAMES: &[&str] = &[
+    "Bella", "Mortimer", "Nina", "Dina", "Don", "Bob", "Eliza", "Gunther",
+];
+
 type Map = Vec<Vec<Tile>>;
 type Messages = Vec<(String, Color)>;
 
@@ -92,8 +232,101 @@ impl Tile {
     }
 }
 
+/// The game's random number generator. Besides the generator itself it keeps
+/// its seed and how many numbers it has handed out, which is all a save needs:
+/// loading replays the draws, so the sequence continues exactly where it
+/// left off.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "RngState", into = "RngState")]
+struct GameRng {
+    seed: u64,
+    draws: u64,
+    rng: StdRng,
+}
+
+/// What actually gets written to the save for a `GameRng`.
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: u64,
+    draws: u64,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng {
+            seed: seed,
+            draws: 0,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+/// Turn a seed phrase like "cozy-home" into a numeric seed. This is 64-bit
+/// FNV-1a, which unlike the standard library's hasher is guaranteed to give
+/// the same number on every platform and version, so a shared phrase always
+/// makes the same house.
+fn seed_from_str(text: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    text.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::new(rand::thread_rng().gen())
+    }
+}
+
+impl From<RngState> for GameRng {
+    fn from(state: RngState) -> Self {
+        let mut rng = GameRng::new(state.seed);
+        for _ in 0..state.draws {
+            rng.next_u32();
+        }
+        rng
+    }
+}
+
+impl From<GameRng> for RngState {
+    fn from(rng: GameRng) -> Self {
+        RngState {
+            seed: rng.seed,
+            draws: rng.draws,
+        }
+    }
+}
+
+impl RngCore for GameRng {
+    // everything is built from `next_u32` so that counting its calls is
+    // enough to replay the generator
+    fn next_u32(&mut self) -> u32 {
+        self.draws += 1;
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
 /// A rectangle on the map, used to characterise a room.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 struct Rect {
     x1: i32,
     y1: i32,
@@ -117,6 +350,11 @@ impl Rect {
         (center_x, center_y)
     }
 
+    /// whether (x, y) is on the room's floor, not counting its walls
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x > self.x1 && x < self.x2 && y > self.y1 && y < self.y2
+    }
+
     pub fn intersects_with(&self, other: &Rect) -> bool {
         // returns true if this rectangle intersects with another one
         (self.x1 <= other.x2)
@@ -128,8 +366,10 @@ impl Rect {
 
 /// This is a generic object: the player, a monster, an item, the stairs...
 /// It's always represented by a character on screen.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 struct Object {
+    #[serde(default)]
+    id: u64, // stable across moves in and out of `game.objects`; 0 until the game hands one out
     x: i32,
     y: i32,
     char: char,
@@ -142,11 +382,436 @@ struct Object {
     item: Option<Item>,
     equipment: Option<Equipment>,
     always_visible: bool,
+    #[serde(default)]
+    render_layer: RenderLayer,
+    #[serde(default)]
+    door: Option<Door>,
+    #[serde(default)]
+    furniture: Option<Furniture>,
+    #[serde(default)]
+    tags: HashSet<String>,
+    #[serde(default)]
+    facing: Option<Direction>, // which way it last moved; None until it does
+    #[serde(default)]
+    timers: HashMap<String, i32>, // turns left on each named countdown that's running
+    #[serde(default)]
+    animation: Vec<char>, // characters cycled through while it's in use, if any
+    #[serde(default = "relationship_by_default")]
+    relationship: i32, // how an NPC feels about the player
+    #[serde(default = "stack_of_one")]
+    count: u32, // how many of the item this object stands for
+}
+
+fn stack_of_one() -> u32 {
+    1
+}
+
+fn relationship_by_default() -> i32 {
+    RELATIONSHIP_START
+}
+
+/// How an NPC behaves towards the player, going by their relationship.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Attitude {
+    Friendly,
+    Neutral,
+    Hostile,
+}
+
+impl Attitude {
+    fn from_relationship(relationship: i32) -> Self {
+        if relationship >= RELATIONSHIP_FRIENDLY {
+            Attitude::Friendly
+        } else if relationship <= RELATIONSHIP_HOSTILE {
+            Attitude::Hostile
+        } else {
+            Attitude::Neutral
+        }
+    }
+
+    /// what the log says when an NPC turns this way
+    fn change_key(self) -> &'static str {
+        match self {
+            Attitude::Friendly => "npc_turns_friendly",
+            Attitude::Neutral => "npc_turns_neutral",
+            Attitude::Hostile => "npc_turns_hostile",
+        }
+    }
+}
+
+/// A snapshot of an object's key fields that can be written out as JSON.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct ObjectSummary {
+    id: u64,
+    name: String,
+    x: i32,
+    y: i32,
+    alive: bool,
+    item: Option<Item>,
+    equipment: bool,
+    furniture: Option<FurnitureKind>,
+    stats: Option<Stats>,
+}
+
+/// The way an object faces, after the last step it took.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    /// the direction of a step; diagonal steps count as sideways
+    fn from_step(dx: i32, dy: i32) -> Option<Direction> {
+        match (dx.signum(), dy.signum()) {
+            (1, _) => Some(Direction::East),
+            (-1, _) => Some(Direction::West),
+            (0, -1) => Some(Direction::North),
+            (0, 1) => Some(Direction::South),
+            _ => None,
+        }
+    }
+
+    fn glyph(self) -> char {
+        match self {
+            Direction::North => '^',
+            Direction::South => 'v',
+            Direction::East => '>',
+            Direction::West => '<',
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// A piece of furniture the player can use by bumping into it.
+struct Furniture {
+    kind: FurnitureKind,
+    durability: i32, // uses left before it breaks
+    #[serde(default)]
+    occupied_by: Option<u64>, // id of whoever is using it right now
+    #[serde(default)]
+    occupied_turns: i32, // turns until they get up again
+}
+
+impl Furniture {
+    pub fn is_broken(&self) -> bool {
+        self.kind.max_durability().is_some() && self.durability <= 0
+    }
+
+    /// start using this for `turns` turns; fails if somebody else already is
+    pub fn claim(&mut self, who: u64, turns: i32) -> bool {
+        if self.occupied_by.map_or(false, |occupant| occupant != who) {
+            return false;
+        }
+        self.occupied_by = Some(who);
+        self.occupied_turns = turns;
+        true
+    }
+
+    pub fn release(&mut self) {
+        self.occupied_by = None;
+        self.occupied_turns = 0;
+    }
+
+    /// whether someone other than `who` is using it
+    pub fn occupied_by_other(&self, who: u64) -> bool {
+        self.occupied_by.map_or(false, |occupant| occupant != who)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum FurnitureKind {
+    Bed,
+    Toilet,
+    Shower,
+    Fridge,
+    Sofa,
+    Television,
+    Valve,
+}
+
+impl FurnitureKind {
+    /// the kinds that get scattered around the rooms
+    const PLACEABLE: [FurnitureKind; 6] = [
+        FurnitureKind::Bed,
+        FurnitureKind::Toilet,
+        FurnitureKind::Shower,
+        FurnitureKind::Fridge,
+        FurnitureKind::Sofa,
+        FurnitureKind::Television,
+    ];
+
+    /// width and height in tiles, counted right and down from the object's position
+    fn footprint(self) -> (i32, i32) {
+        match self {
+            FurnitureKind::Bed | FurnitureKind::Sofa => (2, 1),
+            _ => (1, 1),
+        }
+    }
+
+    /// which need using it takes care of, and by how much
+    fn restores(self) -> Option<(Need, i32)> {
+        match self {
+            FurnitureKind::Bed => Some((Need::Energy, 40)),
+            FurnitureKind::Toilet => Some((Need::Bladder, 60)),
+            FurnitureKind::Shower => Some((Need::Hygiene, 50)),
+            FurnitureKind::Fridge => Some((Need::Hunger, 30)),
+            FurnitureKind::Sofa => Some((Need::Comfort, 25)),
+            FurnitureKind::Television => Some((Need::Fun, 25)),
+            FurnitureKind::Valve => None,
+        }
+    }
+
+    /// how many uses it takes to break, `None` if it never wears out
+    fn max_durability(self) -> Option<i32> {
+        match self {
+            FurnitureKind::Bed => Some(15),
+            FurnitureKind::Toilet => Some(25),
+            FurnitureKind::Shower => Some(20),
+            FurnitureKind::Fridge => Some(30),
+            FurnitureKind::Sofa => Some(30),
+            FurnitureKind::Television => Some(20),
+            FurnitureKind::Valve => None,
+        }
+    }
+
+    /// bathroom fixtures only work while the water is on
+    fn needs_water(self) -> bool {
+        match self {
+            FurnitureKind::Toilet | FurnitureKind::Shower => true,
+            _ => false,
+        }
+    }
+
+    /// appliances stop working in a power outage
+    fn needs_power(self) -> bool {
+        match self {
+            FurnitureKind::Fridge | FurnitureKind::Television => true,
+            _ => false,
+        }
+    }
+
+    fn glyph(self) -> char {
+        match self {
+            FurnitureKind::Bed => '=',
+            FurnitureKind::Toilet => 'o',
+            FurnitureKind::Shower => '~',
+            FurnitureKind::Fridge => ']',
+            FurnitureKind::Sofa => 'h',
+            FurnitureKind::Television => 'T',
+            FurnitureKind::Valve => '*',
+        }
+    }
+
+    /// the characters it cycles through while someone is using it, if any
+    fn animation(self) -> &'static [char] {
+        match self {
+            FurnitureKind::Shower => &['~', '\u{f7}'], // approximately equal sign
+            FurnitureKind::Television => &['T', '\u{b1}'], // medium shade, for the picture
+            _ => &[],
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            FurnitureKind::Bed => colors::LIGHT_BLUE,
+            FurnitureKind::Toilet | FurnitureKind::Shower => colors::LIGHTEST_GREY,
+            FurnitureKind::Fridge => colors::LIGHT_CYAN,
+            FurnitureKind::Sofa => colors::DARK_ORANGE,
+            FurnitureKind::Television => colors::LIGHT_GREY,
+            FurnitureKind::Valve => colors::LIGHT_BLUE,
+        }
+    }
+}
+
+impl std::fmt::Display for FurnitureKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            FurnitureKind::Bed => "furniture_bed",
+            FurnitureKind::Toilet => "furniture_toilet",
+            FurnitureKind::Shower => "furniture_shower",
+            FurnitureKind::Fridge => "furniture_fridge",
+            FurnitureKind::Sofa => "furniture_sofa",
+            FurnitureKind::Television => "furniture_television",
+            FurnitureKind::Valve => "furniture_valve",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
+}
+
+fn make_furniture(kind: FurnitureKind, x: i32, y: i32) -> Object {
+    let mut object = Object::new(x, y, kind.glyph(), &kind.to_string(), kind.color(), true);
+    object.furniture = Some(Furniture {
+        kind: kind,
+        durability: kind.max_durability().unwrap_or(0),
+        occupied_by: None,
+        occupied_turns: 0,
+    });
+    object.animation = kind.animation().to_vec();
+    object.always_visible = true;
+    object.render_layer = RenderLayer::Furniture;
+    object.add_tag(TAG_FURNITURE);
+    object.add_tag(TAG_INTERACTIVE);
+    object
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// A door between a room and a corridor. Closed doors block movement.
+struct Door {
+    open: bool,
+}
+
+/// Decides which object ends up on top when several share a tile: higher
+/// layers are drawn later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum RenderLayer {
+    Floor,
+    Item,
+    Furniture,
+    Actor,
+}
+
+impl RenderLayer {
+    /// bright, clearly different colors per kind of object for high-contrast mode
+    fn high_contrast_color(self) -> Color {
+        match self {
+            RenderLayer::Floor => colors::LIGHT_GREY,
+            RenderLayer::Item => colors::YELLOW,
+            RenderLayer::Furniture => colors::CYAN,
+            RenderLayer::Actor => colors::WHITE,
+        }
+    }
+}
+
+/// the dark (out of sight) and lit colors of a tile; high-contrast mode looks
+/// the same everywhere
+fn tile_colors(wall: bool, high_contrast: bool, biome: Biome) -> (Color, Color) {
+    match (wall, high_contrast) {
+        (true, false) => biome.wall_colors(),
+        (false, false) => biome.ground_colors(),
+        (true, true) => (HC_DARK_WALL, HC_LIGHT_WALL),
+        (false, true) => (HC_DARK_GROUND, HC_LIGHT_GROUND),
+    }
+}
+
+/// What part of the house a level is. It sets the colors of the map and what
+/// tends to turn up in the rooms.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Biome {
+    House,
+    Basement,
+    Attic,
+    Garden,
+}
+
+impl Biome {
+    const ALL: [Biome; 4] = [Biome::House, Biome::Basement, Biome::Attic, Biome::Garden];
+
+    /// the first level is always the house itself, after that it's a surprise
+    fn for_level(level: u32, rng: &mut GameRng) -> Biome {
+        if level <= 1 {
+            Biome::House
+        } else {
+            Biome::ALL[rng.gen_range(0..Biome::ALL.len())]
+        }
+    }
+
+    /// string table key of its name
+    fn name_key(self) -> &'static str {
+        match self {
+            Biome::House => "biome_house",
+            Biome::Basement => "biome_basement",
+            Biome::Attic => "biome_attic",
+            Biome::Garden => "biome_garden",
+        }
+    }
+
+    /// dark and lit wall colors
+    fn wall_colors(self) -> (Color, Color) {
+        match self {
+            Biome::House => (COLOR_DARK_WALL, COLOR_LIGHT_WALL),
+            Biome::Basement => (Color::new(30, 30, 30), Color::new(110, 110, 100)),
+            Biome::Attic => (Color::new(50, 30, 10), Color::new(140, 90, 50)),
+            Biome::Garden => (Color::new(0, 40, 0), Color::new(40, 110, 40)),
+        }
+    }
+
+    /// dark and lit floor colors
+    fn ground_colors(self) -> (Color, Color) {
+        match self {
+            Biome::House => (COLOR_DARK_GROUND, COLOR_LIGHT_GROUND),
+            Biome::Basement => (Color::new(50, 50, 60), Color::new(150, 150, 140)),
+            Biome::Attic => (Color::new(70, 50, 30), Color::new(190, 150, 100)),
+            Biome::Garden => (Color::new(20, 60, 20), Color::new(100, 180, 70)),
+        }
+    }
+
+    /// how likely each kind of furniture is to be placed, relative to the others
+    fn furniture_weight(self, kind: FurnitureKind) -> u32 {
+        use FurnitureKind::*;
+        match (self, kind) {
+            (Biome::House, _) => 1,
+            (Biome::Basement, Fridge) | (Biome::Basement, Shower) => 3,
+            (Biome::Attic, Bed) => 3,
+            (Biome::Attic, Sofa) => 2,
+            (Biome::Attic, Toilet) | (Biome::Attic, Shower) => 0,
+            (Biome::Garden, Sofa) => 3, // garden benches
+            (Biome::Garden, Bed) | (Biome::Garden, Toilet) | (Biome::Garden, Television) => 0,
+            _ => 1,
+        }
+    }
+
+    /// multiplies the chance of an item showing up
+    fn item_weight(self, item: Item) -> u32 {
+        match (self, item) {
+            (Biome::Basement, Item::Lightning) => 2,
+            (Biome::Attic, Item::Confuse) | (Biome::Attic, Item::Sword) => 2,
+            (Biome::Garden, Item::Heal) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Default for Biome {
+    fn default() -> Self {
+        Biome::House
+    }
+}
+
+/// the character drawn on a tile; normally tiles are just colored in
+fn tile_char(wall: bool, high_contrast: bool) -> char {
+    if wall && high_contrast {
+        HIGH_CONTRAST_WALL_CHAR
+    } else {
+        ' '
+    }
+}
+
+impl Default for RenderLayer {
+    fn default() -> Self {
+        RenderLayer::Item
+    }
+}
+
+/// order in which objects on the same tile are drawn: by layer, then blocking ones last
+fn draw_order(o1: &Object, o2: &Object) -> cmp::Ordering {
+    (o1.render_layer, o1.blocks)
+        .cmp(&(o2.render_layer, o2.blocks))
+        .then_with(|| stable_order(o1, o2))
+}
+
+/// A fixed order for objects that otherwise tie, so the same scene comes out
+/// the same however `swap_remove` has shuffled the objects around.
+fn stable_order(o1: &Object, o2: &Object) -> cmp::Ordering {
+    (o1.id, &o1.name).cmp(&(o2.id, &o2.name))
 }
 
 impl Object {
     pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
         Object {
+            id: 0,
             x: x,
             y: y,
             char: char,
@@ -159,18 +824,101 @@ impl Object {
             item: None,
             equipment: None,
             always_visible: false,
+            // anything that blocks is a character until told otherwise
+            render_layer: if blocks {
+                RenderLayer::Actor
+            } else {
+                RenderLayer::Item
+            },
+            door: None,
+            furniture: None,
+            tags: HashSet::new(),
+            facing: None,
+            timers: HashMap::new(),
+            animation: vec![],
+            relationship: RELATIONSHIP_START,
+            count: 1,
         }
     }
 
     /// set the color and then draw the character that represents this object at its position
-    pub fn draw(&self, con: &mut Console) {
-        con.set_default_foreground(self.color);
-        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+    pub fn draw(&self, con: &mut Console, high_contrast: bool, frame: u32) {
+        con.set_default_foreground(self.render_color(high_contrast));
+        let glyph = self.glyph(frame);
+        for (x, y) in self.footprint() {
+            con.put_char(x, y, glyph, BackgroundFlag::None);
+        }
+    }
+
+    /// The char to draw for it on the given animation frame. NPCs show which
+    /// way they're facing, furniture in use is animated and everything else
+    /// shows its usual char. The pet keeps its own: its arrows would look just
+    /// like the stairs.
+    pub fn glyph(&self, frame: u32) -> char {
+        let in_use = self.furniture.map_or(false, |f| f.occupied_by.is_some());
+        let facing_shown = self.ai.is_some() && self.ai != Some(Ai::Follow);
+        match self.facing {
+            Some(facing) if facing_shown => facing.glyph(),
+            _ if in_use && !self.animation.is_empty() => {
+                self.animation[animation_frame(frame, self.animation.len())]
+            }
+            _ => self.char,
+        }
+    }
+
+    /// the main facts about this object, for tools and debugging
+    pub fn summary(&self) -> ObjectSummary {
+        ObjectSummary {
+            id: self.id,
+            name: self.name.clone(),
+            x: self.x,
+            y: self.y,
+            alive: self.alive,
+            item: self.item,
+            equipment: self.equipment.is_some(),
+            furniture: self.furniture.map(|f| f.kind),
+            stats: self.stats,
+        }
+    }
+
+    /// move to (x, y), turning to face the way it went
+    pub fn step_to(&mut self, x: i32, y: i32) {
+        if let Some(facing) = Direction::from_step(x - self.x, y - self.y) {
+            self.facing = Some(facing);
+        }
+        self.set_pos(x, y);
+    }
+
+    /// the color it's drawn in: confused NPCs stand out from the rest
+    pub fn render_color(&self, high_contrast: bool) -> Color {
+        match self.ai {
+            Some(Ai::Confused { .. }) => COLOR_CONFUSED,
+            _ if high_contrast => self.render_layer.high_contrast_color(),
+            _ => self.color,
+        }
     }
 
     /// Erase the character that represents this object
     pub fn clear(&self, con: &mut Console) {
-        con.put_char(self.x, self.y, ' ', BackgroundFlag::None);
+        for (x, y) in self.footprint() {
+            con.put_char(x, y, ' ', BackgroundFlag::None);
+        }
+    }
+
+    /// every tile the object takes up; only some furniture spans more than one
+    pub fn footprint(&self) -> Vec<(i32, i32)> {
+        let (width, height) = self.furniture.map_or((1, 1), |f| f.kind.footprint());
+        let mut tiles = vec![];
+        for dy in 0..height {
+            for dx in 0..width {
+                tiles.push((self.x + dx, self.y + dy));
+            }
+        }
+        tiles
+    }
+
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        self.footprint().contains(&(x, y))
     }
 
     pub fn pos(&self) -> (i32, i32) {
@@ -182,6 +930,36 @@ impl Object {
         self.y = y;
     }
 
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag)
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        self.tags.insert(tag.into());
+    }
+
+    /// start the named countdown at `turns`, replacing any already running
+    pub fn set_timer(&mut self, name: &str, turns: i32) {
+        if turns > 0 {
+            self.timers.insert(name.into(), turns);
+        } else {
+            self.timers.remove(name);
+        }
+    }
+
+    /// turns left on the named countdown, 0 if it isn't running
+    pub fn timer(&self, name: &str) -> i32 {
+        self.timers.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn timer_running(&self, name: &str) -> bool {
+        self.timer(name) > 0
+    }
+
+    pub fn attitude(&self) -> Attitude {
+        Attitude::from_relationship(self.relationship)
+    }
+
     /// return the distance to another object
     pub fn distance_to(&self, other: &Object) -> f32 {
         let dx = other.x - self.x;
@@ -207,7 +985,10 @@ impl Object {
             if !equipment.equipped {
                 equipment.equipped = true;
                 log.add(
-                    format!("Equipped {} on {}.", self.name, equipment.slot),
+                    t(
+                        "equipped",
+                        &[("item", &self.name), ("slot", &equipment.slot)],
+                    ),
                     colors::LIGHT_GREEN,
                 );
             }
@@ -232,7 +1013,10 @@ impl Object {
             if equipment.equipped {
                 equipment.equipped = false;
                 log.add(
-                    format!("unequipped {} from {}.", self.name, equipment.slot),
+                    t(
+                        "unequipped",
+                        &[("item", &self.name), ("slot", &equipment.slot)],
+                    ),
                     colors::LIGHT_YELLOW,
                 );
             }
@@ -275,6 +1059,154 @@ impl Object {
     pub fn max_room(&self) -> i32 {
         return self.stats.map_or(0, |s| s.base_max_all_stats);
     }
+
+    pub fn max_need(&self, need: Need) -> i32 {
+        match need {
+            Need::Hunger => self.max_hunger(),
+            Need::Comfort => self.max_comfort(),
+            Need::Hygiene => self.max_hygiene(),
+            Need::Bladder => self.max_bladder(),
+            Need::Energy => self.max_energy(),
+            Need::Fun => self.max_fun(),
+            Need::Social => self.max_social(),
+            Need::Room => self.max_room(),
+        }
+    }
+}
+
+/// The needs every character has to keep up with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Need {
+    Hunger,
+    Comfort,
+    Hygiene,
+    Bladder,
+    Energy,
+    Fun,
+    Social,
+    Room,
+}
+
+impl Need {
+    const ALL: [Need; 8] = [
+        Need::Hunger,
+        Need::Comfort,
+        Need::Hygiene,
+        Need::Bladder,
+        Need::Energy,
+        Need::Fun,
+        Need::Social,
+        Need::Room,
+    ];
+
+    /// how many points this need loses every 100 turns on normal difficulty
+    fn drain_rate(self) -> f64 {
+        match self {
+            Need::Hunger => 10.0,
+            Need::Comfort => 6.0,
+            Need::Hygiene => 5.0,
+            Need::Bladder => 12.0,
+            Need::Energy => 7.0,
+            Need::Fun => 8.0,
+            Need::Social => 6.0,
+            Need::Room => 3.0,
+        }
+    }
+
+    /// string table key of what the advisor suggests when it runs low
+    fn advice_key(self) -> &'static str {
+        match self {
+            Need::Hunger => "advice_hunger",
+            Need::Comfort => "advice_comfort",
+            Need::Hygiene => "advice_hygiene",
+            Need::Bladder => "advice_bladder",
+            Need::Energy => "advice_energy",
+            Need::Fun => "advice_fun",
+            Need::Social => "advice_social",
+            Need::Room => "advice_room",
+        }
+    }
+
+    /// how much the need counts towards the player's mood
+    fn mood_weight(self) -> i32 {
+        match self {
+            Need::Hunger => 3,
+            Need::Bladder | Need::Energy => 2,
+            Need::Comfort | Need::Hygiene | Need::Fun | Need::Social | Need::Room => 1,
+        }
+    }
+}
+
+/// How the player feels overall, summing up all the needs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mood {
+    Happy,
+    Neutral,
+    Sad,
+    Miserable,
+}
+
+impl Mood {
+    fn glyph(self) -> char {
+        match self {
+            Mood::Happy | Mood::Neutral => '\u{1}', // smiling face
+            Mood::Sad | Mood::Miserable => '\u{2}', // filled-in face
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Mood::Happy => colors::LIGHT_GREEN,
+            Mood::Neutral => colors::LIGHT_YELLOW,
+            Mood::Sad => colors::ORANGE,
+            Mood::Miserable => colors::RED,
+        }
+    }
+}
+
+impl std::fmt::Display for Mood {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            Mood::Happy => "mood_happy",
+            Mood::Neutral => "mood_neutral",
+            Mood::Sad => "mood_sad",
+            Mood::Miserable => "mood_miserable",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
+}
+
+/// weighted average of the needs, as a percentage of their maximum
+fn compute_mood(stats: &Stats) -> Mood {
+    let max = cmp::max(stats.base_max_all_stats, 1);
+    let total_weight: i32 = Need::ALL.iter().map(|need| need.mood_weight()).sum();
+    let score: i32 = Need::ALL
+        .iter()
+        .map(|&need| need.mood_weight() * stats.need(need) * 100 / max)
+        .sum::<i32>()
+        / total_weight;
+    match score {
+        70..=i32::MAX => Mood::Happy,
+        45..=69 => Mood::Neutral,
+        20..=44 => Mood::Sad,
+        _ => Mood::Miserable,
+    }
+}
+
+impl std::fmt::Display for Need {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            Need::Hunger => "need_hunger",
+            Need::Comfort => "need_comfort",
+            Need::Hygiene => "need_hygiene",
+            Need::Bladder => "need_bladder",
+            Need::Energy => "need_energy",
+            Need::Fun => "need_fun",
+            Need::Social => "need_social",
+            Need::Room => "need_room",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
 }
 
 // character-related properties and methods (player, NPC).
@@ -292,1259 +1224,9867 @@ struct Stats {
     on_death: DeathCallback,
 }
 
-/// move by the given amount, if the destination is not blocked
-fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
-    let (x, y) = objects[id].pos();
-    if !is_blocked(x + dx, y + dy, map, objects) {
-        objects[id].set_pos(x + dx, y + dy);
+impl Stats {
+    pub fn need(&self, need: Need) -> i32 {
+        match need {
+            Need::Hunger => self.hunger,
+            Need::Comfort => self.comfort,
+            Need::Hygiene => self.hygiene,
+            Need::Bladder => self.bladder,
+            Need::Energy => self.energy,
+            Need::Fun => self.fun,
+            Need::Social => self.social,
+            Need::Room => self.room,
+        }
+    }
+
+    pub fn need_mut(&mut self, need: Need) -> &mut i32 {
+        match need {
+            Need::Hunger => &mut self.hunger,
+            Need::Comfort => &mut self.comfort,
+            Need::Hygiene => &mut self.hygiene,
+            Need::Bladder => &mut self.bladder,
+            Need::Energy => &mut self.energy,
+            Need::Fun => &mut self.fun,
+            Need::Social => &mut self.social,
+            Need::Room => &mut self.room,
+        }
     }
 }
 
-/// add to the player's inventory and remove from the map
-fn pick_item_up(object_id: usize, game: &mut Game) {
-    if game.inventory.len() >= 26 {
-        game.log.add(
-            format!(
-                "Your inventory is full, cannot pick up {}.",
-                game.objects[object_id].name
-            ),
-            colors::RED,
-        );
-    } else {
-        let item = game.objects.swap_remove(object_id);
-        game.log
-            .add(format!("You picked up a {}!", item.name), colors::GREEN);
-        let index = game.inventory.len();
-        let slot = item.equipment.map(|e| e.slot);
-        game.inventory.push(item);
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
 
-        // automatically equip, if the corresponding equipment slot is unused
-        if let Some(slot) = slot {
-            if get_equipped_in_slot(slot, &game.inventory).is_none() {
-                game.inventory[index].equip(&mut game.log);
-            }
+impl Difficulty {
+    const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    /// scales how fast needs drain
+    fn need_drain_multiplier(self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
         }
     }
-}
 
-fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
-    for (inventory_id, item) in inventory.iter().enumerate() {
-        if item
-            .equipment
-            .as_ref()
-            .map_or(false, |e| e.equipped && e.slot == slot)
-        {
-            return Some(inventory_id);
+    /// scales how many helpful items show up in each room
+    fn spawn_multiplier(self) -> f64 {
+        match self {
+            Difficulty::Easy => 1.5,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 0.5,
         }
     }
-    None
 }
 
-fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-    // first test the map tile
-    if map[x as usize][y as usize].blocked {
-        return true;
+impl Default for Difficulty {
+    fn default() -> Self {
+        Difficulty::Normal
     }
-    // now check for any blocking objects
-    objects
-        .iter()
-        .any(|object| object.blocks && object.x == x && object.y == y)
 }
 
+/// What happens to the saves when the player dies.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum DeathCallback {
-    Player,
-    NPC,
+enum SaveMode {
+    Casual,     // the last save stays, to go back to
+    Permadeath, // the saves are deleted and that's the end of it
 }
 
-impl DeathCallback {
-    fn callback(self, object: &mut Object, game: &mut Game) {
-        let callback: fn(&mut Object, &mut Game) = match self {
-            DeathCallback::Player => player_death,
-            DeathCallback::NPC => npc_death,
-        };
-        callback(object, game);
+impl SaveMode {
+    const ALL: [SaveMode; 2] = [SaveMode::Casual, SaveMode::Permadeath];
+
+    /// string table key of its name in the new game menu
+    fn name_key(self) -> &'static str {
+        match self {
+            SaveMode::Casual => "menu_mode_casual",
+            SaveMode::Permadeath => "menu_mode_permadeath",
+        }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-enum Ai {
-    Basic,
-    Confused {
-        previous_ai: Box<Ai>,
-        num_turns: i32,
-    },
+impl Default for SaveMode {
+    fn default() -> Self {
+        SaveMode::Casual
+    }
+}
+
+impl std::fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            Difficulty::Easy => "difficulty_easy",
+            Difficulty::Normal => "difficulty_normal",
+            Difficulty::Hard => "difficulty_hard",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
 }
 
+/// How a need drains as it runs down.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Item {
-    Heal,
-    Lightning,
-    Confuse,
-    Fireball,
-    Sword,
-    Shield,
+enum DecayCurve {
+    Linear,       // always at the same rate
+    Accelerating, // slowly when full, up to twice as fast when empty
+    Step,         // at half the rate while above half full, one and a half times below
 }
 
-enum UseResult {
-    UsedUp,
-    UsedAndKept,
-    Cancelled,
+impl Default for DecayCurve {
+    fn default() -> Self {
+        DecayCurve::Linear
+    }
 }
 
-fn use_item(inventory_id: usize, game: &mut Game, tcod: &mut Tcod) {
-    // just call the "use_function" if it is defined
-    if let Some(item) = game.inventory[inventory_id].item {
-        let on_use: fn(usize, &mut Game, &mut Tcod) -> UseResult = match item {
-            Item::Heal => cast_heal,
-            Item::Lightning => cast_lightning,
-            Item::Confuse => cast_confuse,
-            Item::Fireball => cast_fireball,
-            Item::Sword => toggle_equipment,
-            Item::Shield => toggle_equipment,
-        };
-        match on_use(inventory_id, game, tcod) {
-            UseResult::UsedUp => {
-                // destroy after use, unless it was cancelled for some reason
-                game.inventory.remove(inventory_id);
-            }
-            UseResult::UsedAndKept => {} // do nothing
-            UseResult::Cancelled => {
-                game.log.add("Cancelled", colors::WHITE);
-            }
-        }
+/// The drain rate of a need at `value` out of `maximum`, given its base
+/// `rate` and the curve it follows.
+fn apply_decay(value: i32, maximum: i32, curve: DecayCurve, rate: f64) -> f64 {
+    let full = if maximum > 0 {
+        (value as f64 / maximum as f64).max(0.0).min(1.0)
     } else {
-        game.log.add(
-            format!("The {} cannot be used.", game.inventory[inventory_id].name),
-            colors::WHITE,
-        );
+        0.0
+    };
+    match curve {
+        DecayCurve::Linear => rate,
+        DecayCurve::Accelerating => rate * (2.0 - full),
+        DecayCurve::Step if full > 0.5 => rate * 0.5,
+        DecayCurve::Step => rate * 1.5,
     }
 }
 
-fn drop_item(inventory_id: usize, game: &mut Game) {
-    let mut item = game.inventory.remove(inventory_id);
-    if item.equipment.is_some() {
-        item.unequip(&mut game.log);
+/// Returns how many points a need draining at `rate` points per 100 turns
+/// loses on the given turn. Fractional rates are spread evenly over the turns.
+fn need_drain(rate: f64, turn: u32) -> i32 {
+    if turn == 0 {
+        return 0;
     }
-    item.set_pos(game.objects[PLAYER].x, game.objects[PLAYER].y);
-    game.log
-        .add(format!("You dropped a {}.", item.name), colors::YELLOW);
-    game.objects.push(item);
+    let now = (turn as f64 * rate / 100.0).floor();
+    let before = ((turn - 1) as f64 * rate / 100.0).floor();
+    (now - before) as i32
 }
 
-/// return the position of a tile left-clicked in player's FOV (optionally in a
-/// range), or (None,None) if right-clicked.
-fn target_tile(
-    tcod: &mut Tcod,
-    objects: &[Object],
-    game: &mut Game,
-    max_range: Option<f32>,
-) -> Option<(i32, i32)> {
-    use tcod::input::KeyCode::Escape;
-    loop {
-        // render the screen. this erases the inventory and shows the names of
-        // objects under the mouse.
-        tcod.root.flush();
-        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
-        let mut key = None;
-        match event {
-            Some(Event::Mouse(m)) => tcod.mouse = m,
-            Some(Event::Key(k)) => key = Some(k),
-            None => {}
-        }
-        render_all(tcod, game, false);
-
-        let (x, y) = (tcod.mouse.cx as i32, tcod.mouse.cy as i32);
+/// Drain the player's needs for one turn. Returns the needs that just dropped
+/// to a critical level.
+fn tick_needs(game: &mut Game) -> Vec<Need> {
+    let multiplier = game.difficulty.need_drain_multiplier();
+    let turn = game.turn;
+    let maxima: Vec<i32> = Need::ALL
+        .iter()
+        .map(|&need| game.objects[PLAYER].max_need(need))
+        .collect();
 
-        // accept the target if the player clicked in FOV, and in case a range
-        // is specified, if it's in that range
-        let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
-        if tcod.mouse.lbutton_pressed && in_fov && in_range {
-            return Some((x, y));
+    // living around broken furniture gets the room need down faster
+    let broken_nearby = game
+        .objects
+        .iter()
+        .filter(|object| {
+            object.furniture.map_or(false, |f| f.is_broken())
+                && object.distance_to(&game.objects[PLAYER]) <= BROKEN_FURNITURE_RADIUS
+        })
+        .count();
+
+    // the needs hold steady for a while at the start, while the player finds
+    // their way around
+    let grace = turn <= game.config.grace_turns;
+
+    // a pet close by cheers the player up a little. It's a point now and then
+    // rather than every turn, or it'd keep fun and social topped up on its own
+    let pet_cheer = turn % PET_CHEER_INTERVAL == 0
+        && game.objects.iter().any(|object| {
+            object.ai == Some(Ai::Follow)
+                && object.distance_to(&game.objects[PLAYER]) <= PET_FOLLOW_DISTANCE
+        });
+
+    let mut critical = vec![];
+    let mood_before = game.objects[PLAYER].stats.map(|s| compute_mood(&s));
+    let player = &mut game.objects[PLAYER];
+    if let Some(stats) = player.stats.as_mut().filter(|_| !grace) {
+        for (&need, &maximum) in Need::ALL.iter().zip(maxima.iter()) {
+            let mut rate = need.drain_rate() * game.weather.drain_multiplier(need);
+            if need == Need::Room {
+                rate += BROKEN_FURNITURE_ROOM_DRAIN * broken_nearby as f64;
+            }
+            let curve = game
+                .config
+                .decay_curves
+                .get(&need)
+                .copied()
+                .unwrap_or_default();
+            let value = stats.need_mut(need);
+            let before = *value;
+            let rate = apply_decay(before, maximum, curve, rate);
+            *value = cmp::max(*value - need_drain(rate * multiplier, turn), 0);
+            if before >= CRITICAL_NEED && *value < CRITICAL_NEED {
+                critical.push(need);
+            }
+        }
+    }
+    if let Some(stats) = player.stats.as_mut().filter(|_| pet_cheer) {
+        for (&need, &maximum) in Need::ALL.iter().zip(maxima.iter()) {
+            if need == Need::Fun || need == Need::Social {
+                let value = stats.need_mut(need);
+                *value = cmp::min(*value + PET_CHEER, maximum);
+            }
         }
+    }
+    for need in &critical {
+        game.log
+            .add(t("need_critical", &[("need", need)]), colors::ORANGE);
+    }
 
-        let escape = key.map_or(false, |k| k.code == Escape);
-        if tcod.mouse.rbutton_pressed || escape {
-            return None; // cancel if the player right-clicked or pressed Escape
+    // running out of food is fatal
+    let starved = player.alive && player.stats.map_or(false, |s| s.hunger == 0);
+    if starved {
+        player.alive = false;
+        if let Some(on_death) = player.stats.map(|s| s.on_death) {
+            on_death.callback(player, &mut game.log);
         }
     }
-}
 
-fn cast_heal(_inventory_id: usize, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
-    game.objects[PLAYER].stats.as_mut().unwrap().bladder += 20;
+    for &need in &critical {
+        game.emit(GameEvent::NeedCritical(need));
+    }
+    if starved {
+        let id = game.objects[PLAYER].id;
+        game.emit(GameEvent::Death(id));
+    }
 
-    UseResult::UsedUp
-}
+    let low_need = game.objects[PLAYER].stats.map_or(false, |s| {
+        Need::ALL.iter().any(|&need| s.need(need) < HINT_LOW_NEED)
+    });
+    if low_need {
+        show_hint(Hint::LowNeed, game);
+    }
 
-fn cast_lightning(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
-    game.objects[PLAYER].stats.as_mut().unwrap().energy += 20;
+    // a good stretch with every need taken care of is worth celebrating
+    let milestone = game.config.milestone;
+    let all_good = game.objects[PLAYER].alive
+        && game.objects[PLAYER].stats.map_or(false, |s| {
+            Need::ALL
+                .iter()
+                .all(|&need| s.need(need) >= milestone.threshold)
+        });
+    if milestone.turns == 0 || !all_good {
+        game.good_turns = 0;
+    } else {
+        game.good_turns += 1;
+        if game.good_turns >= milestone.turns {
+            game.good_turns = 0;
+            game.milestones += 1;
+            game.log.add(
+                t(
+                    "milestone_reached",
+                    &[("turns", &milestone.turns), ("count", &game.milestones)],
+                ),
+                colors::LIGHT_YELLOW,
+            );
+            game.emit(GameEvent::Milestone(game.milestones));
+        }
+    }
 
-    UseResult::UsedUp
-}
+    // say so when the needs add up to a different mood
+    let mood = game.objects[PLAYER].stats.map(|s| compute_mood(&s));
+    if let (Some(before), Some(mood)) = (mood_before, mood) {
+        if mood != before && game.objects[PLAYER].alive {
+            game.log
+                .add(t("mood_changed", &[("mood", &mood)]), mood.color());
+        }
+    }
 
-fn cast_confuse(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
-    game.objects[PLAYER].stats.as_mut().unwrap().social += 20;
+    // remember how the needs are doing for the history graph
+    if turn % HISTORY_SAMPLE_INTERVAL == 0 {
+        if let Some(stats) = game.objects[PLAYER].stats {
+            game.need_history.record(&stats);
+        }
+    }
 
-    UseResult::UsedUp
+    critical
 }
 
-fn cast_fireball(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
-    game.objects[PLAYER].stats.as_mut().unwrap().comfort += 20;
+/// Drain the NPCs' needs for one turn. It's a simpler version of what the
+/// player goes through: slower, and without weather or decay curves. An NPC
+/// that runs out of food dies.
+fn tick_npc_needs(game: &mut Game) {
+    if game.turn <= game.config.grace_turns {
+        return;
+    }
+    let multiplier = game.difficulty.need_drain_multiplier() * NPC_NEED_DRAIN;
+    let turn = game.turn;
 
-    UseResult::UsedUp
+    let mut starved = vec![];
+    for (index, npc) in game.objects.iter_mut().enumerate() {
+        if index == PLAYER || !npc.alive {
+            continue;
+        }
+        let stats = match npc.stats.as_mut() {
+            Some(stats) => stats,
+            None => continue,
+        };
+        for &need in Need::ALL.iter() {
+            let value = stats.need_mut(need);
+            *value = cmp::max(*value - need_drain(need.drain_rate() * multiplier, turn), 0);
+        }
+        if stats.hunger == 0 {
+            let on_death = stats.on_death;
+            npc.alive = false;
+            on_death.callback(npc, &mut game.log);
+            starved.push(npc.id);
+        }
+    }
+    for id in starved {
+        game.emit(GameEvent::Death(id));
+    }
 }
 
-fn toggle_equipment(inventory_id: usize, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
-    let equipment = match game.inventory[inventory_id].equipment {
-        Some(equipment) => equipment,
-        None => return UseResult::Cancelled,
-    };
-    if equipment.equipped {
-        game.inventory[inventory_id].unequip(&mut game.log);
-    } else {
-        // if the slot is already being used, dequip whatever is there first
-        if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
-            game.inventory[current].unequip(&mut game.log);
-        }
-        game.inventory[inventory_id].equip(&mut game.log);
+/// What the advisor would suggest right now: something to do about the lowest
+/// need, if it's low enough to worry about and the last piece of advice was
+/// long enough ago.
+fn advise(game: &Game) -> Option<String> {
+    let recent = game
+        .last_advice
+        .map_or(false, |turn| game.turn < turn + ADVICE_COOLDOWN);
+    if recent {
+        return None;
     }
-    UseResult::UsedAndKept
+    let stats = game.objects[PLAYER].stats?;
+    let need = *Need::ALL.iter().min_by_key(|&&need| stats.need(need))?;
+    if stats.need(need) >= ADVICE_LOW_NEED {
+        return None;
+    }
+    Some(t(need.advice_key(), &[]))
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-/// An object that can be equipped, yielding bonuses.
-struct Equipment {
-    slot: Slot,
-    equipped: bool,
-    max_hp_bonus: i32,
-    defense_bonus: i32,
-    power_bonus: i32,
+/// Log the advisor's suggestion for this turn, if it's switched on and has one.
+fn give_advice(game: &mut Game) {
+    if !game.config.advisor || !game.objects[PLAYER].alive {
+        return;
+    }
+    if let Some(advice) = advise(game) {
+        game.log.add(advice, colors::LIGHT_CYAN);
+        game.last_advice = Some(game.turn);
+    }
 }
 
+/// What it's like outside. Some weather makes needs drain faster.
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
-enum Slot {
-    LeftHand,
-    RightHand,
-    Head,
+enum Weather {
+    Clear,
+    Rain,
+    Cold,
+    Hot,
 }
 
-impl std::fmt::Display for Slot {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match *self {
-            Slot::LeftHand => write!(f, "left hand"),
-            Slot::RightHand => write!(f, "right hand"),
-            Slot::Head => write!(f, "head"),
+impl Weather {
+    const ALL: [Weather; 4] = [Weather::Clear, Weather::Rain, Weather::Cold, Weather::Hot];
+
+    /// how much faster than usual a need drains in this weather
+    fn drain_multiplier(self, need: Need) -> f64 {
+        match (self, need) {
+            (Weather::Cold, Need::Energy) | (Weather::Cold, Need::Comfort) => 1.5,
+            (Weather::Hot, Need::Hygiene) => 1.5,
+            (Weather::Hot, Need::Hunger) => 1.25,
+            (Weather::Rain, Need::Fun) => 1.25,
+            _ => 1.0,
         }
     }
-}
 
-fn create_room(room: Rect, map: &mut Map) {
-    // go through the tiles in the rectangle and make them passable
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+    /// string table key of its name
+    fn name_key(self) -> &'static str {
+        match self {
+            Weather::Clear => "weather_clear",
+            Weather::Rain => "weather_rain",
+            Weather::Cold => "weather_cold",
+            Weather::Hot => "weather_hot",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Weather::Clear => colors::LIGHT_YELLOW,
+            Weather::Rain => colors::LIGHT_BLUE,
+            Weather::Cold => colors::LIGHTER_CYAN,
+            Weather::Hot => colors::ORANGE,
         }
     }
 }
 
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
-    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
-    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+impl Default for Weather {
+    fn default() -> Self {
+        Weather::Clear
     }
 }
 
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
-    // vertical tunnel
-    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+/// every few hours, maybe change the weather
+fn update_weather(game: &mut Game) {
+    if game.turn % WEATHER_CHANGE_INTERVAL != 0 {
+        return;
+    }
+    let weather = Weather::ALL[game.rng.gen_range(0..Weather::ALL.len())];
+    if weather != game.weather {
+        game.weather = weather;
+        game.log.add(
+            t(
+                "weather_changed",
+                &[("weather", &t(weather.name_key(), &[]))],
+            ),
+            weather.color(),
+        );
     }
 }
 
-fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
-    // fill map with "blocked" tiles
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+/// Something out of the ordinary that happens around the house now and then.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum RandomEvent {
+    PowerOutage, // the fridge and the television stop working
+    Leak,        // hygiene slowly drops until it's fixed
+    Gift,        // an item turns up next to the player
+}
 
-    // Player is the first element, remove everything else.
-    // NOTE: works only when the player is the first object!
-    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
-    objects.truncate(1);
+impl RandomEvent {
+    /// how long it lasts, `None` for one that's over as soon as it happens
+    fn duration(self) -> Option<i32> {
+        match self {
+            RandomEvent::PowerOutage => Some(POWER_OUTAGE_TURNS),
+            RandomEvent::Leak => Some(LEAK_TURNS),
+            RandomEvent::Gift => None,
+        }
+    }
 
-    let mut rooms = vec![];
+    fn apply(self, game: &mut Game) {
+        match self {
+            RandomEvent::PowerOutage => game.log.add(t("event_power_out", &[]), colors::ORANGE),
+            RandomEvent::Leak => game.log.add(t("event_leak", &[]), colors::LIGHT_BLUE),
+            RandomEvent::Gift => {
+                const GIFTS: [Item; 4] =
+                    [Item::Heal, Item::Lightning, Item::Confuse, Item::Fireball];
+                let gift = GIFTS[game.rng.gen_range(0..GIFTS.len())];
+                let mut item = make_item(gift, 0, 0);
+                game.log.add(
+                    t("event_gift", &[("item", &item.name)]),
+                    colors::LIGHT_GREEN,
+                );
+                match find_drop_spot(game) {
+                    Some((x, y)) => {
+                        item.set_pos(x, y);
+                        item.always_visible = true;
+                        game.objects.push(item);
+                        game.assign_object_ids();
+                    }
+                    // nowhere to put it down, so straight into the inventory
+                    None => stash_item(item, game),
+                }
+            }
+        }
+    }
 
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = rand::thread_rng().gen_range(ROOM_MIN_SIZE..ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(ROOM_MIN_SIZE..ROOM_MAX_SIZE + 1);
-        // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0..MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0..MAP_HEIGHT - h);
+    /// what it does every turn while it lasts
+    fn tick(self, game: &mut Game) {
+        if self == RandomEvent::Leak && game.turn % LEAK_DRAIN_INTERVAL == 0 {
+            drain_player_need(Need::Hygiene, 1, game);
+        }
+    }
 
-        let new_room = Rect::new(x, y, w, h);
+    fn expire(self, game: &mut Game) {
+        match self {
+            RandomEvent::PowerOutage => game
+                .log
+                .add(t("event_power_back", &[]), colors::LIGHT_YELLOW),
+            RandomEvent::Leak => game.log.add(t("event_leak_fixed", &[]), colors::LIGHT_BLUE),
+            RandomEvent::Gift => {}
+        }
+    }
+}
 
-        // run through the other rooms and see if they intersect with this one
-        let failed = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
+/// A random event that's still going.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct ActiveEvent {
+    event: RandomEvent,
+    turns_left: i32,
+}
 
-        if !failed {
-            // this means there are no intersections, so this room is valid
+/// Tick the events that are going, end the ones that are over, and every
+/// so often maybe start a new one.
+fn update_events(game: &mut Game) {
+    let active = game.active_events.clone();
+    for active in &active {
+        active.event.tick(game);
+    }
+    for active in game.active_events.iter_mut() {
+        active.turns_left -= 1;
+    }
+    let (expired, going): (Vec<_>, Vec<_>) = game
+        .active_events
+        .drain(..)
+        .partition(|active| active.turns_left <= 0);
+    game.active_events = going;
+    for active in expired {
+        active.event.expire(game);
+    }
 
-            // "paint" it to the map's tiles
-            create_room(new_room, &mut map);
+    let params = &game.config.events;
+    if params.interval == 0 || game.turn % params.interval != 0 || params.table.is_empty() {
+        return;
+    }
+    if !game.rng.gen_bool(params.chance) {
+        return;
+    }
+    let total: u32 = params.table.iter().map(|entry| entry.weight).sum();
+    if total == 0 {
+        return;
+    }
+    let mut roll = game.rng.gen_range(0..total);
+    let event = params
+        .table
+        .iter()
+        .find(|entry| {
+            if roll < entry.weight {
+                true
+            } else {
+                roll -= entry.weight;
+                false
+            }
+        })
+        .map(|entry| entry.event);
+    let event = match event {
+        // the same thing doesn't happen twice at once
+        Some(event) if !game.event_active(event) => event,
+        _ => return,
+    };
+    event.apply(game);
+    if let Some(turns) = event.duration() {
+        game.active_events.push(ActiveEvent {
+            event,
+            turns_left: turns,
+        });
+    }
+}
 
-            // add some content to this room
-            place_objects(new_room, &map, objects, level);
+/// The recent values of every need, sampled every few turns. Only the last
+/// `HISTORY_CAPACITY` samples are kept so the save doesn't grow forever.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct NeedHistory {
+    samples: VecDeque<[i32; 8]>, // one value per need, in `Need::ALL` order
+}
 
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
+impl NeedHistory {
+    pub fn record(&mut self, stats: &Stats) {
+        if self.samples.len() >= HISTORY_CAPACITY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(need_values(stats));
+    }
+}
 
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                objects[PLAYER].set_pos(new_x, new_y);
-            } else {
-                // all rooms after the first:
-                // connect it to the previous room with a tunnel
+/// every need's value, in `Need::ALL` order
+fn need_values(stats: &Stats) -> [i32; 8] {
+    let mut values = [0; 8];
+    for (value, &need) in values.iter_mut().zip(Need::ALL.iter()) {
+        *value = stats.need(need);
+    }
+    values
+}
 
-                // center coordinates of the previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
+/// how much each need went up (or down) from `before` to `after`
+fn need_deltas(before: &[i32; 8], after: &[i32; 8]) -> Vec<(Need, i32)> {
+    Need::ALL
+        .iter()
+        .zip(before.iter().zip(after.iter()))
+        .map(|(&need, (before, after))| (need, after - before))
+        .collect()
+}
 
-                // toss a coin (random bool value -- either true or false)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    // first move vertically, then horizontally
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
-                }
+/// List how each need changed since the game was last saved or loaded.
+fn show_needs_since_save(game: &mut Game, tcod: &mut Tcod) {
+    let before = match game.saved_needs {
+        Some(before) => before,
+        None => {
+            game.log
+                .add(t("needs_diff_unsaved", &[]), colors::LIGHT_GREY);
+            return;
+        }
+    };
+    let after = match game.objects[PLAYER].stats {
+        Some(stats) => need_values(&stats),
+        None => return,
+    };
+    let entries: Vec<(String, Color)> = need_deltas(&before, &after)
+        .into_iter()
+        .map(|(need, delta)| {
+            let (arrow, color) = match delta.cmp(&0) {
+                cmp::Ordering::Greater => ('\u{18}', colors::LIGHT_GREEN),
+                cmp::Ordering::Less => ('\u{19}', colors::LIGHT_RED),
+                cmp::Ordering::Equal => ('=', colors::LIGHT_GREY),
+            };
+            let text = t(
+                "needs_diff_entry",
+                &[
+                    ("need", &need),
+                    ("arrow", &arrow),
+                    ("delta", &format!("{:+}", delta)),
+                ],
+            );
+            (text, color)
+        })
+        .collect();
+    list_screen(&t("needs_diff_title", &[]), &entries, tcod);
+}
+
+/// move by the given amount, if the destination is not blocked
+fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
+    let (x, y) = objects[id].pos();
+    let (x, y) = (x + dx, y + dy);
+    if in_map(x, y) && !is_blocked(x, y, map, objects) {
+        objects[id].step_to(x, y);
+    }
+}
+
+fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+    // vector from this object to the target, and distance
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // normalize it to length 1 (preserving direction), then round it and
+    // convert to integer so the movement is restricted to the map grid
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    move_by(id, dx, dy, map, objects);
+}
+
+/// Give every NPC its turn.
+///
+/// Each AI is `take()`n out of its object while it runs, so the NPC only needs
+/// its index to look at and move around the rest of `game.objects`, and put
+/// back afterwards. Objects are never added or removed while this runs, so the
+/// indices stay valid.
+fn npc_turns(fov: &FovMap, game: &mut Game) {
+    game.walk_map = None;
+    for id in 0..game.objects.len() {
+        if let Some(ai) = game.objects[id].ai.take() {
+            let new_ai = ai_take_turn(id, ai, fov, game);
+            // an NPC that died during its own turn has no AI to go back to
+            if game.objects[id].alive {
+                game.objects[id].ai = Some(new_ai);
             }
+        }
+    }
+}
 
-            // finally, append the new room to the list
-            rooms.push(new_room);
+fn ai_take_turn(id: usize, ai: Ai, fov: &FovMap, game: &mut Game) -> Ai {
+    use Ai::*;
+    match ai {
+        Basic => ai_basic(id, fov, game),
+        Confused {
+            previous_ai,
+            num_turns,
+        } => ai_confused(id, game, previous_ai, num_turns),
+        Guest {
+            stage,
+            turns_left,
+            exit,
+        } => ai_guest(id, game, stage, turns_left, exit),
+        Follow => ai_follow(id, game),
+    }
+}
+
+/// NPCs the player can see come over for a chat; the others wander the house.
+fn ai_basic(id: usize, fov: &FovMap, game: &mut Game) -> Ai {
+    let npc = game.objects[id].id;
+    let busy = game.objects.iter().any(|object| {
+        object
+            .furniture
+            .map_or(false, |f| f.occupied_by == Some(npc))
+    });
+    if busy {
+        // stay put until done with the furniture
+        return Ai::Basic;
+    }
+    if npc_tend_needs(id, game) {
+        return Ai::Basic;
+    }
+
+    let (npc_x, npc_y) = game.objects[id].pos();
+    let distance = game.objects[id].distance_to(&game.objects[PLAYER]);
+    if distance <= stink_radius(&game.objects[PLAYER]) {
+        // keep well clear of a smelly player
+        let (player_x, player_y) = game.objects[PLAYER].pos();
+        let dx = (npc_x - player_x).signum();
+        let dy = (npc_y - player_y).signum();
+        move_by(id, dx, dy, &game.map, &mut game.objects);
+    } else if fov.is_in_fov(npc_x, npc_y) && game.objects[id].attitude() != Attitude::Neutral {
+        // friends come over for a chat, and those who can't stand the player
+        // come over to make that known
+        if distance >= 2.0 {
+            let (player_x, player_y) = game.objects[PLAYER].pos();
+            move_towards(id, player_x, player_y, &game.map, &mut game.objects);
+        } else if game.objects[id].attitude() == Attitude::Hostile {
+            npc_insult(id, game);
         }
+    } else if game.rng.gen_bool(NPC_FURNITURE_CHANCE) {
+        // sit down at something nearby for a while, if it's free
+        let nearby = game.objects.iter().position(|object| {
+            object.furniture.map_or(false, |f| {
+                !f.is_broken() && f.kind.restores().is_some() && f.occupied_by.is_none()
+            }) && object
+                .footprint()
+                .into_iter()
+                .any(|(x, y)| (x - npc_x).abs() <= 1 && (y - npc_y).abs() <= 1)
+        });
+        if let Some(furniture) = nearby.and_then(|f| game.objects[f].furniture.as_mut()) {
+            furniture.claim(npc, NPC_FURNITURE_TURNS);
+        }
+    } else if game.rng.gen_bool(0.5) {
+        let dx = game.rng.gen_range(-1..2);
+        let dy = game.rng.gen_range(-1..2);
+        move_by(id, dx, dy, &game.map, &mut game.objects);
     }
+    Ai::Basic
+}
 
-    // create stairs at the center of the last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = Object::new(
-        last_room_x,
-        last_room_y,
-        '<',
-        "stairs",
-        colors::WHITE,
-        false,
-    );
-    stairs.always_visible = true;
-    objects.push(stairs);
+/// A pet trots after the player whenever it falls behind.
+fn ai_follow(id: usize, game: &mut Game) -> Ai {
+    if game.objects[id].distance_to(&game.objects[PLAYER]) > PET_FOLLOW_DISTANCE {
+        let target = game.objects[PLAYER].pos();
+        move_astar(id, target, game);
+    }
+    Ai::Follow
+}
 
-    map
+/// A hostile NPC next to the player has a go at them now and then, which
+/// stings the player's social need.
+fn npc_insult(id: usize, game: &mut Game) {
+    if game.objects[id].timer_running(TIMER_INSULT) {
+        return;
+    }
+    game.objects[id].set_timer(TIMER_INSULT, INSULT_COOLDOWN);
+    drain_player_need(Need::Social, INSULT_SOCIAL_LOSS, game);
+    game.log.add(
+        t("npc_insult", &[("name", &game.objects[id].name)]),
+        colors::LIGHT_RED,
+    );
 }
 
-struct Transition {
-    level: u32,
-    value: u32,
+/// Shift how an NPC feels about the player, logging it if that changes how
+/// they behave.
+fn change_relationship(id: usize, amount: i32, game: &mut Game) {
+    let npc = &mut game.objects[id];
+    let before = npc.attitude();
+    npc.relationship = (npc.relationship + amount).clamp(-100, 100);
+    let after = npc.attitude();
+    if after != before {
+        let message = t(after.change_key(), &[("name", &npc.name)]);
+        game.log.add(message, colors::LIGHT_GREY);
+    }
 }
 
-/// Returns a value that depends on level. the table specifies what
-/// value occurs after each level, default is 0.
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
-    table
+/// An NPC with a need running low heads for the closest free piece of
+/// furniture that takes care of it, lowest need first, and uses it once it gets
+/// there. Returns whether the NPC did anything about its needs this turn.
+fn npc_tend_needs(id: usize, game: &mut Game) -> bool {
+    let stats = match game.objects[id].stats {
+        Some(stats) => stats,
+        None => return false,
+    };
+    let mut low: Vec<Need> = Need::ALL
         .iter()
-        .rev()
-        .find(|transition| level >= transition.level)
-        .map_or(0, |transition| transition.value)
+        .copied()
+        .filter(|&need| stats.need(need) < NPC_LOW_NEED)
+        .collect();
+    low.sort_by_key(|&need| stats.need(need));
+
+    for need in low {
+        let water_on = game.water_on;
+        let power_on = !game.event_active(RandomEvent::PowerOutage);
+        let nearest = game
+            .objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| {
+                object.furniture.map_or(false, |f| {
+                    !f.is_broken()
+                        && f.occupied_by.is_none()
+                        && (water_on || !f.kind.needs_water())
+                        && (power_on || !f.kind.needs_power())
+                        && f.kind.restores().map_or(false, |(n, _)| n == need)
+                })
+            })
+            .min_by(|(_, a), (_, b)| {
+                let npc = &game.objects[id];
+                npc.distance_to(a)
+                    .partial_cmp(&npc.distance_to(b))
+                    .unwrap_or(cmp::Ordering::Equal)
+            })
+            .map(|(index, _)| index);
+        let furniture = match nearest {
+            Some(furniture) => furniture,
+            None => continue,
+        };
+
+        let (npc_x, npc_y) = game.objects[id].pos();
+        let next_to = game.objects[furniture]
+            .footprint()
+            .into_iter()
+            .any(|(x, y)| (x - npc_x).abs() <= 1 && (y - npc_y).abs() <= 1);
+        if !next_to {
+            let target = game.objects[furniture].pos();
+            move_astar(id, target, game);
+            return true;
+        }
+
+        let npc = game.objects[id].id;
+        let restores = game.objects[furniture]
+            .furniture
+            .and_then(|f| f.kind.restores());
+        if let Some(f) = game.objects[furniture].furniture.as_mut() {
+            f.claim(npc, NPC_FURNITURE_TURNS);
+        }
+        if let Some((need, amount)) = restores {
+            let maximum = game.objects[id].max_need(need);
+            if let Some(stats) = game.objects[id].stats.as_mut() {
+                let value = stats.need_mut(need);
+                *value = cmp::min(*value + amount, maximum);
+            }
+        }
+        return true;
+    }
+    false
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
-    use rand::distributions::{Distribution, WeightedIndex};
+/// Count down how long each piece of furniture stays in use, and free the ones
+/// whose user is done or no longer around.
+fn update_furniture_occupancy(game: &mut Game) {
+    for index in 0..game.objects.len() {
+        let (occupant, turns) = match game.objects[index].furniture {
+            Some(Furniture {
+                occupied_by: Some(occupant),
+                occupied_turns,
+                ..
+            }) => (occupant, occupied_turns),
+            _ => continue,
+        };
+        let present = game
+            .index_of(occupant)
+            .map_or(false, |id| game.objects[id].alive);
+        if let Some(furniture) = game.objects[index].furniture.as_mut() {
+            if turns <= 1 || !present {
+                furniture.release();
+            } else {
+                furniture.occupied_turns -= 1;
+            }
+        }
+    }
+}
 
-    // maximum number of items per room
-    let max_items = from_dungeon_level(
-        &[
-            Transition { level: 1, value: 1 },
-            Transition { level: 4, value: 2 },
-        ],
-        level,
-    );
+/// Pass turns until the furniture with this object id is free, then use it.
+/// Gives up after `FURNITURE_WAIT_LIMIT` turns, or when a need gets critical.
+fn wait_for_furniture(furniture: u64, game: &mut Game, tcod: &mut Tcod) {
+    game.log.add(t("furniture_wait", &[]), colors::LIGHT_GREY);
+    let player = game.objects[PLAYER].id;
+    for _ in 0..FURNITURE_WAIT_LIMIT {
+        let index = match game.index_of(furniture) {
+            Some(index) => index,
+            None => return,
+        };
+        let taken = game.objects[index]
+            .furniture
+            .map_or(false, |f| f.occupied_by_other(player));
+        if !taken {
+            use_furniture(index, game);
+            return;
+        }
+        let critical = advance_turn(&tcod.transients.fov, game);
+        if !game.objects[PLAYER].alive || !critical.is_empty() {
+            return;
+        }
+    }
+    game.log
+        .add(t("furniture_wait_gave_up", &[]), colors::LIGHT_GREY);
+}
 
-    // item random table
-    let item_chances = &mut [
-        (Item::Heal, 35), // healing potion always shows up, even if all other items have 0 chance
-        (
-            Item::Lightning,
-            from_dungeon_level(
-                &[Transition {
-                    level: 4,
-                    value: 25,
-                }],
-                level,
-            ),
-        ),
-        (
-            Item::Fireball,
-            from_dungeon_level(
-                &[Transition {
-                    level: 6,
-                    value: 25,
-                }],
-                level,
-            ),
-        ),
-        (
-            Item::Confuse,
-            from_dungeon_level(
-                &[Transition {
-                    level: 2,
-                    value: 10,
-                }],
-                level,
-            ),
-        ),
-        (
-            Item::Sword,
-            from_dungeon_level(&[Transition { level: 4, value: 5 }], level),
-        ),
-        (
-            Item::Shield,
-            from_dungeon_level(
-                &[Transition {
-                    level: 8,
-                    value: 15,
-                }],
-                level,
-            ),
-        ),
-    ];
-    let item_choice = WeightedIndex::new(item_chances.iter().map(|item| item.1)).unwrap();
+/// How far around the player the smell of low hygiene carries: nothing above
+/// `STINK_HYGIENE`, growing to `STINK_MAX_RADIUS` as hygiene runs out.
+fn stink_radius(player: &Object) -> f32 {
+    let hygiene = player.stats.map_or(STINK_HYGIENE, |s| s.hygiene);
+    if hygiene >= STINK_HYGIENE {
+        return 0.0;
+    }
+    let missing = (STINK_HYGIENE - cmp::max(hygiene, 0)) as f32 / STINK_HYGIENE as f32;
+    STINK_MAX_RADIUS * missing
+}
 
-    // choose random number of items
-    let num_items = rand::thread_rng().gen_range(0..max_items + 1);
+fn ai_confused(id: usize, game: &mut Game, previous_ai: Box<Ai>, num_turns: i32) -> Ai {
+    if num_turns >= 0 {
+        // still confused: stumble around in a random direction
+        let dx = game.rng.gen_range(-1..2);
+        let dy = game.rng.gen_range(-1..2);
+        move_by(id, dx, dy, &game.map, &mut game.objects);
+        Ai::Confused {
+            previous_ai,
+            num_turns: num_turns - 1,
+        }
+    } else {
+        // restore the previous AI (this one will be deleted)
+        game.log.add(
+            t("no_longer_confused", &[("name", &game.objects[id].name)]),
+            colors::RED,
+        );
+        *previous_ai
+    }
+}
 
-    for _ in 0..num_items {
-        // choose random spot for this item
-        let x = rand::thread_rng().gen_range(room.x1 + 1..room.x2);
-        let y = rand::thread_rng().gen_range(room.y1 + 1..room.y2);
+/// Guests walk over to the player, hang around for a while and then head back
+/// out the way they came.
+fn ai_guest(
+    id: usize,
+    game: &mut Game,
+    stage: GuestStage,
+    turns_left: i32,
+    exit: (i32, i32),
+) -> Ai {
+    let (player_x, player_y) = game.objects[PLAYER].pos();
+    let distance = game.objects[id].distance_to(&game.objects[PLAYER]);
+    let (stage, turns_left) = match stage {
+        GuestStage::Arriving if distance < 2.0 => {
+            game.log.add(
+                t("guest_arrived", &[("name", &game.objects[id].name)]),
+                colors::LIGHT_MAGENTA,
+            );
+            (GuestStage::Staying, GUEST_VISIT_TURNS)
+        }
+        // a closed door or a crowd can leave the player out of reach
+        GuestStage::Arriving if turns_left <= 0 => {
+            game.log.add(
+                t("guest_gave_up", &[("name", &game.objects[id].name)]),
+                colors::LIGHT_MAGENTA,
+            );
+            (GuestStage::Leaving, 0)
+        }
+        GuestStage::Arriving => {
+            move_astar(id, (player_x, player_y), game);
+            (GuestStage::Arriving, turns_left - 1)
+        }
+        GuestStage::Staying if turns_left <= 0 => {
+            game.log.add(
+                t("guest_leaving", &[("name", &game.objects[id].name)]),
+                colors::LIGHT_MAGENTA,
+            );
+            (GuestStage::Leaving, 0)
+        }
+        GuestStage::Staying => {
+            if distance >= 3.0 {
+                move_astar(id, (player_x, player_y), game);
+            }
+            (GuestStage::Staying, turns_left - 1)
+        }
+        // close enough counts, in case someone is standing in the doorway
+        GuestStage::Leaving if game.objects[id].distance(exit.0, exit.1) < 2.0 => {
+            (GuestStage::Gone, 0)
+        }
+        GuestStage::Leaving => {
+            move_astar(id, exit, game);
+            (GuestStage::Leaving, 0)
+        }
+        GuestStage::Gone => (GuestStage::Gone, 0),
+    };
+    Ai::Guest {
+        stage,
+        turns_left,
+        exit,
+    }
+}
 
-        // only place it if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            let mut item = match item_chances[item_choice.sample(&mut rand::thread_rng())].0 {
-                Item::Heal => {
-                    // create a healing potion
-                    let mut object =
-                        Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    // create a lightning bolt scroll
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '#',
-                        "scroll of lightning bolt",
-                        colors::LIGHT_YELLOW,
-                        false,
-                    );
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::Fireball => {
-                    // create a fireball scroll
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of fireball", colors::LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                }
-                Item::Confuse => {
-                    // create a confuse scroll
-                    let mut object = Object::new(
-                        x,
-                        y,
-                        '#',
-                        "scroll of confusion",
-                        colors::LIGHT_YELLOW,
-                        false,
-                    );
-                    object.item = Some(Item::Confuse);
-                    object
-                }
-                Item::Sword => {
-                    // create a sword
-                    let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::RightHand,
-                        max_hp_bonus: 0,
-                        defense_bonus: 0,
-                        power_bonus: 3,
-                    });
-                    object
-                }
-                Item::Shield => {
-                    // create a shield
-                    let mut object = Object::new(x, y, '[', "shield", colors::DARKER_ORANGE, false);
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::LeftHand,
-                        max_hp_bonus: 0,
-                        defense_bonus: 1,
-                        power_bonus: 0,
-                    });
-                    object
+/// Which tiles can be walked on, worked out once a turn from the map and the
+/// objects that block. Objects that move during the turn leave it a little out
+/// of date, which only costs the odd detour; each step is still checked.
+struct WalkMap {
+    free: Vec<Vec<bool>>,
+}
+
+impl WalkMap {
+    fn new(map: &Map, objects: &[Object]) -> Self {
+        let mut free = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                free[x as usize][y as usize] = !map[x as usize][y as usize].blocked;
+            }
+        }
+        for object in objects.iter().filter(|object| object.blocks) {
+            for (x, y) in object.footprint() {
+                if in_map(x, y) {
+                    free[x as usize][y as usize] = false;
                 }
-            };
-            item.always_visible = true;
-            objects.push(item);
+            }
         }
+        WalkMap { free }
+    }
+
+    fn is_free(&self, x: i32, y: i32) -> bool {
+        in_map(x, y) && self.free[x as usize][y as usize]
     }
 }
 
-/// Advance to the next level
-fn next_level(tcod: &mut Tcod, game: &mut Game) {
-    game.log.add(
-        "After a rare moment of peace, you descend deeper into \
-         the heart of the dungeon...",
-        colors::RED,
-    );
-    game.dungeon_level += 1;
-    game.map = make_map(&mut game.objects, game.dungeon_level);
-    initialise_fov(&game.map, tcod);
+/// The result of `search_grid`: how every tile it got to was reached, and the
+/// goal it stopped at, if any.
+struct GridSearch {
+    came_from: Vec<Vec<Option<(i32, i32)>>>,
+    found: Option<(i32, i32)>,
 }
 
-fn render_bar(
-    panel: &mut Offscreen,
-    x: i32,
-    y: i32,
-    total_width: i32,
-    name: &str,
-    value: i32,
-    maximum: i32,
-    bar_color: Color,
-    back_color: Color,
-) {
-    // render a bar (HP, experience, etc). First calculate the width of the bar
-    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+impl GridSearch {
+    fn reached(&self, x: i32, y: i32) -> bool {
+        in_map(x, y) && self.came_from[x as usize][y as usize].is_some()
+    }
 
-    // render the background first
-    panel.set_default_background(back_color);
-    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+    /// the steps from the start to the goal that was found, start excluded
+    fn path(&self) -> Vec<(i32, i32)> {
+        let mut path = vec![];
+        let mut pos = match self.found {
+            Some(pos) => pos,
+            None => return path,
+        };
+        while let Some(previous) = self.came_from[pos.0 as usize][pos.1 as usize] {
+            if previous == pos {
+                break;
+            }
+            path.push(pos);
+            pos = previous;
+        }
+        path.reverse();
+        path
+    }
+}
 
-    // now render the bar on top
-    panel.set_default_background(bar_color);
-    if bar_width > 0 {
-        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+/// A* over the map, eight ways, with diagonal steps costing a little more. It
+/// stops at the closest of `goals`, or goes over everything reachable when there
+/// are none. `passable` says which tiles can be stepped onto; every pathfinder
+/// in the game goes through here.
+fn search_grid<F>(start: (i32, i32), goals: &[(i32, i32)], passable: F) -> GridSearch
+where
+    F: Fn(i32, i32) -> bool,
+{
+    const STRAIGHT: i32 = 10;
+    const DIAGONAL: i32 = 14;
+    let estimate = |x: i32, y: i32| {
+        goals
+            .iter()
+            .map(|&(goal_x, goal_y)| {
+                let (dx, dy) = ((goal_x - x).abs(), (goal_y - y).abs());
+                STRAIGHT * cmp::max(dx, dy) + (DIAGONAL - STRAIGHT) * cmp::min(dx, dy)
+            })
+            .min()
+            .unwrap_or(0)
+    };
+
+    let mut search = GridSearch {
+        came_from: vec![vec![None; MAP_HEIGHT as usize]; MAP_WIDTH as usize],
+        found: None,
+    };
+    if !in_map(start.0, start.1) {
+        return search;
+    }
+    let mut cost = vec![vec![i32::MAX; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let mut open = BinaryHeap::new();
+    cost[start.0 as usize][start.1 as usize] = 0;
+    search.came_from[start.0 as usize][start.1 as usize] = Some(start);
+    open.push(Reverse((estimate(start.0, start.1), 0, start)));
+
+    while let Some(Reverse((_, so_far, (x, y)))) = open.pop() {
+        if so_far > cost[x as usize][y as usize] {
+            continue;
+        }
+        if goals.contains(&(x, y)) {
+            search.found = Some((x, y));
+            break;
+        }
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (nx, ny) = (x + dx, y + dy);
+                if (dx, dy) == (0, 0) || !in_map(nx, ny) || !passable(nx, ny) {
+                    continue;
+                }
+                let step = if dx != 0 && dy != 0 {
+                    DIAGONAL
+                } else {
+                    STRAIGHT
+                };
+                let next = so_far + step;
+                if next < cost[nx as usize][ny as usize] {
+                    cost[nx as usize][ny as usize] = next;
+                    search.came_from[nx as usize][ny as usize] = Some((x, y));
+                    open.push(Reverse((next + estimate(nx, ny), next, (nx, ny))));
+                }
+            }
+        }
     }
+    search
+}
 
-    // finally, some centered text with the values
-    panel.set_default_foreground(colors::BLACK);
-    panel.print_ex(
-        x,
-        y,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        &format!("{: >7}: {}/{}", name, value, maximum),
+/// Take one step along the shortest path to `target`, walking around other
+/// objects in the way. Falls back to a straight line if there is no path.
+fn move_astar(id: usize, target: (i32, i32), game: &mut Game) {
+    let start = game.objects[id].pos();
+    let step = {
+        let walk = game.walk_map();
+        let search = search_grid(start, &[target], |x, y| {
+            (x, y) == target || walk.is_free(x, y)
+        });
+        let path = search.path();
+        if search.found.is_some() && path.len() < (MAP_WIDTH + MAP_HEIGHT) as usize {
+            path.first().copied()
+        } else {
+            None
+        }
+    };
+    match step {
+        Some((step_x, step_y)) => {
+            if !is_blocked(step_x, step_y, &game.map, &game.objects) {
+                game.objects[id].step_to(step_x, step_y);
+            }
+        }
+        None => move_towards(id, target.0, target.1, &game.map, &mut game.objects),
+    }
+}
+
+/// Where a new guest turns up: out of sight, as close to the map edge as the
+/// layout allows.
+fn guest_entrance(fov: &FovMap, game: &mut Game) -> Option<(i32, i32)> {
+    // free floor tiles the player can't see, so guests don't pop into view
+    let mut spots = vec![];
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            if !is_blocked(x, y, &game.map, &game.objects) && !fov.is_in_fov(x, y) {
+                spots.push((x, y));
+            }
+        }
+    }
+    // the outer wall is solid, so the edge is the free tiles nearest to it
+    let to_edge = |&(x, y): &(i32, i32)| x.min(y).min(MAP_WIDTH - 1 - x).min(MAP_HEIGHT - 1 - y);
+    let nearest = spots.iter().map(to_edge).min()?;
+    spots.retain(|spot| to_edge(spot) == nearest);
+    Some(spots[game.rng.gen_range(0..spots.len())])
+}
+
+/// Now and then during the day someone drops by: they turn up at the edge of the
+/// map, come find the player and leave again after a while.
+fn maybe_invite_guest(fov: &FovMap, game: &mut Game) {
+    if is_night(clock_minutes(game.turn)) || !game.rng.gen_bool(GUEST_CHANCE) {
+        return;
+    }
+    let visiting = game
+        .objects
+        .iter()
+        .any(|object| matches!(object.ai, Some(Ai::Guest { .. })));
+    if visiting {
+        return;
+    }
+
+    let (x, y) = match guest_entrance(fov, game) {
+        Some(spot) => spot,
+        None => return,
+    };
+
+    let name = NPC_NAMES[game.rng.gen_range(0..NPC_NAMES.len())];
+    let mut guest = Object::new(x, y, '@', name, colors::LIGHT_MAGENTA, true);
+    guest.alive = true;
+    guest.stats = Some(Stats {
+        base_max_all_stats: 100,
+        hunger: 100,
+        comfort: 100,
+        hygiene: 100,
+        bladder: 100,
+        energy: 100,
+        fun: 100,
+        social: 100,
+        room: 100,
+        on_death: DeathCallback::NPC,
+    });
+    guest.ai = Some(Ai::Guest {
+        stage: GuestStage::Arriving,
+        turns_left: GUEST_ARRIVE_TURNS,
+        exit: (x, y),
+    });
+    guest.add_tag(TAG_INTERACTIVE);
+    game.objects.push(guest);
+    game.assign_object_ids();
+    game.log.add(
+        t("guest_visiting", &[("name", &name)]),
+        colors::LIGHT_MAGENTA,
+    );
+}
+
+/// What bumping into a tile amounts to for the player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Interaction {
+    OpenDoor(usize),
+    Talk(usize),
+    UseFurniture(usize),
+    StepOntoItem(usize),
+    Move,
+    Blocked,
+}
+
+/// work out what moving the player onto (x, y) means, in order of priority
+fn resolve_interaction(x: i32, y: i32, game: &Game) -> Interaction {
+    let here = |object: &&Object| object.covers(x, y);
+
+    if let Some(id) = game
+        .objects
+        .iter()
+        .position(|object| here(&object) && object.door.map_or(false, |door| !door.open))
+    {
+        return Interaction::OpenDoor(id);
+    }
+    if let Some(id) = game.objects.iter().position(|object| {
+        here(&object) && object.alive && object.ai.is_some() && object.has_tag(TAG_INTERACTIVE)
+    }) {
+        return Interaction::Talk(id);
+    }
+    if let Some(id) = game
+        .objects
+        .iter()
+        .position(|object| here(&object) && object.has_tag(TAG_FURNITURE))
+    {
+        return Interaction::UseFurniture(id);
+    }
+    if is_blocked(x, y, &game.map, &game.objects) {
+        return Interaction::Blocked;
+    }
+    if let Some(id) = game
+        .objects
+        .iter()
+        .position(|object| here(&object) && object.item.is_some())
+    {
+        return Interaction::StepOntoItem(id);
+    }
+    Interaction::Move
+}
+
+/// Use a piece of furniture, offering to wait if someone else has it and to
+/// sleep through the night if it's a bed.
+fn interact_with_furniture(furniture_id: usize, game: &mut Game, tcod: &mut Tcod) {
+    let player = game.objects[PLAYER].id;
+    let occupant = game.objects[furniture_id]
+        .furniture
+        .and_then(|f| f.occupied_by)
+        .filter(|&occupant| occupant != player)
+        .and_then(|occupant| game.index_of(occupant));
+    if let Some(occupant) = occupant {
+        let kind = game.objects[furniture_id].name.clone();
+        let options = &[t("furniture_wait_option", &[]), t("never_mind", &[])];
+        let header = format!(
+            "{}\n",
+            t(
+                "furniture_occupied",
+                &[("name", &game.objects[occupant].name), ("furniture", &kind)]
+            )
+        );
+        if menu(&header, options, 40, tcod) == Some(0) {
+            let furniture = game.objects[furniture_id].id;
+            wait_for_furniture(furniture, game, tcod);
+        }
+        return;
+    }
+
+    let bed = game.objects[furniture_id]
+        .furniture
+        .map_or(false, |f| f.kind == FurnitureKind::Bed && !f.is_broken());
+    if bed && is_night(clock_minutes(game.turn)) {
+        let options = &[t("bed_sleep", &[]), t("bed_rest", &[])];
+        let header = format!("{}\n", t("bed_prompt", &[]));
+        match menu(&header, options, 30, tcod) {
+            Some(0) => sleep_until_morning(furniture_id, game, &tcod.transients.fov),
+            Some(1) => use_furniture(furniture_id, game),
+            _ => {}
+        }
+    } else {
+        use_furniture(furniture_id, game);
+    }
+}
+
+/// What the interact key does, depending on where the player stands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ContextAction {
+    Descend,
+    PickUp(usize),
+    UseFurniture(usize),
+    Nothing,
+}
+
+/// work out what the interact key means where the player is, in order of
+/// priority: stairs underfoot, an item underfoot, then furniture next to them
+fn resolve_context(game: &Game) -> ContextAction {
+    let (x, y) = game.objects[PLAYER].pos();
+
+    if game
+        .objects_with_tag(TAG_STAIRS)
+        .any(|id| game.objects[id].pos() == (x, y))
+    {
+        return ContextAction::Descend;
+    }
+    if let Some(id) = game
+        .objects
+        .iter()
+        .position(|object| object.pos() == (x, y) && object.item.is_some())
+    {
+        return ContextAction::PickUp(id);
+    }
+    // furniture can't be stood on, so anything within reach will do
+    if let Some(id) = game.objects.iter().position(|object| {
+        object.has_tag(TAG_FURNITURE)
+            && object
+                .footprint()
+                .into_iter()
+                .any(|(fx, fy)| (fx - x).abs() <= 1 && (fy - y).abs() <= 1)
+    }) {
+        return ContextAction::UseFurniture(id);
+    }
+    ContextAction::Nothing
+}
+
+/// do whatever makes sense where the player is; only furniture takes a turn,
+/// just like the keys for each of these
+fn context_interact(game: &mut Game, tcod: &mut Tcod) -> PlayerAction {
+    match resolve_context(game) {
+        ContextAction::Descend => {
+            if ready_to_descend(game) {
+                next_level(tcod, game);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+        ContextAction::PickUp(item_id) => {
+            pick_item_up(item_id, game);
+            PlayerAction::DidntTakeTurn
+        }
+        ContextAction::UseFurniture(furniture_id) => {
+            interact_with_furniture(furniture_id, game, tcod);
+            PlayerAction::TookTurn
+        }
+        ContextAction::Nothing => PlayerAction::DidntTakeTurn,
+    }
+}
+
+/// move the player, or interact with whatever is in the way
+fn player_move_or_interact(dx: i32, dy: i32, game: &mut Game, tcod: &mut Tcod) {
+    // the coordinates the player is moving to
+    let x = game.objects[PLAYER].x + dx;
+    let y = game.objects[PLAYER].y + dy;
+
+    match resolve_interaction(x, y, game) {
+        Interaction::OpenDoor(door_id) => open_door(door_id, game),
+        Interaction::Talk(npc_id) => talk_to(npc_id, game),
+        Interaction::UseFurniture(furniture_id) => {
+            interact_with_furniture(furniture_id, game, tcod)
+        }
+        Interaction::StepOntoItem(item_id) => {
+            step_onto_item(item_id, dx, dy, game, |q| confirm(q, tcod))
+        }
+        Interaction::Move => walk(dx, dy, game),
+        Interaction::Blocked => {}
+    }
+}
+
+/// Step onto an item and offer to pick it up; `ask` puts the question to the
+/// player. Left where it is, it can still be picked up later.
+fn step_onto_item<F>(item_id: usize, dx: i32, dy: i32, game: &mut Game, ask: F)
+where
+    F: FnOnce(&str) -> bool,
+{
+    move_by(PLAYER, dx, dy, &game.map, &mut game.objects);
+    let name = game.objects[item_id].name.clone();
+    if ask(&t("confirm_pick_up", &[("item", &name)])) {
+        pick_item_up(item_id, game);
+    } else {
+        game.log
+            .add(t("item_here", &[("item", &name)]), colors::LIGHT_GREY);
+        show_hint(Hint::ItemHere, game);
+    }
+}
+
+/// Step the player onto the open tile next to them, and on to the one after
+/// that when sprinting.
+fn walk(dx: i32, dy: i32, game: &mut Game) {
+    move_by(PLAYER, dx, dy, &game.map, &mut game.objects);
+    if game.sprinting {
+        sprint_step(dx, dy, game);
+    }
+    show_hint(Hint::FirstMove, game);
+    let player = &game.objects[PLAYER];
+    let next_to_furniture = game.objects.iter().any(|object| {
+        object.has_tag(TAG_FURNITURE)
+            && object
+                .footprint()
+                .into_iter()
+                .any(|(x, y)| (x - player.x).abs() <= 1 && (y - player.y).abs() <= 1)
+    });
+    if next_to_furniture {
+        show_hint(Hint::Furniture, game);
+    }
+}
+
+/// The extra tile a sprint covers, if it's free and the player has the energy
+/// for it. Anything but open floor ends the sprint early.
+fn sprint_step(dx: i32, dy: i32, game: &mut Game) {
+    let (x, y) = game.objects[PLAYER].pos();
+    let (x, y) = (x + dx, y + dy);
+    if is_blocked(x, y, &game.map, &game.objects) {
+        return;
+    }
+    if let Interaction::Move = resolve_interaction(x, y, game) {
+        if game.objects[PLAYER].stats.map_or(0, |s| s.energy) < SPRINT_ENERGY_COST {
+            return;
+        }
+        move_by(PLAYER, dx, dy, &game.map, &mut game.objects);
+        drain_player_need(Need::Energy, SPRINT_ENERGY_COST, game);
+    }
+}
+
+fn open_door(door_id: usize, game: &mut Game) {
+    let door = &mut game.objects[door_id];
+    door.door = Some(Door { open: true });
+    door.blocks = false;
+    door.char = '\'';
+    game.log.add(t("door_opened", &[]), colors::LIGHT_GREY);
+}
+
+/// use a piece of furniture, restoring whatever need it takes care of
+fn use_furniture(furniture_id: usize, game: &mut Game) {
+    let kind = match game.objects[furniture_id].furniture {
+        Some(furniture) => furniture.kind,
+        None => return,
+    };
+
+    if kind == FurnitureKind::Valve {
+        game.water_on = !game.water_on;
+        if game.water_on {
+            game.log.add(t("water_on", &[]), colors::LIGHT_BLUE);
+        } else {
+            game.log.add(t("water_off", &[]), colors::LIGHT_BLUE);
+        }
+        return;
+    }
+
+    if kind.needs_water() && !game.water_on {
+        game.log.add(
+            t("furniture_no_water", &[("furniture", &kind)]),
+            colors::ORANGE,
+        );
+        return;
+    }
+
+    if kind.needs_power() && game.event_active(RandomEvent::PowerOutage) {
+        game.log.add(
+            t("furniture_no_power", &[("furniture", &kind)]),
+            colors::ORANGE,
+        );
+        return;
+    }
+
+    let furniture = &mut game.objects[furniture_id];
+    if let Some(durability) = furniture.furniture.as_ref().map(|f| f.durability) {
+        if durability <= 0 {
+            game.log.add(
+                t("furniture_broken", &[("furniture", &kind)]),
+                colors::ORANGE,
+            );
+            return;
+        }
+        // wear it down a little
+        if let Some(f) = furniture.furniture.as_mut() {
+            f.durability -= 1;
+        }
+        if durability == 1 {
+            furniture.color = colors::DARK_GREY;
+            game.log.add(
+                t("furniture_breaks", &[("furniture", &kind)]),
+                colors::ORANGE,
+            );
+        }
+    }
+
+    if let Some((need, amount)) = kind.restores() {
+        let maximum = game.objects[PLAYER].max_need(need);
+        if let Some(stats) = game.objects[PLAYER].stats.as_mut() {
+            let value = stats.need_mut(need);
+            *value = cmp::min(*value + amount, maximum);
+        }
+        game.log.add(
+            t("furniture_used", &[("furniture", &kind)]),
+            colors::LIGHT_GREEN,
+        );
+        game.emit(GameEvent::FurnitureUsed(kind));
+    }
+}
+
+/// minutes since midnight at the start of a turn
+fn clock_minutes(turn: u32) -> u32 {
+    (START_TIME + turn) % MINUTES_PER_DAY
+}
+
+/// "Day 2, 07:30"
+fn format_clock(turn: u32) -> String {
+    let day = (START_TIME + turn) / MINUTES_PER_DAY + 1;
+    let minutes = clock_minutes(turn);
+    t(
+        "clock",
+        &[
+            ("day", &day),
+            ("time", &format!("{:02}:{:02}", minutes / 60, minutes % 60)),
+        ],
+    )
+}
+
+fn is_night(minutes: u32) -> bool {
+    minutes >= NIGHT_START || minutes < NIGHT_END
+}
+
+/// how many minutes from `now` until the clock next shows `until`, going
+/// past midnight if need be
+fn minutes_until(now: u32, until: u32) -> u32 {
+    (until + MINUTES_PER_DAY - now) % MINUTES_PER_DAY
+}
+
+/// Sleep until the configured wake-up time, playing out every skipped turn as
+/// usual. A need turning critical or someone coming over wakes the player early.
+fn sleep_until_morning(bed_id: usize, game: &mut Game, fov: &FovMap) {
+    let wake_time = game.config.wake_hour % 24 * 60;
+    let turns = minutes_until(clock_minutes(game.turn), wake_time);
+    game.log.add(t("fall_asleep", &[]), colors::LIGHT_BLUE);
+
+    // nobody else gets the bed while the player is in it
+    let bed = game.objects[bed_id].id;
+    let player = game.objects[PLAYER].id;
+    if let Some(furniture) = game.objects[bed_id].furniture.as_mut() {
+        furniture.claim(player, turns as i32 + 1);
+    }
+
+    let mut woken_by = None;
+    for _ in 0..turns {
+        let critical = advance_turn(fov, game);
+
+        let max_energy = game.objects[PLAYER].max_energy();
+        let turn = game.turn;
+        if let Some(stats) = game.objects[PLAYER].stats.as_mut() {
+            let rested = need_drain(SLEEP_ENERGY_RATE, turn);
+            stats.energy = cmp::min(stats.energy + rested, max_energy);
+        }
+
+        if !game.objects[PLAYER].alive || !critical.is_empty() {
+            break;
+        }
+        let player = &game.objects[PLAYER];
+        // the pet curls up next to the bed, it doesn't count
+        woken_by = game.objects.iter().position(|object| {
+            object.ai.is_some()
+                && object.ai != Some(Ai::Follow)
+                && object.alive
+                && object.distance_to(player) < 2.0
+        });
+        if woken_by.is_some() {
+            break;
+        }
+    }
+
+    if let Some(furniture) = game
+        .index_of(bed)
+        .and_then(|id| game.objects[id].furniture.as_mut())
+    {
+        furniture.release();
+    }
+
+    if !game.objects[PLAYER].alive {
+        return;
+    }
+    let time = format_clock(game.turn);
+    match woken_by {
+        Some(id) => game.log.add(
+            t(
+                "woken_by",
+                &[("name", &game.objects[id].name), ("time", &time)],
+            ),
+            colors::LIGHT_BLUE,
+        ),
+        None => game
+            .log
+            .add(t("wake_up", &[("time", &time)]), colors::LIGHT_BLUE),
+    }
+}
+
+/// Find the closest piece of furniture (by walking distance) that restores the
+/// given need. Only explored tiles are searched, so this never gives away
+/// furniture the player hasn't seen yet.
+fn nearest_source_for(need: Need, game: &Game) -> Option<(i32, i32)> {
+    let restores = |x: i32, y: i32| {
+        game.objects.iter().any(|object| {
+            object.covers(x, y)
+                && object.furniture.map_or(false, |f| {
+                    !f.is_broken() && f.kind.restores().map_or(false, |(n, _)| n == need)
+                })
+        })
+    };
+
+    let goals: Vec<(i32, i32)> = (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| game.map[x as usize][y as usize].explored && restores(x, y))
+        .collect();
+    // only over explored floor, and you can't walk through furniture (doors
+    // open and people move aside)
+    let start = game.objects[PLAYER].pos();
+    let search = search_grid(start, &goals, |x, y| {
+        let furniture_here = || {
+            game.objects
+                .iter()
+                .any(|object| object.covers(x, y) && object.has_tag(TAG_FURNITURE))
+        };
+        !is_wall(&game.map, x, y)
+            && game.map[x as usize][y as usize].explored
+            && (goals.contains(&(x, y)) || !furniture_here())
+    });
+    search.found.filter(|&found| found != start)
+}
+
+/// ask which need to take care of and point out the closest place to do it
+fn show_nearest_source(game: &mut Game, tcod: &mut Tcod) {
+    let options: Vec<String> = match game.objects[PLAYER].stats {
+        Some(stats) => Need::ALL
+            .iter()
+            .map(|&need| format!("{} ({})", need, stats.need(need)))
+            .collect(),
+        None => return,
+    };
+    let header = format!("{}\n", t("menu_which_need", &[]));
+    let need = match menu(&header, &options, INVENTORY_WIDTH, tcod) {
+        Some(index) => Need::ALL[index],
+        None => return,
+    };
+
+    match nearest_source_for(need, game) {
+        Some(pos) => {
+            spawn_particle(tcod, pos, (0.0, 0.0), 'X', colors::LIGHT_YELLOW, 40);
+            game.log
+                .add(t("source_marked", &[("need", &need)]), colors::LIGHT_YELLOW);
+        }
+        None => game
+            .log
+            .add(t("source_unknown", &[("need", &need)]), colors::LIGHT_GREY),
+    }
+}
+
+/// fix up a broken piece of furniture next to the player, at the cost of some energy
+fn repair_furniture(game: &mut Game) -> bool {
+    let player = &game.objects[PLAYER];
+    let broken_id = game.objects.iter().position(|object| {
+        object.furniture.map_or(false, |f| f.is_broken())
+            && object
+                .footprint()
+                .into_iter()
+                .any(|(x, y)| player.distance(x, y) < 2.0)
+    });
+    let broken_id = match broken_id {
+        Some(id) => id,
+        None => {
+            game.log
+                .add(t("nothing_to_repair", &[]), colors::LIGHT_GREY);
+            return false;
+        }
+    };
+
+    let furniture = &mut game.objects[broken_id];
+    if let Some(f) = furniture.furniture.as_mut() {
+        f.durability = f.kind.max_durability().unwrap_or(0);
+        furniture.color = f.kind.color();
+        game.log.add(
+            t("furniture_repaired", &[("furniture", &f.kind)]),
+            colors::LIGHT_GREEN,
+        );
+    }
+    if let Some(stats) = game.objects[PLAYER].stats.as_mut() {
+        stats.energy = cmp::max(stats.energy - REPAIR_ENERGY_COST, 0);
+    }
+    true
+}
+
+/// have a chat with an NPC, raising the player's social need
+fn talk_to(npc_id: usize, game: &mut Game) {
+    if game.objects[npc_id].attitude() == Attitude::Hostile {
+        game.log.add(
+            t("npc_refuses", &[("name", &game.objects[npc_id].name)]),
+            colors::LIGHT_RED,
+        );
+        change_relationship(npc_id, RELATIONSHIP_PESTER, game);
+        return;
+    }
+    if game.objects[npc_id].timer_running(TIMER_TALK) {
+        game.log.add(
+            t("npc_busy", &[("name", &game.objects[npc_id].name)]),
+            colors::LIGHT_GREY,
+        );
+        change_relationship(npc_id, RELATIONSHIP_PESTER, game);
+        return;
+    }
+
+    // the worse the player smells, the less likely anyone wants to chat
+    let radius = stink_radius(&game.objects[PLAYER]);
+    if radius > 0.0 && game.rng.gen_bool((radius / STINK_MAX_RADIUS) as f64) {
+        game.log.add(
+            t("npc_smell", &[("name", &game.objects[npc_id].name)]),
+            colors::LIGHT_GREY,
+        );
+        return;
+    }
+
+    // a guest made the trip over, so they're better company
+    let gain = match game.objects[npc_id].ai {
+        Some(Ai::Guest { .. }) => TALK_SOCIAL_GAIN + GUEST_SOCIAL_BONUS,
+        _ => TALK_SOCIAL_GAIN,
+    };
+    let max_social = game.objects[PLAYER].max_social();
+    if let Some(stats) = game.objects[PLAYER].stats.as_mut() {
+        stats.social = cmp::min(stats.social + gain, max_social);
+    }
+    game.objects[npc_id].set_timer(TIMER_TALK, TALK_COOLDOWN);
+    game.log.add(
+        t("npc_chat", &[("name", &game.objects[npc_id].name)]),
+        colors::LIGHT_CYAN,
+    );
+    change_relationship(npc_id, RELATIONSHIP_TALK, game);
+}
+
+/// add to the player's inventory and remove from the map
+fn pick_item_up(object_id: usize, game: &mut Game) {
+    if game.inventory.len() >= INVENTORY_CAPACITY {
+        game.log.add(
+            t("inventory_full", &[("item", &game.objects[object_id].name)]),
+            colors::RED,
+        );
+    } else if let Some(item) = game.take_object(object_id) {
+        game.log
+            .add(t("picked_up", &[("item", &item.name)]), colors::GREEN);
+        stash_item(item, game);
+        if let Some(key) = game.config.auto_sort_inventory {
+            sort_inventory(&mut game.inventory, key);
+        }
+    }
+}
+
+/// A furnished room layout that can be stamped onto the room the player is in.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RoomTemplate {
+    name: String,
+    furniture: Vec<TemplatePiece>,
+}
+
+/// One piece of furniture in a template, placed relative to the room's
+/// top-left floor tile.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct TemplatePiece {
+    kind: FurnitureKind,
+    x: i32,
+    y: i32,
+}
+
+fn default_room_templates() -> Vec<RoomTemplate> {
+    use FurnitureKind::*;
+    let piece = |kind, x, y| TemplatePiece { kind, x, y };
+    vec![
+        RoomTemplate {
+            name: "Bedroom".into(),
+            furniture: vec![piece(Bed, 0, 0), piece(Television, 3, 0)],
+        },
+        RoomTemplate {
+            name: "Bathroom".into(),
+            furniture: vec![piece(Toilet, 0, 0), piece(Shower, 2, 0)],
+        },
+        RoomTemplate {
+            name: "Living room".into(),
+            furniture: vec![
+                piece(Sofa, 0, 0),
+                piece(Television, 0, 2),
+                piece(Fridge, 3, 0),
+            ],
+        },
+    ]
+}
+
+/// Furnish the room the player is standing in from a template. Pieces that
+/// would stick out of the room or land on something are left out; returns
+/// how many were placed.
+fn apply_template(game: &mut Game, template: &RoomTemplate) -> usize {
+    let (player_x, player_y) = game.objects[PLAYER].pos();
+    let room = match game
+        .rooms
+        .iter()
+        .find(|room| room.contains(player_x, player_y))
+    {
+        Some(room) => *room,
+        None => {
+            game.log.add(t("template_no_room", &[]), colors::ORANGE);
+            return 0;
+        }
+    };
+
+    let mut placed = 0;
+    for piece in &template.furniture {
+        let furniture = make_furniture(piece.kind, room.x1 + 1 + piece.x, room.y1 + 1 + piece.y);
+        let fits = furniture
+            .footprint()
+            .into_iter()
+            .all(|(x, y)| room.contains(x, y) && !is_blocked(x, y, &game.map, &game.objects));
+        if fits {
+            game.objects.push(furniture);
+            placed += 1;
+        }
+    }
+    game.assign_object_ids();
+
+    let total = template.furniture.len();
+    let (key, color) = if placed == total {
+        ("template_applied", colors::LIGHT_GREEN)
+    } else {
+        ("template_partial", colors::ORANGE)
+    };
+    game.log.add(
+        t(
+            key,
+            &[
+                ("template", &template.name),
+                ("placed", &placed),
+                ("total", &total),
+            ],
+        ),
+        color,
     );
+    placed
+}
+
+/// What the inventory can be sorted by.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum SortKey {
+    Name,
+    Kind, // consumables first, then equipment, in the order `Item` declares them
+}
+
+/// Sort the inventory. The sort is stable, so items that compare equal keep
+/// the order they were picked up in, and equipped items stay equipped.
+fn sort_inventory(inventory: &mut Vec<Object>, key: SortKey) {
+    match key {
+        SortKey::Name => inventory.sort_by(|a, b| a.name.cmp(&b.name)),
+        SortKey::Kind => inventory.sort_by_key(|object| object.item.map(|item| item as u8)),
+    }
 }
 
-/// return a string with the names of all objects under the mouse
-fn get_names_under_mouse(mouse: Mouse, objects: &[Object], fov_map: &FovMap) -> String {
-    let (x, y) = (mouse.cx as i32, mouse.cy as i32);
+/// put an item in the inventory, equipping it if its slot is still free
+fn stash_item(item: Object, game: &mut Game) {
+    if let Some(kind) = item.item {
+        game.codex.add_item(kind);
+    }
+    let index = game.inventory.len();
+    let slot = item.equipment.map(|e| e.slot);
+    game.inventory.push(item);
+    // equipped items are tracked by id, so it needs one now
+    game.assign_object_ids();
+
+    // automatically equip, if the corresponding equipment slot is unused
+    if let Some(slot) = slot {
+        if !game.equipped.contains_key(&slot) {
+            game.equip(index);
+        }
+    }
+}
+
+fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    for (inventory_id, item) in inventory.iter().enumerate() {
+        if item
+            .equipment
+            .as_ref()
+            .map_or(false, |e| e.equipped && e.slot == slot)
+        {
+            return Some(inventory_id);
+        }
+    }
+    None
+}
+
+/// whether (x, y) is a tile on the map at all
+fn in_map(x: i32, y: i32) -> bool {
+    x >= 0 && x < MAP_WIDTH && y >= 0 && y < MAP_HEIGHT
+}
+
+fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+    // off the map is as good as a wall
+    if !in_map(x, y) {
+        return true;
+    }
+    // first test the map tile
+    if map[x as usize][y as usize].blocked {
+        return true;
+    }
+    // now check for any blocking objects
+    objects
+        .iter()
+        .any(|object| object.blocks && object.covers(x, y))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum DeathCallback {
+    Player,
+    NPC,
+}
+
+impl DeathCallback {
+    fn callback(self, object: &mut Object, log: &mut Messages) {
+        let callback: fn(&mut Object, &mut Messages) = match self {
+            DeathCallback::Player => player_death,
+            DeathCallback::NPC => npc_death,
+        };
+        callback(object, log);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Ai {
+    Basic,
+    Confused {
+        previous_ai: Box<Ai>,
+        num_turns: i32,
+    },
+    Guest {
+        stage: GuestStage,
+        turns_left: i32,
+        exit: (i32, i32),
+    },
+    Follow, // a pet, sticking close to the player
+}
+
+/// Where a visiting guest is in their visit.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum GuestStage {
+    Arriving,
+    Staying,
+    Leaving,
+    Gone,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Item {
+    Heal,
+    Lightning,
+    Confuse,
+    Fireball,
+    Sword,
+    Shield,
+    Dagger,
+}
+
+impl Item {
+    const ALL: [Item; 7] = [
+        Item::Heal,
+        Item::Lightning,
+        Item::Confuse,
+        Item::Fireball,
+        Item::Sword,
+        Item::Shield,
+        Item::Dagger,
+    ];
+
+    /// string table key of its description: some flavor and what it does
+    fn description_key(self) -> &'static str {
+        match self {
+            Item::Heal => "item_heal_description",
+            Item::Lightning => "item_lightning_description",
+            Item::Confuse => "item_confuse_description",
+            Item::Fireball => "item_fireball_description",
+            Item::Sword => "item_sword_description",
+            Item::Shield => "item_shield_description",
+            Item::Dagger => "item_dagger_description",
+        }
+    }
+
+    /// its description with the numbers filled in from what it actually does,
+    /// so the text can't drift from the item-def table
+    fn description(self) -> String {
+        let amount = self.restores().map_or(0, |(_, amount)| amount);
+        let (slot, power, defense) = match make_item(self, 0, 0).equipment {
+            Some(equipment) => (
+                equipment.slot.to_string(),
+                equipment.power_bonus,
+                equipment.defense_bonus,
+            ),
+            None => (String::new(), 0, 0),
+        };
+        t(
+            self.description_key(),
+            &[
+                ("amount", &amount),
+                ("slot", &slot),
+                ("power", &power),
+                ("defense", &defense),
+            ],
+        )
+    }
+
+    /// which of the player's needs using it raises, and by how much
+    fn restores(self) -> Option<(Need, i32)> {
+        match self {
+            Item::Heal => Some((Need::Bladder, 20)),
+            Item::Lightning => Some((Need::Energy, 20)),
+            Item::Confuse => Some((Need::Social, 20)),
+            Item::Fireball | Item::Sword | Item::Shield | Item::Dagger => None,
+        }
+    }
+
+    /// whether using it is worth a second thought: these are hard to come by
+    /// and gone once used, so the player is asked before it happens
+    fn confirm_use(self) -> bool {
+        match self {
+            Item::Lightning | Item::Fireball => true,
+            Item::Heal | Item::Confuse | Item::Sword | Item::Shield | Item::Dagger => false,
+        }
+    }
+}
+
+enum UseResult {
+    UsedUp,
+    UsedAndKept,
+    Cancelled,
+}
+
+fn use_item(inventory_id: usize, game: &mut Game, tcod: &mut Tcod) {
+    if let Some(item) = item_to_use(inventory_id, game, |question| confirm(question, tcod)) {
+        apply_item(inventory_id, item, game, tcod);
+    }
+}
+
+/// The kind of item to use from the inventory, once the player has answered
+/// `ask` for the ones that need confirming. None if it can't be used or the
+/// player thought better of it, in which case the inventory is left alone.
+fn item_to_use<F>(inventory_id: usize, game: &mut Game, ask: F) -> Option<Item>
+where
+    F: FnOnce(&str) -> bool,
+{
+    let item = match game.inventory[inventory_id].item {
+        Some(item) => item,
+        None => {
+            game.log.add(
+                t(
+                    "cannot_use",
+                    &[("item", &game.inventory[inventory_id].name)],
+                ),
+                colors::WHITE,
+            );
+            return None;
+        }
+    };
+    if item.confirm_use() {
+        let question = t(
+            "confirm_use",
+            &[("item", &game.inventory[inventory_id].name)],
+        );
+        if !ask(&question) {
+            game.log.add(t("cancelled", &[]), colors::WHITE);
+            return None;
+        }
+    }
+    Some(item)
+}
+
+/// use an item without asking first
+fn apply_item(inventory_id: usize, item: Item, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    let on_use: fn(usize, &mut Game, &mut Tcod) -> UseResult = match item {
+        Item::Heal => cast_heal,
+        Item::Lightning => cast_lightning,
+        Item::Confuse => cast_confuse,
+        Item::Fireball => cast_fireball,
+        Item::Sword => toggle_equipment,
+        Item::Shield => toggle_equipment,
+        Item::Dagger => toggle_equipment,
+    };
+    let result = on_use(inventory_id, game, tcod);
+    match result {
+        UseResult::UsedUp => {
+            // destroy after use, unless it was cancelled for some reason
+            game.inventory.remove(inventory_id);
+            game.emit(GameEvent::ItemUsed(item));
+        }
+        UseResult::UsedAndKept => game.emit(GameEvent::ItemUsed(item)),
+        UseResult::Cancelled => {
+            game.log.add(t("cancelled", &[]), colors::WHITE);
+        }
+    }
+    result
+}
+
+/// Use every item of a kind in the inventory, one after the other, until the
+/// need they raise is full. Only works for items that raise a need; whatever
+/// isn't needed stays in the inventory.
+fn use_all_of_type(item: Item, game: &mut Game, tcod: &mut Tcod) {
+    let need = match item.restores() {
+        Some((need, _)) => need,
+        None => {
+            game.log
+                .add(t("use_all_not_consumable", &[]), colors::WHITE);
+            return;
+        }
+    };
+    let name = match game
+        .inventory
+        .iter()
+        .find(|object| object.item == Some(item))
+    {
+        Some(object) => object.name.clone(),
+        None => return,
+    };
+    if item.confirm_use() {
+        let count = game
+            .inventory
+            .iter()
+            .filter(|object| object.item == Some(item))
+            .count();
+        let question = t("confirm_use_all", &[("count", &count), ("item", &name)]);
+        if !confirm(&question, tcod) {
+            game.log.add(t("cancelled", &[]), colors::WHITE);
+            return;
+        }
+    }
+
+    let used = use_until_full(item, need, game, |inventory_id, game| {
+        apply_item(inventory_id, item, game, tcod)
+    });
+
+    let left = game
+        .inventory
+        .iter()
+        .filter(|object| object.item == Some(item))
+        .count();
+    game.log.add(
+        t(
+            "use_all_done",
+            &[
+                ("count", &used),
+                ("item", &name),
+                ("need", &need),
+                ("left", &left),
+            ],
+        ),
+        colors::LIGHT_GREEN,
+    );
+}
+
+/// Use one item of a kind after another with `use_one` until `need` is full or
+/// there are none left. Returns how many were used up.
+fn use_until_full<F>(item: Item, need: Need, game: &mut Game, mut use_one: F) -> u32
+where
+    F: FnMut(usize, &mut Game) -> UseResult,
+{
+    let maximum = game.objects[PLAYER].max_need(need);
+    let mut used = 0;
+    loop {
+        let full = game.objects[PLAYER]
+            .stats
+            .map_or(true, |stats| stats.need(need) >= maximum);
+        let next = game
+            .inventory
+            .iter()
+            .position(|object| object.item == Some(item));
+        let inventory_id = match next {
+            Some(inventory_id) if !full => inventory_id,
+            _ => break,
+        };
+        match use_one(inventory_id, game) {
+            UseResult::UsedUp => used += 1,
+            UseResult::UsedAndKept | UseResult::Cancelled => break,
+        }
+    }
+    used
+}
+
+/// What two items can be combined into. The order of the ingredients doesn't matter.
+const RECIPES: &[(Item, Item, Item)] = &[
+    (Item::Dagger, Item::Dagger, Item::Sword),
+    (Item::Lightning, Item::Confuse, Item::Fireball),
+    (Item::Heal, Item::Heal, Item::Lightning),
+];
+
+/// the item a recipe makes out of `a` and `b`, if there is one
+fn recipe_for(a: Item, b: Item) -> Option<Item> {
+    RECIPES
+        .iter()
+        .find(|&&(x, y, _)| (x, y) == (a, b) || (x, y) == (b, a))
+        .map(|&(_, _, result)| result)
+}
+
+/// What the player has come across so far: the kinds of items they've held
+/// and the recipes they've made, each in the order they were found.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct Codex {
+    items: Vec<Item>,
+    recipes: Vec<(Item, Item)>, // the two ingredients
+}
+
+impl Codex {
+    /// Returns whether the item is new to the codex.
+    pub fn add_item(&mut self, item: Item) -> bool {
+        if self.items.contains(&item) {
+            return false;
+        }
+        self.items.push(item);
+        true
+    }
+
+    /// Returns whether the recipe is new to the codex, whichever way round
+    /// the ingredients go.
+    pub fn add_recipe(&mut self, a: Item, b: Item) -> bool {
+        if self.recipes.contains(&(a, b)) || self.recipes.contains(&(b, a)) {
+            return false;
+        }
+        self.recipes.push((a, b));
+        true
+    }
+}
+
+/// list everything in the codex, items first and then recipes
+fn show_codex(game: &Game, tcod: &mut Tcod) {
+    let name = |item: Item| make_item(item, 0, 0).name;
+    let mut entries: Vec<(String, Color)> = game
+        .codex
+        .items
+        .iter()
+        .map(|&item| (name(item), colors::WHITE))
+        .collect();
+    for &(a, b) in &game.codex.recipes {
+        if let Some(result) = recipe_for(a, b) {
+            let text = t(
+                "codex_recipe",
+                &[
+                    ("first", &name(a)),
+                    ("second", &name(b)),
+                    ("result", &name(result)),
+                ],
+            );
+            entries.push((text, colors::LIGHT_GREEN));
+        }
+    }
+    list_screen(&t("codex_title", &[]), &entries, tcod);
+}
+
+/// Combine two inventory items into a new one, using both of them up.
+/// Returns whether anything was made; if not, both items are left alone.
+fn craft(a: usize, b: usize, game: &mut Game) -> bool {
+    let recipe = match (game.inventory[a].item, game.inventory[b].item) {
+        (Some(x), Some(y)) if a != b => recipe_for(x, y),
+        _ => None,
+    };
+    let result = match recipe {
+        Some(result) => result,
+        None => {
+            game.log.add(
+                t(
+                    "craft_failed",
+                    &[
+                        ("first", &game.inventory[a].name),
+                        ("second", &game.inventory[b].name),
+                    ],
+                ),
+                colors::ORANGE,
+            );
+            return false;
+        }
+    };
+
+    if let (Some(x), Some(y)) = (game.inventory[a].item, game.inventory[b].item) {
+        if game.codex.add_recipe(x, y) {
+            game.log.add(t("codex_new_recipe", &[]), colors::LIGHT_CYAN);
+        }
+    }
+
+    // remove the later one first so the other index stays valid
+    for &index in [cmp::max(a, b), cmp::min(a, b)].iter() {
+        if game.inventory[index].equipment.is_some() {
+            game.unequip(index);
+        }
+        game.inventory.remove(index);
+    }
+    let item = make_item(result, 0, 0);
+    game.log
+        .add(t("crafted", &[("item", &item.name)]), colors::LIGHT_GREEN);
+    stash_item(item, game);
+    game.assign_object_ids();
+    true
+}
+
+/// Where a dropped item can go: the player's own tile if nothing is lying
+/// there, else the first free neighbouring tile. Items never go on top of
+/// furniture or other items.
+fn find_drop_spot(game: &Game) -> Option<(i32, i32)> {
+    let (player_x, player_y) = game.objects[PLAYER].pos();
+    let taken = |x: i32, y: i32| {
+        game.objects.iter().any(|object| {
+            object.covers(x, y) && (object.item.is_some() || object.has_tag(TAG_FURNITURE))
+        })
+    };
+
+    if !taken(player_x, player_y) {
+        return Some((player_x, player_y));
+    }
+    for dy in -1..2 {
+        for dx in -1..2 {
+            let (x, y) = (player_x + dx, player_y + dy);
+            if (dx, dy) != (0, 0)
+                && in_map(x, y)
+                && !is_blocked(x, y, &game.map, &game.objects)
+                && !taken(x, y)
+            {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+fn drop_item(inventory_id: usize, game: &mut Game) {
+    let (x, y) = match find_drop_spot(game) {
+        Some(spot) => spot,
+        None => {
+            game.log.add(
+                t(
+                    "drop_no_room",
+                    &[("item", &game.inventory[inventory_id].name)],
+                ),
+                colors::ORANGE,
+            );
+            return;
+        }
+    };
+    if game.inventory[inventory_id].equipment.is_some() {
+        game.unequip(inventory_id);
+    }
+    let mut item = game.inventory.remove(inventory_id);
+    item.set_pos(x, y);
+    game.log
+        .add(t("dropped", &[("item", &item.name)]), colors::YELLOW);
+    game.objects.push(item);
+}
+
+/// Drop `n` items off the stack at `inventory_id` as a stack of their own,
+/// leaving the rest in the inventory. Returns whether anything was dropped;
+/// asking for more than the stack holds drops nothing.
+fn split_stack(inventory_id: usize, n: u32, game: &mut Game) -> bool {
+    let count = game.inventory[inventory_id].count;
+    if n == 0 || n > count {
+        game.log.add(
+            t(
+                "split_too_many",
+                &[
+                    ("item", &game.inventory[inventory_id].name),
+                    ("count", &count),
+                ],
+            ),
+            colors::ORANGE,
+        );
+        return false;
+    }
+    if n == count {
+        drop_item(inventory_id, game);
+        return true;
+    }
+    let (x, y) = match find_drop_spot(game) {
+        Some(spot) => spot,
+        None => {
+            game.log.add(
+                t(
+                    "drop_no_room",
+                    &[("item", &game.inventory[inventory_id].name)],
+                ),
+                colors::ORANGE,
+            );
+            return false;
+        }
+    };
+    game.inventory[inventory_id].count -= n;
+    let mut pile = game.inventory[inventory_id].clone();
+    pile.id = 0;
+    pile.count = n;
+    pile.set_pos(x, y);
+    game.log.add(
+        t("dropped_some", &[("item", &pile.name), ("count", &n)]),
+        colors::YELLOW,
+    );
+    game.objects.push(pile);
+    game.assign_object_ids();
+    true
+}
+
+/// return the position of a tile left-clicked in player's FOV (optionally in a
+/// range), or (None,None) if right-clicked.
+///
+/// The movement keys move a cursor around too, starting from the player, and
+/// Enter picks the tile under it. Moving the mouse takes the cursor along.
+fn target_tile(tcod: &mut Tcod, game: &mut Game, max_range: Option<f32>) -> Option<(i32, i32)> {
+    let mut cursor = game.objects[PLAYER].pos();
+    loop {
+        // render the screen. this erases the inventory and shows the names of
+        // objects under the mouse.
+        tcod.root.flush();
+        let event = input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1);
+        let mut key = None;
+        let (mut clicked, mut cancelled) = (false, false);
+        match event {
+            Some(Event::Mouse(m)) => {
+                tcod.mouse = m;
+                // over the panel the mouse isn't pointing at any tile
+                if in_view(m.cx as i32, m.cy as i32, game.config.panel.y()) {
+                    cursor = tcod.transients.camera.to_map(m.cx as i32, m.cy as i32);
+                    clicked = m.lbutton_pressed;
+                    cancelled = m.rbutton_pressed;
+                }
+            }
+            Some(Event::Key(k)) => key = Some(k),
+            None => {}
+        }
+        let player = &game.objects[PLAYER];
+        let mut picked = None;
+        if clicked && valid_target(cursor, &tcod.transients.fov, player, max_range) {
+            picked = Some(cursor);
+        }
+        if let Some(k) = key {
+            let (moved, chosen) =
+                targeting_key(cursor, k.code, &tcod.transients.fov, player, max_range);
+            cursor = moved;
+            picked = picked.or(chosen);
+        }
+        render_all(tcod, game, false);
+
+        // show where the cursor is
+        let (screen_x, screen_y) = tcod.transients.camera.to_screen(cursor.0, cursor.1);
+        if in_view(screen_x, screen_y, game.config.panel.y()) {
+            tcod.root.set_char_background(
+                screen_x,
+                screen_y,
+                colors::LIGHT_YELLOW,
+                BackgroundFlag::Set,
+            );
+        }
+
+        if picked.is_some() {
+            return picked;
+        }
+
+        let escape = key.map_or(false, |k| k.code == KeyCode::Escape);
+        if cancelled || escape {
+            return None; // cancel if the player right-clicked or pressed Escape
+        }
+    }
+}
+
+/// One key press while targeting: a movement key moves the cursor, and Enter
+/// picks the tile under it if it's a valid target. Returns where the cursor is
+/// now and the tile picked, if any.
+fn targeting_key(
+    cursor: (i32, i32),
+    code: KeyCode,
+    fov: &FovMap,
+    player: &Object,
+    max_range: Option<f32>,
+) -> ((i32, i32), Option<(i32, i32)>) {
+    if let Some((dx, dy)) = key_step(code) {
+        return (move_cursor(cursor, dx, dy, fov), None);
+    }
+    let confirmed = code == KeyCode::Enter || code == KeyCode::NumPadEnter;
+    if confirmed && valid_target(cursor, fov, player, max_range) {
+        (cursor, Some(cursor))
+    } else {
+        (cursor, None)
+    }
+}
+
+/// whether a tile can be targeted: the player has to see it and, in case a
+/// range is specified, it has to be in that range
+fn valid_target((x, y): (i32, i32), fov: &FovMap, player: &Object, max_range: Option<f32>) -> bool {
+    let in_fov = in_map(x, y) && fov.is_in_fov(x, y);
+    in_fov && max_range.map_or(true, |range| player.distance(x, y) <= range)
+}
+
+/// Move the targeting cursor a step, unless that would take it somewhere the
+/// player can't see.
+fn move_cursor((x, y): (i32, i32), dx: i32, dy: i32, fov: &FovMap) -> (i32, i32) {
+    let (new_x, new_y) = (x + dx, y + dy);
+    if in_map(new_x, new_y) && fov.is_in_fov(new_x, new_y) {
+        (new_x, new_y)
+    } else {
+        (x, y)
+    }
+}
+
+/// The player's stats, for items that change them. A player without stats
+/// (say, after loading a damaged save) gets a warning instead of a crash.
+fn player_stats_mut(game: &mut Game) -> Option<&mut Stats> {
+    if game.objects[PLAYER].stats.is_none() {
+        game.log.add(t("player_no_stats", &[]), colors::RED);
+    }
+    game.objects[PLAYER].stats.as_mut()
+}
+
+/// Lower one of the player's needs, but not below zero.
+fn drain_player_need(need: Need, amount: i32, game: &mut Game) {
+    if let Some(stats) = player_stats_mut(game) {
+        let value = stats.need_mut(need);
+        *value = cmp::max(*value - amount, 0);
+    }
+}
+
+/// Raise the need `item` takes care of, up to its maximum. Returns false if
+/// the player has no needs to raise.
+fn restore_player_need(item: Item, game: &mut Game) -> bool {
+    let (need, amount) = match item.restores() {
+        Some(restores) => restores,
+        None => return false,
+    };
+    let maximum = game.objects[PLAYER].max_need(need);
+    match player_stats_mut(game) {
+        Some(stats) => {
+            let value = stats.need_mut(need);
+            *value = cmp::min(*value + amount, maximum);
+            true
+        }
+        None => false,
+    }
+}
+
+fn cast_heal(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    if !restore_player_need(Item::Heal, game) {
+        return UseResult::Cancelled;
+    }
+
+    // a little green cross floats up from the player
+    spawn_particle(
+        tcod,
+        game.objects[PLAYER].pos(),
+        (0.0, -0.25),
+        '+',
+        colors::LIGHT_GREEN,
+        8,
+    );
+
+    UseResult::UsedUp
+}
+
+fn cast_lightning(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    if !restore_player_need(Item::Lightning, game) {
+        return UseResult::Cancelled;
+    }
+
+    UseResult::UsedUp
+}
+
+fn cast_confuse(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    if !restore_player_need(Item::Confuse, game) {
+        return UseResult::Cancelled;
+    }
+
+    UseResult::UsedUp
+}
+
+fn cast_fireball(_inventory_id: usize, game: &mut Game, tcod: &mut Tcod) -> UseResult {
+    game.log.add(t("fireball_aim", &[]), colors::LIGHT_CYAN);
+    let target = match target_tile(tcod, game, Some(FIREBALL_RANGE)) {
+        Some(target) => target,
+        None => return UseResult::Cancelled,
+    };
+    game.log.add(t("fireball_explodes", &[]), colors::ORANGE);
+
+    // furniture that burns through goes up in flames too, setting off a blast
+    // of its own; every object is only caught once
+    let mut blasts = VecDeque::new();
+    blasts.push_back(target);
+    let mut burnt = HashSet::new();
+    while let Some(center) = blasts.pop_front() {
+        spawn_particle(tcod, center, (0.0, -0.25), '*', colors::ORANGE, 8);
+        for id in objects_in_radius(&game.objects, center, FIREBALL_RADIUS) {
+            if !burnt.insert(id) {
+                continue;
+            }
+            if burn_object(id, game) {
+                blasts.push_back(game.objects[id].pos());
+            }
+        }
+    }
+
+    UseResult::UsedUp
+}
+
+/// indices of every object with a tile within `radius` of `center`
+fn objects_in_radius(objects: &[Object], center: (i32, i32), radius: f32) -> Vec<usize> {
+    objects
+        .iter()
+        .enumerate()
+        .filter(|(_, object)| {
+            object.footprint().into_iter().any(|(x, y)| {
+                let (dx, dy) = (x - center.0, y - center.1);
+                ((dx.pow(2) + dy.pow(2)) as f32).sqrt() <= radius
+            })
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// Scorch an object caught in a fireball. Returns whether it was furniture
+/// that burnt through and so explodes in turn.
+fn burn_object(id: usize, game: &mut Game) -> bool {
+    let object = &mut game.objects[id];
+    if let Some(stats) = object.stats.as_mut() {
+        stats.comfort = cmp::max(stats.comfort - FIREBALL_COMFORT_LOSS, 0);
+        game.log.add(
+            t("fireball_singed", &[("name", &object.name)]),
+            colors::ORANGE,
+        );
+        if object.ai.is_some() {
+            change_relationship(id, RELATIONSHIP_SINGED, game);
+        }
+        return false;
+    }
+
+    let kind = match object.furniture {
+        // furniture that can't break doesn't burn either
+        Some(furniture) if furniture.kind.max_durability().is_some() && !furniture.is_broken() => {
+            furniture.kind
+        }
+        _ => return false,
+    };
+    if let Some(f) = object.furniture.as_mut() {
+        f.durability -= FIREBALL_FURNITURE_DAMAGE;
+    }
+    if object.furniture.map_or(false, |f| f.is_broken()) {
+        object.color = colors::DARK_GREY;
+        game.log.add(
+            t("fireball_furniture_burns", &[("furniture", &kind)]),
+            colors::ORANGE,
+        );
+        return true;
+    }
+    false
+}
+
+fn toggle_equipment(inventory_id: usize, game: &mut Game, _tcod: &mut Tcod) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        game.unequip(inventory_id);
+    } else {
+        // if the slot is already being used, dequip whatever is there first
+        if let Some(current) = game.equipped_in_slot(equipment.slot) {
+            game.unequip(current);
+        }
+        game.equip(inventory_id);
+    }
+    UseResult::UsedAndKept
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// An object that can be equipped, yielding bonuses.
+struct Equipment {
+    slot: Slot,
+    equipped: bool,
+    max_hp_bonus: i32,
+    defense_bonus: i32,
+    power_bonus: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Slot {
+    LeftHand,
+    RightHand,
+    Head,
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            Slot::LeftHand => "slot_left_hand",
+            Slot::RightHand => "slot_right_hand",
+            Slot::Head => "slot_head",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
+}
+
+fn create_room(room: Rect, map: &mut Map) {
+    // go through the tiles in the rectangle and make them passable
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            map[x as usize][y as usize] = Tile::empty();
+        }
+    }
+}
+
+/// Make sure the outermost ring of the map is wall, whatever got carved, so
+/// nothing ever walks or sees right up to the edge.
+fn seal_map_edges(map: &mut Map) {
+    for x in 0..MAP_WIDTH {
+        map[x as usize][0] = Tile::wall();
+        map[x as usize][(MAP_HEIGHT - 1) as usize] = Tile::wall();
+    }
+    for y in 0..MAP_HEIGHT {
+        map[0][y as usize] = Tile::wall();
+        map[(MAP_WIDTH - 1) as usize][y as usize] = Tile::wall();
+    }
+}
+
+/// Every tile that can be walked to from `start`, going diagonally too.
+fn flood_fill_reachable(map: &Map, start: (i32, i32)) -> Vec<Vec<bool>> {
+    let mut reachable = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    if is_wall(map, start.0, start.1) {
+        return reachable;
+    }
+    let search = search_grid(start, &[], |x, y| !is_wall(map, x, y));
+    for x in 0..MAP_WIDTH {
+        for y in 0..MAP_HEIGHT {
+            reachable[x as usize][y as usize] = search.reached(x, y);
+        }
+    }
+    reachable
+}
+
+/// The generators should join every room up, but in case one is left cut off,
+/// dig a tunnel from it to the closest room that can be reached from `start`.
+fn ensure_connected(map: &mut Map, rooms: &[Rect], start: (i32, i32)) {
+    let mut reachable = flood_fill_reachable(map, start);
+    for room in rooms {
+        let (x, y) = room.center();
+        if reachable[x as usize][y as usize] {
+            continue;
+        }
+        let nearest = rooms
+            .iter()
+            .map(|other| other.center())
+            .filter(|&(other_x, other_y)| reachable[other_x as usize][other_y as usize])
+            .min_by_key(|&(other_x, other_y)| (other_x - x).pow(2) + (other_y - y).pow(2));
+        if let Some((other_x, other_y)) = nearest {
+            create_h_tunnel(other_x, x, other_y, map);
+            create_v_tunnel(other_y, y, x, map);
+            reachable = flood_fill_reachable(map, start);
+        }
+    }
+}
+
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    // vertical tunnel
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        map[x as usize][y as usize] = Tile::empty();
+    }
+}
+
+fn make_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    difficulty: Difficulty,
+    biome: Biome,
+    params: &RoomParams,
+    spawners: &mut [Spawner],
+    rng: &mut GameRng,
+) -> (Map, Vec<Rect>) {
+    // fill map with "blocked" tiles
+    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+
+    // Player is the first element, remove everything else.
+    // NOTE: works only when the player is the first object!
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+    objects.truncate(1);
+
+    let rooms = match params.style {
+        MapStyle::Scattered => {
+            make_map_scattered(&mut map, objects, level, difficulty, biome, params, rng)
+        }
+        MapStyle::Bsp => make_map_bsp(&mut map, objects, level, difficulty, biome, params, rng),
+    };
+
+    ensure_connected(&mut map, &rooms, objects[PLAYER].pos());
+
+    // whatever's been hooked in gets to add to each room, after the built-in spawns
+    for spawner in spawners.iter_mut() {
+        for &room in &rooms {
+            spawner(room, &map, level, objects);
+        }
+    }
+    seal_map_edges(&mut map);
+
+    // put doors in some of the gaps the tunnels left in the room walls
+    for room in &rooms {
+        place_doors(*room, &map, objects, rng);
+    }
+
+    // the water valve for the whole level lives in the first room
+    place_valve(rooms[0], &map, objects, rng);
+
+    // create stairs at the center of the last room
+    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+    let mut stairs = Object::new(
+        last_room_x,
+        last_room_y,
+        '<',
+        "stairs",
+        colors::WHITE,
+        false,
+    );
+    stairs.always_visible = true;
+    stairs.render_layer = RenderLayer::Floor;
+    stairs.add_tag(TAG_STAIRS);
+    objects.push(stairs);
+
+    (map, rooms)
+}
+
+/// Place rooms of random sizes at random spots, dropping any that overlap an
+/// earlier one, and join each to the one before it with a tunnel.
+fn make_map_scattered(
+    map: &mut Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    difficulty: Difficulty,
+    biome: Biome,
+    params: &RoomParams,
+    rng: &mut GameRng,
+) -> Vec<Rect> {
+    let mut rooms = vec![];
+
+    for _ in 0..params.max_rooms {
+        // random width and height
+        let w = rng.gen_range(params.min_size..params.max_size + 1);
+        let h = rng.gen_range(params.min_size..params.max_size + 1);
+        // random position without going out of the boundaries of the map
+        let x = rng.gen_range(0..MAP_WIDTH - w);
+        let y = rng.gen_range(0..MAP_HEIGHT - h);
+
+        let new_room = Rect::new(x, y, w, h);
+
+        // run through the other rooms and see if they intersect with this one
+        let failed = rooms
+            .iter()
+            .any(|other_room| new_room.intersects_with(other_room));
+
+        if !failed {
+            // this means there are no intersections, so this room is valid
+
+            // "paint" it to the map's tiles
+            create_room(new_room, map);
+
+            // add some content to this room
+            place_objects(new_room, map, objects, level, difficulty, biome, rng);
+
+            // center coordinates of the new room, will be useful later
+            let (new_x, new_y) = new_room.center();
+
+            if rooms.is_empty() {
+                // this is the first room, where the player starts at
+                objects[PLAYER].set_pos(new_x, new_y);
+            } else {
+                // all rooms after the first:
+                // connect it to the previous room with a tunnel
+
+                // center coordinates of the previous room
+                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
+
+                // toss a coin (random bool value -- either true or false)
+                if rng.gen() {
+                    // first move horizontally, then vertically
+                    create_h_tunnel(prev_x, new_x, prev_y, map);
+                    create_v_tunnel(prev_y, new_y, new_x, map);
+                } else {
+                    // first move vertically, then horizontally
+                    create_v_tunnel(prev_y, new_y, prev_x, map);
+                    create_h_tunnel(prev_x, new_x, new_y, map);
+                }
+            }
+
+            // finally, append the new room to the list
+            rooms.push(new_room);
+        }
+    }
+
+    rooms
+}
+
+/// Split the map in two, and each half in two again, until the pieces are
+/// about room sized, then put one room in each piece. Rooms are joined to the
+/// room next to them in the split, so the house comes out as tidy clusters
+/// rather than rooms strewn about.
+fn make_map_bsp(
+    map: &mut Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    difficulty: Difficulty,
+    biome: Biome,
+    params: &RoomParams,
+    rng: &mut GameRng,
+) -> Vec<Rect> {
+    let mut rooms = vec![];
+    let whole = Rect::new(0, 0, MAP_WIDTH - 1, MAP_HEIGHT - 1);
+    bsp_split(whole, params, map, &mut rooms, rng);
+
+    for &room in &rooms {
+        place_objects(room, map, objects, level, difficulty, biome, rng);
+    }
+    let (start_x, start_y) = rooms[0].center();
+    objects[PLAYER].set_pos(start_x, start_y);
+    rooms
+}
+
+/// Carve out the rooms for one piece of the map and connect its two halves.
+/// Returns the center of one of its rooms, for the caller to connect to.
+fn bsp_split(
+    area: Rect,
+    params: &RoomParams,
+    map: &mut Map,
+    rooms: &mut Vec<Rect>,
+    rng: &mut GameRng,
+) -> (i32, i32) {
+    let (w, h) = (area.x2 - area.x1, area.y2 - area.y1);
+    // each half has to be able to hold the smallest room
+    let smallest = params.min_size + 1;
+    let horizontal = w >= h;
+    let length = if horizontal { w } else { h };
+
+    if length <= 2 * params.max_size || length < 2 * smallest {
+        // small enough: a room somewhere in this piece
+        let room_w = rng.gen_range(params.min_size..cmp::min(params.max_size, w - 1) + 1);
+        let room_h = rng.gen_range(params.min_size..cmp::min(params.max_size, h - 1) + 1);
+        let x = rng.gen_range(area.x1..area.x2 - room_w);
+        let y = rng.gen_range(area.y1..area.y2 - room_h);
+        let room = Rect::new(x, y, room_w, room_h);
+        create_room(room, map);
+        rooms.push(room);
+        return room.center();
+    }
+
+    let cut = rng.gen_range(smallest..length - smallest + 1);
+    let (first, second) = if horizontal {
+        (
+            Rect::new(area.x1, area.y1, cut, h),
+            Rect::new(area.x1 + cut, area.y1, w - cut, h),
+        )
+    } else {
+        (
+            Rect::new(area.x1, area.y1, w, cut),
+            Rect::new(area.x1, area.y1 + cut, w, h - cut),
+        )
+    };
+    let (x1, y1) = bsp_split(first, params, map, rooms, rng);
+    let (x2, y2) = bsp_split(second, params, map, rooms, rng);
+    if rng.gen() {
+        create_h_tunnel(x1, x2, y1, map);
+        create_v_tunnel(y1, y2, x2, map);
+    } else {
+        create_v_tunnel(y1, y2, x1, map);
+        create_h_tunnel(x1, x2, y2, map);
+    }
+    (x1, y1)
+}
+
+/// whether the tile blocks movement, treating anything off the map as a wall
+fn is_wall(map: &Map, x: i32, y: i32) -> bool {
+    !in_map(x, y) || map[x as usize][y as usize].blocked
+}
+
+fn place_doors(room: Rect, map: &Map, objects: &mut Vec<Object>, rng: &mut GameRng) {
+    // walk the wall ring around the room looking for openings
+    let mut ring = vec![];
+    for x in room.x1..(room.x2 + 1) {
+        ring.push((x, room.y1));
+        ring.push((x, room.y2));
+    }
+    for y in (room.y1 + 1)..room.y2 {
+        ring.push((room.x1, y));
+        ring.push((room.x2, y));
+    }
+
+    for (x, y) in ring {
+        // a doorway is a gap with wall on both sides of it
+        let gap = !is_wall(map, x, y)
+            && ((is_wall(map, x - 1, y) && is_wall(map, x + 1, y))
+                || (is_wall(map, x, y - 1) && is_wall(map, x, y + 1)));
+        let taken = objects.iter().any(|object| object.pos() == (x, y));
+        if gap && !taken && rng.gen() {
+            let mut door = Object::new(x, y, '+', "door", colors::DARKER_ORANGE, true);
+            door.door = Some(Door { open: false });
+            door.always_visible = true;
+            door.render_layer = RenderLayer::Furniture;
+            door.add_tag(TAG_INTERACTIVE);
+            objects.push(door);
+        }
+    }
+}
+
+fn place_valve(room: Rect, map: &Map, objects: &mut Vec<Object>, rng: &mut GameRng) {
+    // keep trying random spots until one is free; give up after a while in a
+    // very crowded room
+    for _ in 0..100 {
+        let x = rng.gen_range(room.x1 + 1..room.x2);
+        let y = rng.gen_range(room.y1 + 1..room.y2);
+        if !is_blocked(x, y, map, objects) && objects[PLAYER].pos() != (x, y) {
+            objects.push(make_furniture(FurnitureKind::Valve, x, y));
+            return;
+        }
+    }
+}
+
+struct Transition {
+    level: u32,
+    value: u32,
+}
+
+/// Returns a value that depends on level. the table specifies what
+/// value occurs after each level, default is 0.
+fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .rev()
+        .find(|transition| level >= transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
+/// Furnish a room and put NPCs and items in it. Everything is drawn from `rng`
+/// and nothing else, so the same seed, room and map always spawn the same
+/// objects in the same places.
+fn place_objects(
+    room: Rect,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    difficulty: Difficulty,
+    biome: Biome,
+    rng: &mut GameRng,
+) {
+    use rand::distributions::{Distribution, WeightedIndex};
+
+    // every room gets a piece or two of furniture, which kinds depends on the biome
+    let num_furniture = rng.gen_range(1..3);
+    let furniture_choice = WeightedIndex::new(
+        FurnitureKind::PLACEABLE
+            .iter()
+            .map(|&kind| biome.furniture_weight(kind)),
+    )
+    .unwrap();
+
+    for _ in 0..num_furniture {
+        // choose random spot for this piece of furniture
+        let kind = FurnitureKind::PLACEABLE[furniture_choice.sample(rng)];
+        let x = rng.gen_range(room.x1 + 1..room.x2);
+        let y = rng.gen_range(room.y1 + 1..room.y2);
+        let furniture = make_furniture(kind, x, y);
+
+        // only place it if its whole footprint is free floor inside the room,
+        // and keep the center free since that's where the player or the
+        // stairs may end up
+        let fits = furniture.footprint().into_iter().all(|(x, y)| {
+            x < room.x2 && y < room.y2 && !is_blocked(x, y, map, objects) && (x, y) != room.center()
+        });
+        if fits {
+            objects.push(furniture);
+        }
+    }
+
+    // maximum number of NPCs per room
+    let max_npcs = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 1 },
+            Transition { level: 4, value: 2 },
+        ],
+        level,
+    );
+
+    // choose random number of NPCs
+    let num_npcs = rng.gen_range(0..max_npcs + 1);
+
+    for _ in 0..num_npcs {
+        // choose random spot for this NPC
+        let x = rng.gen_range(room.x1 + 1..room.x2);
+        let y = rng.gen_range(room.y1 + 1..room.y2);
+
+        // only place it if the tile is not blocked, keeping the center free
+        if !is_blocked(x, y, map, objects) && (x, y) != room.center() {
+            let name = NPC_NAMES[rng.gen_range(0..NPC_NAMES.len())];
+            let mut npc = Object::new(x, y, '@', name, colors::LIGHT_BLUE, true);
+            npc.alive = true;
+            npc.stats = Some(Stats {
+                base_max_all_stats: 100,
+                hunger: 100,
+                comfort: 100,
+                hygiene: 100,
+                bladder: 100,
+                energy: 100,
+                fun: 100,
+                social: 100,
+                room: 100,
+                on_death: DeathCallback::NPC,
+            });
+            npc.ai = Some(Ai::Basic);
+            npc.add_tag(TAG_INTERACTIVE);
+            objects.push(npc);
+        }
+    }
+
+    // maximum number of items per room, scaled by difficulty
+    let max_items = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 1 },
+            Transition { level: 4, value: 2 },
+        ],
+        level,
+    );
+    let max_items = (max_items as f64 * difficulty.spawn_multiplier()).ceil() as u32;
+
+    // item random table
+    let item_chances = &mut [
+        (Item::Heal, 35), // healing potion always shows up, even if all other items have 0 chance
+        (
+            Item::Lightning,
+            from_dungeon_level(
+                &[Transition {
+                    level: 4,
+                    value: 25,
+                }],
+                level,
+            ),
+        ),
+        (
+            Item::Fireball,
+            from_dungeon_level(
+                &[Transition {
+                    level: 6,
+                    value: 25,
+                }],
+                level,
+            ),
+        ),
+        (
+            Item::Confuse,
+            from_dungeon_level(
+                &[Transition {
+                    level: 2,
+                    value: 10,
+                }],
+                level,
+            ),
+        ),
+        (
+            Item::Sword,
+            from_dungeon_level(&[Transition { level: 4, value: 5 }], level),
+        ),
+        (
+            Item::Shield,
+            from_dungeon_level(
+                &[Transition {
+                    level: 8,
+                    value: 15,
+                }],
+                level,
+            ),
+        ),
+    ];
+    let item_choice = WeightedIndex::new(
+        item_chances
+            .iter()
+            .map(|&(item, chance)| chance * biome.item_weight(item)),
+    )
+    .unwrap();
+
+    // choose random number of items
+    let num_items = rng.gen_range(0..max_items + 1);
+
+    for _ in 0..num_items {
+        // choose random spot for this item
+        let x = rng.gen_range(room.x1 + 1..room.x2);
+        let y = rng.gen_range(room.y1 + 1..room.y2);
+
+        // only place it if the tile is not blocked
+        if !is_blocked(x, y, map, objects) {
+            let mut item = make_item(item_chances[item_choice.sample(rng)].0, x, y);
+            item.always_visible = true;
+            objects.push(item);
+        }
+    }
+}
+
+/// The item-def table: build a fresh object for the given kind of item.
+fn make_item(item: Item, x: i32, y: i32) -> Object {
+    match item {
+        Item::Heal => {
+            // create a healing potion
+            let mut object = Object::new(x, y, '!', "healing potion", colors::VIOLET, false);
+            object.item = Some(Item::Heal);
+            object
+        }
+        Item::Lightning => {
+            // create a lightning bolt scroll
+            let mut object = Object::new(
+                x,
+                y,
+                '#',
+                "scroll of lightning bolt",
+                colors::LIGHT_YELLOW,
+                false,
+            );
+            object.item = Some(Item::Lightning);
+            object
+        }
+        Item::Fireball => {
+            // create a fireball scroll
+            let mut object =
+                Object::new(x, y, '#', "scroll of fireball", colors::LIGHT_YELLOW, false);
+            object.item = Some(Item::Fireball);
+            object
+        }
+        Item::Confuse => {
+            // create a confuse scroll
+            let mut object = Object::new(
+                x,
+                y,
+                '#',
+                "scroll of confusion",
+                colors::LIGHT_YELLOW,
+                false,
+            );
+            object.item = Some(Item::Confuse);
+            object
+        }
+        Item::Sword => {
+            // create a sword
+            let mut object = Object::new(x, y, '/', "sword", colors::SKY, false);
+            object.item = Some(Item::Sword);
+            object.equipment = Some(Equipment {
+                equipped: false,
+                slot: Slot::RightHand,
+                max_hp_bonus: 0,
+                defense_bonus: 0,
+                power_bonus: 3,
+            });
+            object
+        }
+        Item::Shield => {
+            // create a shield
+            let mut object = Object::new(x, y, '[', "shield", colors::DARKER_ORANGE, false);
+            object.item = Some(Item::Shield);
+            object.equipment = Some(Equipment {
+                equipped: false,
+                slot: Slot::LeftHand,
+                max_hp_bonus: 0,
+                defense_bonus: 1,
+                power_bonus: 0,
+            });
+            object
+        }
+        Item::Dagger => {
+            // create a dagger
+            let mut object = Object::new(x, y, '-', "dagger", colors::SKY, false);
+            object.item = Some(Item::Dagger);
+            object.equipment = Some(Equipment {
+                equipped: false,
+                slot: Slot::LeftHand,
+                max_hp_bonus: 0,
+                defense_bonus: 0,
+                power_bonus: 2,
+            });
+            object
+        }
+    }
+}
+
+/// With `stairs_need_threshold` set, the stairs are off limits until every
+/// need is above it; the player is told which one is holding them back.
+fn ready_to_descend(game: &mut Game) -> bool {
+    let threshold = match game.config.stairs_need_threshold {
+        Some(threshold) => threshold,
+        None => return true,
+    };
+    let stats = match game.objects[PLAYER].stats {
+        Some(stats) => stats,
+        None => return true,
+    };
+    let lowest = Need::ALL
+        .iter()
+        .copied()
+        .filter(|&need| stats.need(need) <= threshold)
+        .min_by_key(|&need| stats.need(need));
+    match lowest {
+        Some(need) => {
+            game.log
+                .add(t("stairs_needs_low", &[("need", &need)]), colors::ORANGE);
+            false
+        }
+        None => true,
+    }
+}
+
+/// Advance to the next level
+fn next_level(tcod: &mut Tcod, game: &mut Game) {
+    game.log.add(t("descend", &[]), colors::RED);
+    game.dungeon_level += 1;
+    // the snapshots belong to the old map
+    game.undo.clear();
+    game.biome = Biome::for_level(game.dungeon_level, &mut game.rng);
+    // the pet comes along; everything else stays behind
+    let pet = game
+        .objects
+        .iter()
+        .position(|object| object.ai == Some(Ai::Follow))
+        .and_then(|index| game.take_object(index));
+    let (map, rooms) = make_map(
+        &mut game.objects,
+        game.dungeon_level,
+        game.difficulty,
+        game.biome,
+        &game.config.rooms,
+        &mut game.spawners,
+        &mut game.rng,
+    );
+    game.map = map;
+    game.rooms = rooms;
+    if let Some(pet) = pet {
+        place_pet(pet, game);
+    }
+    game.assign_object_ids();
+    initialise_fov(&game.map, tcod);
+    diag(
+        "next_level",
+        &format!("level={} biome={:?}", game.dungeon_level, game.biome),
+    );
+    game.log.add(
+        t("biome_arrive", &[("biome", &t(game.biome.name_key(), &[]))]),
+        colors::LIGHT_GREY,
+    );
+    game.emit(GameEvent::LevelDescended(game.dungeon_level));
+}
+
+/// One of the need bars in the panel.
+struct StatBar {
+    need: Need,
+    color: Color,
+    back_color: Color,
+}
+
+/// The bars shown in the panel, in order: they fill columns of
+/// `BARS_PER_COLUMN` from the top down, with the columns lined up against the
+/// right edge of the screen.
+const STAT_BARS: &[StatBar] = &[
+    StatBar {
+        need: Need::Hunger,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Comfort,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Hygiene,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Bladder,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Energy,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Fun,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Social,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+    StatBar {
+        need: Need::Room,
+        color: colors::LIGHT_GREEN,
+        back_color: colors::DARKER_GREEN,
+    },
+];
+
+/// where the `index`th of `count` stat bars goes in the panel
+fn stat_bar_position(index: usize, count: usize) -> (i32, i32) {
+    let per_column = BARS_PER_COLUMN as usize;
+    let columns = ((count + per_column - 1) / per_column) as i32;
+    let column = (index / per_column) as i32;
+    let row = (index % per_column) as i32;
+
+    // count the columns from the right edge of the screen
+    let from_right = columns - column;
+    let x = SCREEN_WIDTH - BAR_WIDTH * from_right - BAR_LEFT_PADDING * (from_right - 1);
+    (x, BAR_TOP_PADDING + 1 + row)
+}
+
+fn render_bar(
+    panel: &mut Offscreen,
+    x: i32,
+    y: i32,
+    total_width: i32,
+    name: &str,
+    value: i32,
+    maximum: i32,
+    bar_color: Color,
+    back_color: Color,
+) {
+    // render a bar (HP, experience, etc). First calculate the width of the bar
+    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+
+    // render the background first
+    panel.set_default_background(back_color);
+    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+
+    // now render the bar on top
+    panel.set_default_background(bar_color);
+    if bar_width > 0 {
+        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    }
+
+    // finally, some centered text with the values
+    panel.set_default_foreground(colors::BLACK);
+    panel.print_ex(
+        x,
+        y,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        &format!("{: >7}: {}/{}", name, value, maximum),
+    );
+}
+
+/// whether a screen cell shows the map rather than the panel below it, which
+/// starts at `view_height`
+fn in_view(screen_x: i32, screen_y: i32, view_height: i32) -> bool {
+    (0..VIEW_WIDTH).contains(&screen_x) && (0..view_height).contains(&screen_y)
+}
+
+/// return a string with the names of all objects under the mouse
+fn get_names_under_mouse(
+    mouse: Mouse,
+    camera: Camera,
+    view_height: i32,
+    objects: &[Object],
+    fov_map: &FovMap,
+) -> String {
+    // over the panel the camera would point past the bottom of the view
+    if !in_view(mouse.cx as i32, mouse.cy as i32, view_height) {
+        return String::new();
+    }
+    let (x, y) = camera.to_map(mouse.cx as i32, mouse.cy as i32);
+    if !in_map(x, y) {
+        return String::new();
+    }
+
+    // create a list with the names of all objects at the mouse's coordinates and in FOV
+    let mut under_mouse = objects
+        .iter()
+        .filter(|obj| obj.covers(x, y) && fov_map.is_in_fov(x, y))
+        .collect::<Vec<_>>();
+    under_mouse.sort_by(|o1, o2| stable_order(o1, o2));
+    let names = under_mouse
+        .iter()
+        .map(|obj| obj.name.clone())
+        .collect::<Vec<_>>();
+
+    names.join(", ") // join the names, separated by commas
+}
+
+/// Draw a frame, one pass at a time, each on top of the one before.
+fn render_all(tcod: &mut Tcod, game: &mut Game, fov_recompute: bool) {
+    // a flickering torch only changes the colors, the FOV stays as it is
+    let flicker = if game.config.torch_flicker {
+        Some(torch_flicker(tcod.transients.frame))
+    } else {
+        None
+    };
+    tcod.transients.frame = tcod.transients.frame.wrapping_add(1);
+
+    render_tiles(tcod, game, fov_recompute, flicker);
+    render_objects(tcod, game);
+    render_panel(tcod, game);
+    render_overlays(tcod, game);
+}
+
+/// The map's tiles, lit by the torch, exploring whatever is in view.
+fn render_tiles(tcod: &mut Tcod, game: &mut Game, fov_recompute: bool, flicker: Option<f32>) {
+    if fov_recompute {
+        // recompute FOV if needed (the player moved or something)
+        let player = &game.objects[PLAYER];
+        tcod.transients.fov.compute_fov(
+            player.x,
+            player.y,
+            TORCH_RADIUS,
+            FOV_LIGHT_WALLS,
+            FOV_ALGO,
+        );
+    }
+
+    // debugging aid: light up the whole map, without exploring any of it
+    let reveal_all = tcod.transients.reveal_all;
+    if fov_recompute || flicker.is_some() || reveal_all {
+        paint_tiles(
+            &mut tcod.con,
+            &tcod.transients.fov,
+            game,
+            tcod.transients.lit_from,
+            flicker,
+            reveal_all,
+        );
+        tcod.transients.lit_from = Some(game.objects[PLAYER].pos());
+    }
+}
+
+/// Set the background of the map tiles on `con` for the player where they
+/// are now, exploring the ones in view. With the position the map was last
+/// lit from in `lit_from`, only the tiles the torch lit then or lights now are
+/// painted: nothing else can have changed.
+fn paint_tiles(
+    con: &mut Offscreen,
+    fov: &FovMap,
+    game: &mut Game,
+    lit_from: Option<(i32, i32)>,
+    flicker: Option<f32>,
+    reveal_all: bool,
+) {
+    let high_contrast = game.config.high_contrast;
+    let (player_x, player_y) = game.objects[PLAYER].pos();
+    // only the tiles the torch lit before or lights now can change, unless
+    // every lit tile changes color or the screen was wiped
+    let (x_range, y_range) = match lit_from {
+        Some((lit_x, lit_y)) if flicker.is_none() && !reveal_all => (
+            cmp::max(cmp::min(lit_x, player_x) - TORCH_RADIUS, 0)
+                ..cmp::min(cmp::max(lit_x, player_x) + TORCH_RADIUS + 1, MAP_WIDTH),
+            cmp::max(cmp::min(lit_y, player_y) - TORCH_RADIUS, 0)
+                ..cmp::min(cmp::max(lit_y, player_y) + TORCH_RADIUS + 1, MAP_HEIGHT),
+        ),
+        _ => (0..MAP_WIDTH, 0..MAP_HEIGHT),
+    };
+    // go through the tiles, and set their background color
+    for y in y_range {
+        for x in x_range.clone() {
+            let in_fov = fov.is_in_fov(x, y);
+            let visible = in_fov || reveal_all;
+            let wall = game.map[x as usize][y as usize].block_sight;
+            let (dark, light) = tile_colors(wall, high_contrast, game.biome);
+            let color = if visible {
+                // brightest next to the torch, unless that would make the
+                // map harder to read
+                let mut brightness = if in_fov && !high_contrast {
+                    torch_light(x - player_x, y - player_y)
+                } else {
+                    1.0
+                };
+                // dimmed a little more while the torch flickers
+                if let Some(flicker) = flicker {
+                    brightness *= flicker;
+                }
+                lerp_color(dark, light, brightness)
+            } else {
+                // outside of field of view
+                dark
+            };
+
+            let explored = &mut game.map[x as usize][y as usize].explored;
+            if in_fov {
+                // since it's visible, explore it
+                *explored = true;
+            }
+            if *explored || reveal_all {
+                // show explored tiles only (any visible tile is explored already)
+                con.set_char_background(x, y, color, BackgroundFlag::Set);
+                if high_contrast {
+                    // walls are solid blocks rather than just a colored background
+                    con.put_char_ex(x, y, tile_char(wall, true), color, color);
+                }
+            }
+        }
+    }
+}
+
+/// Draw the objects the player can see (or all of them, with `reveal_all`)
+/// onto `con`, actors on top of everything else.
+fn paint_objects(con: &mut Offscreen, fov: &FovMap, game: &Game, reveal_all: bool, frame: u32) {
+    let high_contrast = game.config.high_contrast;
+    let mut to_draw: Vec<_> = game
+        .objects
+        .iter()
+        .filter(|o| {
+            reveal_all
+                || o.footprint().into_iter().any(|(x, y)| {
+                    fov.is_in_fov(x, y)
+                        || (o.always_visible && game.map[x as usize][y as usize].explored)
+                })
+        })
+        .collect();
+    // sort by render layer so that actors are drawn on top of everything else
+    to_draw.sort_by(|o1, o2| draw_order(o1, o2));
+    // draw the objects in the list
+    for object in &to_draw {
+        object.draw(con, high_contrast, frame);
+    }
+}
+
+/// The objects and particles on top of the tiles, and then the part of the
+/// map the camera looks at onto the screen.
+fn render_objects(tcod: &mut Tcod, game: &mut Game) {
+    paint_objects(
+        &mut tcod.con,
+        &tcod.transients.fov,
+        game,
+        tcod.transients.reveal_all,
+        tcod.transients.frame,
+    );
+
+    // draw the particles on top of everything else on the map
+    for particle in &tcod.transients.particles {
+        let (x, y) = particle.pos();
+        if in_map(x, y) {
+            tcod.con.set_default_foreground(particle.color);
+            tcod.con.put_char(x, y, particle.char, BackgroundFlag::None);
+        }
+    }
+
+    // blit the part of "con" the camera looks at to the root console,
+    // jittered while shaking
+    let panel = game.config.panel;
+    let (dead_zone_width, dead_zone_height) = game.config.camera_dead_zone;
+    tcod.transients.camera.follow(
+        game.objects[PLAYER].pos(),
+        panel.y(),
+        dead_zone_width,
+        dead_zone_height,
+    );
+    let magnitude = shake_offset(tcod.transients.effects.shake_frames);
+    let offset = if magnitude > 0 {
+        // purely cosmetic, so it stays off the game's seeded RNG
+        let mut rng = rand::thread_rng();
+        tcod.root.clear();
+        (
+            rng.gen_range(-magnitude..magnitude + 1),
+            rng.gen_range(-magnitude..magnitude + 1),
+        )
+    } else {
+        (0, 0)
+    };
+    blit(
+        &mut tcod.con,
+        (tcod.transients.camera.x, tcod.transients.camera.y),
+        (
+            cmp::min(MAP_WIDTH, VIEW_WIDTH),
+            cmp::min(MAP_HEIGHT, panel.y()),
+        ),
+        &mut tcod.root,
+        offset,
+        1.0,
+        1.0,
+    );
+}
+
+/// Print the newest messages of the log that fit in the panel, oldest at the
+/// top. Returns how many were printed.
+fn paint_log(con: &mut Offscreen, log: &Messages, panel: PanelParams) -> usize {
+    let mut y = panel.msg_lines();
+    let mut printed = 0;
+    for &(ref msg, color) in log.iter().rev() {
+        let msg_height = con.get_height_rect(MSG_X, y, panel.msg_width, 0, msg);
+        y -= msg_height;
+        if y < 0 {
+            break;
+        }
+        con.set_default_foreground(color);
+        con.print_rect(MSG_X, y, panel.msg_width, 0, msg);
+        printed += 1;
+    }
+    printed
+}
+
+/// The panel at the bottom: the message log, the needs, the clock and the
+/// names of whatever is under the mouse.
+fn render_panel(tcod: &mut Tcod, game: &mut Game) {
+    let panel = game.config.panel;
+
+    // prepare to render the GUI panel
+    tcod.panel.set_default_background(colors::BLACK);
+    tcod.panel.clear();
+
+    paint_log(&mut tcod.panel, &game.log, panel);
+
+    // show the player's needs
+    let player = &game.objects[PLAYER];
+    for (index, bar) in STAT_BARS.iter().enumerate() {
+        let (x, y) = stat_bar_position(index, STAT_BARS.len());
+        render_bar(
+            &mut tcod.panel,
+            x,
+            y,
+            BAR_WIDTH,
+            &bar.need.to_string(),
+            player.stats.map_or(0, |p| p.need(bar.need)),
+            player.max_need(bar.need),
+            bar.color,
+            bar.back_color,
+        );
+    }
+
+    // the player's mood at a glance
+    if let Some(stats) = game.objects[PLAYER].stats {
+        let mood = compute_mood(&stats);
+        tcod.panel.set_default_foreground(mood.color());
+        tcod.panel
+            .put_char(1, BAR_TOP_PADDING + 1, mood.glyph(), BackgroundFlag::None);
+        tcod.panel.print_ex(
+            3,
+            BAR_TOP_PADDING + 1,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            t("mood_label", &[("mood", &mood)]),
+        );
+    }
+    tcod.panel.set_default_foreground(colors::LIGHT_GREY);
+    tcod.panel.print_ex(
+        1,
+        BAR_TOP_PADDING + 3,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        format_clock(game.turn),
+    );
+    tcod.panel.set_default_foreground(game.weather.color());
+    tcod.panel.print_ex(
+        1,
+        BAR_TOP_PADDING + 4,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        t(game.weather.name_key(), &[]),
+    );
+
+    // display names of objects under the mouse
+    tcod.panel.set_default_foreground(colors::LIGHT_GREY);
+    tcod.panel.print_ex(
+        1,
+        0,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        get_names_under_mouse(
+            tcod.mouse,
+            tcod.transients.camera,
+            panel.y(),
+            &game.objects,
+            &tcod.transients.fov,
+        ),
+    );
+
+    // blit the contents of `panel` to the root console
+    blit(
+        &tcod.panel,
+        (0, 0),
+        (SCREEN_WIDTH, panel.height),
+        &mut tcod.root,
+        (0, panel.y()),
+        1.0,
+        1.0,
+    );
+}
+
+/// Screen-wide effects over everything else.
+fn render_overlays(tcod: &mut Tcod, _game: &mut Game) {
+    // wash the whole screen in red, fading out over the flash
+    if tcod.transients.effects.flash_frames > 0 {
+        let mut flash = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        flash.set_default_background(colors::RED);
+        flash.clear();
+        let alpha = 0.6 * tcod.transients.effects.flash_frames as f32 / FLASH_FRAMES as f32;
+        blit(
+            &flash,
+            (0, 0),
+            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            &mut tcod.root,
+            (0, 0),
+            0.0,
+            alpha,
+        );
+    }
+    tcod.transients.effects.tick();
+}
+
+/// How brightly the torch lights a tile `dx`, `dy` away from it: fully right
+/// next to it, fading to `1 - TORCH_FALLOFF` at the edge of `TORCH_RADIUS`.
+fn torch_light(dx: i32, dy: i32) -> f32 {
+    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+    1.0 - TORCH_FALLOFF * (distance / TORCH_RADIUS as f32).min(1.0)
+}
+
+/// the color `t` of the way from `dark` to `light`, with `t` kept between 0 and 1
+fn lerp_color(dark: Color, light: Color, t: f32) -> Color {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color {
+        r: channel(dark.r, light.r),
+        g: channel(dark.g, light.g),
+        b: channel(dark.b, light.b),
+    }
+}
+
+/// which of `count` animation frames to show on a screen frame; each one
+/// stays up for `ANIMATION_FRAME_TICKS` and then it starts over
+fn animation_frame(frame: u32, count: usize) -> usize {
+    if count == 0 {
+        return 0;
+    }
+    (frame / ANIMATION_FRAME_TICKS) as usize % count
+}
+
+/// How brightly the torch burns on a frame, between `1 - TORCH_FLICKER` and 1.
+/// Two sine waves out of step with each other make a cheap, irregular flicker.
+fn torch_flicker(frame: u32) -> f32 {
+    let t = (frame % 10_000) as f32;
+    let wave = ((t * 0.7).sin() + (t * 1.9 + 1.3).sin() * 0.5) / 1.5; // -1 to 1
+    1.0 - TORCH_FLICKER * (wave + 1.0) / 2.0
+}
+
+/// how many rows of a sparkline column a value fills
+fn sparkline_height(value: i32, maximum: i32, height: i32) -> i32 {
+    if maximum <= 0 {
+        return 0;
+    }
+    let filled = (value * height + maximum - 1) / maximum;
+    cmp::max(0, cmp::min(filled, height))
+}
+
+/// show a small bar graph of how each need changed over the recent turns
+fn render_history_graph(game: &Game, tcod: &mut Tcod) {
+    modal(tcod, |tcod| {
+        let label_width = 10;
+        let width = label_width + HISTORY_CAPACITY as i32;
+        let height = 2 + Need::ALL.len() as i32 * (HISTORY_GRAPH_HEIGHT + 1);
+        let mut window = Offscreen::new(width, height);
+
+        window.set_default_foreground(colors::WHITE);
+        let title = if game.need_history.samples.is_empty() {
+            t("history_empty", &[])
+        } else {
+            let turns = game.need_history.samples.len() as u32 * HISTORY_SAMPLE_INTERVAL;
+            t("history_title", &[("turns", &turns)])
+        };
+        window.print_ex(0, 0, BackgroundFlag::None, TextAlignment::Left, title);
+
+        for (row, &need) in Need::ALL.iter().enumerate() {
+            let bottom = 1 + (row as i32 + 1) * (HISTORY_GRAPH_HEIGHT + 1);
+            let maximum = game.objects[PLAYER].max_need(need);
+
+            window.set_default_foreground(colors::WHITE);
+            window.print_ex(
+                0,
+                bottom,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                need.to_string(),
+            );
+
+            for (column, sample) in game.need_history.samples.iter().enumerate() {
+                let filled = sparkline_height(sample[row], maximum, HISTORY_GRAPH_HEIGHT);
+                for h in 0..filled {
+                    window.set_char_background(
+                        label_width + column as i32,
+                        bottom - h,
+                        colors::LIGHT_GREEN,
+                        BackgroundFlag::Set,
+                    );
+                }
+            }
+        }
+
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        tcod.overlays
+            .draw(&window, (width, height), &mut tcod.root, (x, y));
+        tcod.root.flush();
+        tcod.root.wait_for_keypress(true);
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HelpTab {
+    Movement,
+    Actions,
+    Screens,
+}
+
+impl HelpTab {
+    const ALL: [HelpTab; 3] = [HelpTab::Movement, HelpTab::Actions, HelpTab::Screens];
+}
+
+impl std::fmt::Display for HelpTab {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            HelpTab::Movement => "help_tab_movement",
+            HelpTab::Actions => "help_tab_actions",
+            HelpTab::Screens => "help_tab_screens",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
+}
+
+/// One line of the help screen: the key(s), the string key of what they do and
+/// where they're listed.
+struct KeyHelp {
+    keys: &'static str,
+    action: &'static str,
+    tab: HelpTab,
+}
+
+/// Every command `handle_keys` understands. Keep this in sync when adding keys.
+const KEY_HELP: &[KeyHelp] = &[
+    KeyHelp {
+        keys: "Arrows, numpad 8/2/4/6",
+        action: "help_move",
+        tab: HelpTab::Movement,
+    },
+    KeyHelp {
+        keys: "Home/PgUp/End/PgDn, numpad 7/9/1/3",
+        action: "help_move_diagonal",
+        tab: HelpTab::Movement,
+    },
+    KeyHelp {
+        keys: "Numpad 5",
+        action: "help_wait",
+        tab: HelpTab::Movement,
+    },
+    KeyHelp {
+        keys: "k",
+        action: "help_codex",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "n",
+        action: "help_needs_since_save",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "s",
+        action: "help_sprint",
+        tab: HelpTab::Movement,
+    },
+    KeyHelp {
+        keys: "<",
+        action: "help_descend",
+        tab: HelpTab::Movement,
+    },
+    KeyHelp {
+        keys: "Space, Enter, numpad Enter",
+        action: "help_interact",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "g",
+        action: "help_pick_up",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "i",
+        action: "help_use_item",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "U",
+        action: "help_use_all",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "d",
+        action: "help_drop",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "x",
+        action: "help_examine_item",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "m",
+        action: "help_craft",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "t",
+        action: "help_furnish",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "u",
+        action: "help_undo",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "r",
+        action: "help_repair",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "f",
+        action: "help_find_source",
+        tab: HelpTab::Actions,
+    },
+    KeyHelp {
+        keys: "e",
+        action: "help_examine",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "l",
+        action: "help_history",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "c",
+        action: "help_character",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "v",
+        action: "help_needs_graph",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "?",
+        action: "help_help",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "p, Escape",
+        action: "help_pause",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "F12",
+        action: "help_export_map",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "`",
+        action: "help_debug_console",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "F9",
+        action: "help_reveal_map",
+        tab: HelpTab::Screens,
+    },
+    KeyHelp {
+        keys: "Alt+Enter",
+        action: "help_fullscreen",
+        tab: HelpTab::Screens,
+    },
+];
+
+/// the help lines listed under a tab
+fn help_lines(tab: HelpTab) -> Vec<String> {
+    KEY_HELP
+        .iter()
+        .filter(|help| help.tab == tab)
+        .map(|help| format!("{:<36}{}", help.keys, t(help.action, &[])))
+        .collect()
+}
+
+/// show the key bindings, one tab at a time; doesn't take a turn
+fn help_screen(tcod: &mut Tcod) {
+    modal(tcod, |tcod| {
+        use tcod::input::KeyCode::*;
+
+        let mut tab = 0;
+        let mut scroll = 0;
+        let width = HELP_WIDTH + 20;
+        let height = HELP_HEIGHT + 4;
+
+        loop {
+            let lines = help_lines(HelpTab::ALL[tab]);
+            let mut window = Offscreen::new(width, height);
+
+            // the tab bar, with the current tab highlighted
+            let mut x = 0;
+            for (index, help_tab) in HelpTab::ALL.iter().enumerate() {
+                let label = format!(" {} ", help_tab);
+                let color = if index == tab {
+                    colors::LIGHT_YELLOW
+                } else {
+                    colors::GREY
+                };
+                window.set_default_foreground(color);
+                window.print_ex(x, 0, BackgroundFlag::None, TextAlignment::Left, &label);
+                x += label.len() as i32 + 1;
+            }
+
+            window.set_default_foreground(colors::WHITE);
+            for (row, line) in lines
+                .iter()
+                .skip(scroll)
+                .take(HELP_HEIGHT as usize)
+                .enumerate()
+            {
+                window.print_ex(
+                    0,
+                    2 + row as i32,
+                    BackgroundFlag::None,
+                    TextAlignment::Left,
+                    line,
+                );
+            }
+            window.set_default_foreground(colors::GREY);
+            window.print_ex(
+                0,
+                height - 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                t("help_footer", &[]),
+            );
+
+            let x = SCREEN_WIDTH / 2 - width / 2;
+            let y = SCREEN_HEIGHT / 2 - height / 2;
+            tcod.overlays
+                .draw(&window, (width, height), &mut tcod.root, (x, y));
+            tcod.root.flush();
+
+            let max_scroll = lines.len().saturating_sub(HELP_HEIGHT as usize);
+            let key = match input::wait_for_event(input::KEY_PRESS | input::MOUSE_PRESS, true) {
+                Some((_, Event::Key(key))) => key,
+                Some((_, Event::Mouse(mouse))) => {
+                    // the wheel scrolls, clicks are ignored
+                    scroll = scroll_offset(scroll, wheel_delta(&mouse), max_scroll);
+                    continue;
+                }
+                None => continue,
+            };
+            match key.code {
+                Left => {
+                    tab = (tab + HelpTab::ALL.len() - 1) % HelpTab::ALL.len();
+                    scroll = 0;
+                }
+                Right | Tab => {
+                    tab = (tab + 1) % HelpTab::ALL.len();
+                    scroll = 0;
+                }
+                Up => scroll = scroll_offset(scroll, -1, max_scroll),
+                Down => scroll = scroll_offset(scroll, 1, max_scroll),
+                _ => break,
+            }
+        }
+    })
+}
+
+/// Show a modal window: whatever is on screen is dimmed while `show` runs and
+/// put back once it returns.
+fn modal<T>(tcod: &mut Tcod, show: impl FnOnce(&mut Tcod) -> T) -> T {
+    tcod.overlays.push(&mut tcod.root);
+    let result = show(tcod);
+    tcod.overlays.pop(&mut tcod.root);
+    result
+}
+
+/// the indices of the entries containing `filter`, ignoring case; all of them
+/// if the filter is empty
+fn filter_entries<T: AsRef<str>>(entries: &[T], filter: &str) -> Vec<usize> {
+    let filter = filter.to_lowercase();
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.as_ref().to_lowercase().contains(&filter))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// A scrollable list of colored lines, like the message history. `/` asks
+/// for text to narrow it down to the lines containing it; doesn't take a turn.
+fn list_screen(title: &str, entries: &[(String, Color)], tcod: &mut Tcod) {
+    modal(tcod, |tcod| {
+        use tcod::input::KeyCode::*;
+
+        let texts: Vec<&str> = entries.iter().map(|(text, _)| text.as_str()).collect();
+        let width = LIST_WIDTH;
+        let height = LIST_HEIGHT + 4;
+        let mut filter = String::new();
+        let mut shown = filter_entries(&texts, &filter);
+        let mut scroll = 0;
+
+        loop {
+            let mut window = Offscreen::new(width, height);
+            let heading = if filter.is_empty() {
+                title.to_string()
+            } else {
+                t("list_filtered", &[("title", &title), ("filter", &filter)])
+            };
+            window.set_default_foreground(colors::LIGHT_YELLOW);
+            window.print_ex(0, 0, BackgroundFlag::None, TextAlignment::Left, heading);
+
+            if shown.is_empty() {
+                window.set_default_foreground(colors::GREY);
+                window.print_ex(
+                    0,
+                    2,
+                    BackgroundFlag::None,
+                    TextAlignment::Left,
+                    t("list_empty", &[]),
+                );
+            }
+            for (row, &index) in shown
+                .iter()
+                .skip(scroll)
+                .take(LIST_HEIGHT as usize)
+                .enumerate()
+            {
+                let (ref text, color) = entries[index];
+                let line: String = text.chars().take(width as usize).collect();
+                window.set_default_foreground(color);
+                window.print_ex(
+                    0,
+                    2 + row as i32,
+                    BackgroundFlag::None,
+                    TextAlignment::Left,
+                    line,
+                );
+            }
+            window.set_default_foreground(colors::GREY);
+            window.print_ex(
+                0,
+                height - 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                t("list_footer", &[]),
+            );
+
+            let x = SCREEN_WIDTH / 2 - width / 2;
+            let y = SCREEN_HEIGHT / 2 - height / 2;
+            tcod.overlays
+                .draw(&window, (width, height), &mut tcod.root, (x, y));
+            tcod.root.flush();
+
+            let max_scroll = shown.len().saturating_sub(LIST_HEIGHT as usize);
+            let key = match input::wait_for_event(input::KEY_PRESS | input::MOUSE_PRESS, true) {
+                Some((_, Event::Key(key))) => key,
+                Some((_, Event::Mouse(mouse))) => {
+                    scroll = scroll_offset(scroll, wheel_delta(&mouse), max_scroll);
+                    continue;
+                }
+                None => continue,
+            };
+            match key.code {
+                Up => scroll = scroll_offset(scroll, -1, max_scroll),
+                Down => scroll = scroll_offset(scroll, 1, max_scroll),
+                _ if key.printable == '/' => {
+                    // Escape keeps the filter as it was
+                    if let Some(text) = text_input(&t("list_filter_prompt", &[]), 50, tcod) {
+                        filter = text.trim().to_string();
+                        shown = filter_entries(&texts, &filter);
+                        scroll = 0;
+                    }
+                }
+                _ => break,
+            }
+        }
+    })
+}
+
+/// lines a mouse wheel event scrolls by: down is positive
+fn wheel_delta(mouse: &Mouse) -> i32 {
+    if mouse.wheel_down {
+        1
+    } else if mouse.wheel_up {
+        -1
+    } else {
+        0
+    }
+}
+
+/// move a scroll position by `delta` lines, staying between 0 and `max`
+fn scroll_offset(offset: usize, delta: i32, max: usize) -> usize {
+    let offset = offset as i64 + delta as i64;
+    cmp::min(cmp::max(offset, 0) as usize, max)
+}
+
+fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, tcod: &mut Tcod) -> Option<usize> {
+    modal(tcod, |tcod| {
+        use tcod::input::KeyCode::*;
+
+        assert!(
+            options.len() <= 26,
+            "Cannot have a menu with more than 26 options."
+        );
+
+        // calculate total height for the header (after auto-wrap) and one line per option
+        let header_height = if header.is_empty() {
+            0
+        } else {
+            tcod.root
+                .get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
+        };
+        let height = options.len() as i32 + header_height;
+
+        // the option Enter picks, moved with the arrow keys
+        let mut selected = 0;
+        loop {
+            // create an off-screen console that represents the menu's window
+            let mut window = Offscreen::new(width, height);
+
+            // print the header, with auto-wrap
+            window.set_default_foreground(colors::WHITE);
+            window.print_rect_ex(
+                0,
+                0,
+                width,
+                height,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                header,
+            );
+
+            // print all the options, the selected one highlighted
+            for (index, option_text) in options.iter().enumerate() {
+                let menu_letter = (b'a' + index as u8) as char;
+                let text = format!("({}) {}", menu_letter, option_text.as_ref());
+                let color = if index == selected {
+                    colors::LIGHT_YELLOW
+                } else {
+                    colors::WHITE
+                };
+                window.set_default_foreground(color);
+                window.print_ex(
+                    0,
+                    header_height + index as i32,
+                    BackgroundFlag::None,
+                    TextAlignment::Left,
+                    text,
+                );
+            }
+
+            // blit the contents of "window" to the root console
+            let x = SCREEN_WIDTH / 2 - width / 2;
+            let y = SCREEN_HEIGHT / 2 - height / 2;
+            tcod.overlays
+                .draw(&window, (width, height), &mut tcod.root, (x, y));
+
+            // present the root console to the player and wait for a key-press;
+            // the mouse wheel moves the selection like the arrow keys
+            tcod.root.flush();
+            let key = match input::wait_for_event(input::KEY_PRESS | input::MOUSE_PRESS, true) {
+                Some((_, Event::Key(key))) => key,
+                Some((_, Event::Mouse(mouse))) => {
+                    if !options.is_empty() {
+                        selected = wrap_selection(selected, wheel_delta(&mouse), options.len());
+                    }
+                    continue;
+                }
+                None => continue,
+            };
+            if options.is_empty() {
+                // nothing to choose, any key closes it
+                return None;
+            }
+            match key.code {
+                Up => selected = wrap_selection(selected, -1, options.len()),
+                Down => selected = wrap_selection(selected, 1, options.len()),
+                Enter | NumPadEnter if !key.alt => return Some(selected),
+                // convert the ASCII code to an index; if it corresponds to an option, return it
+                _ if key.printable.is_alphabetic() => {
+                    let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+                    return if index < options.len() {
+                        Some(index)
+                    } else {
+                        None
+                    };
+                }
+                _ => return None,
+            }
+        }
+    })
+}
+
+/// move a menu selection up (negative) or down, wrapping around at either end
+fn wrap_selection(selected: usize, delta: i32, count: usize) -> usize {
+    (selected as i32 + delta).rem_euclid(count as i32) as usize
+}
+
+/// the prompt above the inventory, followed by how full it is, e.g. "(3/26)"
+fn inventory_header(prompt: &str, count: usize) -> String {
+    format!(
+        "{} {}\n",
+        prompt,
+        t(
+            "inventory_count",
+            &[("count", &count), ("capacity", &INVENTORY_CAPACITY)]
+        )
+    )
+}
+
+fn inventory_menu(inventory: &[Object], prompt: &str, tcod: &mut Tcod) -> Option<usize> {
+    // how a menu with each item of the inventory as an option
+    let options = if inventory.len() == 0 {
+        vec![t("inventory_empty", &[])]
+    } else {
+        inventory
+            .iter()
+            .map(|item| {
+                // show additional information, in case it's equipped
+                match item.equipment {
+                    Some(equipment) if equipment.equipped => t(
+                        "inventory_equipped",
+                        &[("item", &item.name), ("slot", &equipment.slot)],
+                    ),
+                    // otherwise show how it compares to what's in its slot
+                    Some(equipment) => {
+                        let current = get_equipped_in_slot(equipment.slot, inventory)
+                            .and_then(|id| inventory[id].equipment);
+                        format!(
+                            "{} ({})",
+                            item.name,
+                            equipment_delta(&equipment, current.as_ref())
+                        )
+                    }
+                    None => item.name.clone(),
+                }
+            })
+            .collect()
+    };
+
+    let header = inventory_header(prompt, inventory.len());
+    let inventory_index = menu(&header, &options, INVENTORY_WIDTH, tcod);
+
+    // if an item was chosen, return it
+    if inventory.len() > 0 {
+        inventory_index
+    } else {
+        None
+    }
+}
+
+/// describe how equipping `candidate` would change the bonuses of `current`,
+/// e.g. "+1 power, -1 defense vs equipped"
+fn equipment_delta(candidate: &Equipment, current: Option<&Equipment>) -> String {
+    let (power, defense, max_hp) = current.map_or((0, 0, 0), |e| {
+        (e.power_bonus, e.defense_bonus, e.max_hp_bonus)
+    });
+    let changes: Vec<String> = [
+        (candidate.power_bonus - power, "stat_power"),
+        (candidate.defense_bonus - defense, "stat_defense"),
+        (candidate.max_hp_bonus - max_hp, "stat_max_hp"),
+    ]
+    .iter()
+    .filter(|&&(delta, _)| delta != 0)
+    .map(|&(delta, stat)| format!("{:+} {}", delta, t(stat, &[])))
+    .collect();
+
+    let key = match (changes.is_empty(), current.is_some()) {
+        (true, true) => "compare_same",
+        (true, false) => "compare_no_bonus",
+        (false, true) => "compare_vs_equipped",
+        (false, false) => "compare_vs_nothing",
+    };
+    t(key, &[("changes", &changes.join(", "))])
+}
+
+/// ask the player how hard the new game should be
+fn difficulty_menu(tcod: &mut Tcod) -> Option<Difficulty> {
+    let options: Vec<String> = Difficulty::ALL.iter().map(|d| d.to_string()).collect();
+    let header = format!("{}\n", t("menu_difficulty", &[]));
+    menu(&header, &options, 24, tcod).map(|index| Difficulty::ALL[index])
+}
+
+/// whether a death should cost the saves too, or None if cancelled
+fn save_mode_menu(tcod: &mut Tcod) -> Option<SaveMode> {
+    let options: Vec<String> = SaveMode::ALL.iter().map(|m| t(m.name_key(), &[])).collect();
+    let header = format!("{}\n", t("menu_save_mode", &[]));
+    menu(&header, &options, 40, tcod).map(|index| SaveMode::ALL[index])
+}
+
+/// whether to play with the tutorial's hints, or None if cancelled
+fn tutorial_menu(tcod: &mut Tcod) -> Option<bool> {
+    let options = &[t("menu_tutorial_off", &[]), t("menu_tutorial_on", &[])];
+    let header = format!("{}\n", t("menu_tutorial", &[]));
+    menu(&header, options, 30, tcod).map(|index| index == 1)
+}
+
+/// Let the player type a line of text under `prompt`. Enter accepts it,
+/// Escape cancels.
+fn text_input(prompt: &str, width: i32, tcod: &mut Tcod) -> Option<String> {
+    modal(tcod, |tcod| {
+        use tcod::input::KeyCode::*;
+
+        let mut text = String::new();
+        loop {
+            let mut window = Offscreen::new(width, 3);
+            window.set_default_foreground(colors::WHITE);
+            window.print_ex(0, 0, BackgroundFlag::None, TextAlignment::Left, prompt);
+            window.print_ex(
+                0,
+                2,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                format!("> {}_", text),
+            );
+            let x = SCREEN_WIDTH / 2 - width / 2;
+            let y = SCREEN_HEIGHT / 2 - 1;
+            tcod.overlays
+                .draw(&window, (width, 3), &mut tcod.root, (x, y));
+            tcod.root.flush();
+
+            let key = tcod.root.wait_for_keypress(true);
+            match key.code {
+                Enter | NumPadEnter => return Some(text),
+                Escape => return None,
+                Backspace => {
+                    text.pop();
+                }
+                _ if key.printable != '\0' && !key.printable.is_control() => {
+                    if (text.len() as i32) < width - 3 {
+                        text.push(key.printable);
+                    }
+                }
+                _ => {}
+            }
+        }
+    })
+}
+
+/// Something typed into the debug console.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DebugCommand {
+    SetNeed(Need, i32),
+    Give(Item),
+    Teleport(i32, i32),
+    Descend(u32), // levels to go down
+    Describe,     // write a summary of every object to `DESCRIBE_PATH`
+}
+
+/// Parse a debug console line, e.g. `set hunger 10`, `give sword`, `tp 10 5`
+/// or `level +1`. Names are matched ignoring case.
+fn parse_debug_command(line: &str) -> Result<DebugCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let number = |word: &str| {
+        word.parse::<i32>()
+            .map_err(|_| format!("'{}' is not a number", word))
+    };
+    match words.as_slice() {
+        ["set", need, value] => {
+            let need = Need::ALL
+                .iter()
+                .find(|n| format!("{:?}", n).eq_ignore_ascii_case(need))
+                .ok_or_else(|| format!("there is no need called '{}'", need))?;
+            Ok(DebugCommand::SetNeed(*need, number(value)?))
+        }
+        ["give", item] => Item::ALL
+            .iter()
+            .find(|i| format!("{:?}", i).eq_ignore_ascii_case(item))
+            .map(|&item| DebugCommand::Give(item))
+            .ok_or_else(|| format!("there is no item called '{}'", item)),
+        ["tp", x, y] => Ok(DebugCommand::Teleport(number(x)?, number(y)?)),
+        ["describe"] => Ok(DebugCommand::Describe),
+        ["level", levels] => match number(levels.trim_start_matches('+'))? {
+            levels if levels > 0 => Ok(DebugCommand::Descend(levels as u32)),
+            _ => Err("levels can only go down, use level +1".into()),
+        },
+        [] => Err("nothing to do".into()),
+        [command, ..] => Err(format!("unknown command '{}'", command)),
+    }
+}
+
+/// Carry out a debug console command.
+fn run_debug_command(command: DebugCommand, game: &mut Game, tcod: &mut Tcod) {
+    match command {
+        DebugCommand::SetNeed(need, value) => {
+            let maximum = game.objects[PLAYER].max_need(need);
+            if let Some(stats) = game.objects[PLAYER].stats.as_mut() {
+                *stats.need_mut(need) = cmp::min(cmp::max(value, 0), maximum);
+            }
+        }
+        DebugCommand::Give(item) => {
+            if game.inventory.len() >= INVENTORY_CAPACITY {
+                game.log.add(
+                    t("debug_error", &[("error", &"the inventory is full")]),
+                    colors::RED,
+                );
+                return;
+            }
+            stash_item(make_item(item, 0, 0), game);
+            game.assign_object_ids();
+        }
+        DebugCommand::Teleport(x, y) => {
+            if !in_map(x, y) || is_blocked(x, y, &game.map, &game.objects) {
+                game.log.add(
+                    t("debug_error", &[("error", &"that tile is blocked")]),
+                    colors::RED,
+                );
+                return;
+            }
+            game.objects[PLAYER].set_pos(x, y);
+        }
+        DebugCommand::Descend(levels) => {
+            for _ in 0..levels {
+                next_level(tcod, game);
+            }
+        }
+        DebugCommand::Describe => {
+            let written = serde_json::to_string_pretty(&game.describe_all())
+                .map_err(LardumError::from)
+                .and_then(|json| fs::write(DESCRIBE_PATH, json).map_err(LardumError::from));
+            if let Err(e) = written {
+                game.log
+                    .add(t("debug_error", &[("error", &e)]), colors::RED);
+                return;
+            }
+        }
+    }
+    game.log.add(
+        t("debug_done", &[("command", &format!("{:?}", command))]),
+        colors::LIGHT_GREY,
+    );
+}
+
+/// ask the player a yes/no question; anything but yes counts as no
+fn confirm(question: &str, tcod: &mut Tcod) -> bool {
+    let options = &[t("confirm_yes", &[]), t("confirm_no", &[])];
+    let header = format!("{}\n", question);
+    menu(&header, options, 30, tcod) == Some(0)
+}
+
+fn msgbox(text: &str, width: i32, tcod: &mut Tcod) {
+    let options: &[&str] = &[];
+    menu(text, options, width, tcod);
+}
+
+fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game) -> PlayerAction {
+    use tcod::input::KeyCode::*;
+
+    let player_alive = game.objects[PLAYER].alive;
+    match (key, player_alive) {
+        (
+            Key {
+                code: Enter,
+                alt: true,
+                ..
+            },
+            _,
+        ) => {
+            // Alt+Enter: toggle fullscreen
+            let fullscreen = tcod.root.is_fullscreen();
+            tcod.root.set_fullscreen(!fullscreen);
+            PlayerAction::DidntTakeTurn
+        }
+        (Key { code: Escape, .. }, _) | (Key { printable: 'p', .. }, _) => PlayerAction::Pause,
+
+        // movement keys
+        (Key { code: Up, .. }, true) | (Key { code: NumPad8, .. }, true) => {
+            player_move_or_interact(0, -1, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: Down, .. }, true) | (Key { code: NumPad2, .. }, true) => {
+            player_move_or_interact(0, 1, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: Left, .. }, true) | (Key { code: NumPad4, .. }, true) => {
+            player_move_or_interact(-1, 0, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: Right, .. }, true) | (Key { code: NumPad6, .. }, true) => {
+            player_move_or_interact(1, 0, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: Home, .. }, true) | (Key { code: NumPad7, .. }, true) => {
+            player_move_or_interact(-1, -1, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: PageUp, .. }, true) | (Key { code: NumPad9, .. }, true) => {
+            player_move_or_interact(1, -1, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: End, .. }, true) | (Key { code: NumPad1, .. }, true) => {
+            player_move_or_interact(-1, 1, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: PageDown, .. }, true) | (Key { code: NumPad3, .. }, true) => {
+            player_move_or_interact(1, 1, game, tcod);
+            PlayerAction::TookTurn
+        }
+        (Key { code: NumPad5, .. }, true) => {
+            PlayerAction::TookTurn // do nothing, i.e. wait for the monster to come to you
+        }
+
+        (Key { code: Spacebar, .. }, true)
+        | (Key { code: Enter, .. }, true)
+        | (
+            Key {
+                code: NumPadEnter, ..
+            },
+            true,
+        ) => {
+            // take the stairs, pick up or use furniture, whichever fits
+            context_interact(game, tcod)
+        }
+
+        (Key { printable: 'g', .. }, true) => {
+            // pick up an item
+            let item_id = game.objects.iter().position(|object| {
+                object.pos() == game.objects[PLAYER].pos() && object.item.is_some()
+            });
+            if let Some(item_id) = item_id {
+                pick_item_up(item_id, game);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'i', .. }, true) => {
+            // show the inventory: if an item is selected, use it
+            let inventory_index = inventory_menu(&game.inventory, &t("inventory_use", &[]), tcod);
+            if let Some(inventory_index) = inventory_index {
+                use_item(inventory_index, game, tcod);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'U', .. }, true) => {
+            // use every item of the chosen kind until its need is full
+            let inventory_index =
+                inventory_menu(&game.inventory, &t("inventory_use_all", &[]), tcod);
+            if let Some(item) = inventory_index.and_then(|index| game.inventory[index].item) {
+                use_all_of_type(item, game, tcod);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'x', .. }, true) => {
+            // examine an item in the inventory
+            let inventory_index =
+                inventory_menu(&game.inventory, &t("inventory_examine", &[]), tcod);
+            let examined = inventory_index.and_then(|index| {
+                let item = &game.inventory[index];
+                item.item
+                    .map(|kind| format!("{}\n\n{}", item.name, kind.description()))
+            });
+            if let Some(text) = examined {
+                msgbox(&text, INVENTORY_WIDTH, tcod);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'd', .. }, true) => {
+            // show the inventory; if an item is selected, drop it
+            let inventory_index = inventory_menu(&game.inventory, &t("inventory_drop", &[]), tcod);
+            if let Some(inventory_index) = inventory_index {
+                let count = game.inventory[inventory_index].count;
+                if count > 1 {
+                    // only some of a stack, if that's what the player wants
+                    let prompt = t(
+                        "split_prompt",
+                        &[
+                            ("item", &game.inventory[inventory_index].name),
+                            ("count", &count),
+                        ],
+                    );
+                    let answer = text_input(&prompt, 50, tcod)
+                        .map(|text| text.trim().parse::<u32>().map_err(|_| text));
+                    match answer {
+                        Some(Ok(n)) => {
+                            split_stack(inventory_index, n, game);
+                        }
+                        Some(Err(text)) => game
+                            .log
+                            .add(t("not_a_number", &[("text", &text.trim())]), colors::ORANGE),
+                        None => game.log.add(t("cancelled", &[]), colors::WHITE),
+                    }
+                } else {
+                    drop_item(inventory_index, game);
+                }
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'm', .. }, true) => {
+            // make something out of two items
+            let first = inventory_menu(&game.inventory, &t("craft_first", &[]), tcod);
+            let second =
+                first.and_then(|_| inventory_menu(&game.inventory, &t("craft_second", &[]), tcod));
+            match (first, second) {
+                (Some(a), Some(b)) if craft(a, b, game) => PlayerAction::TookTurn,
+                _ => PlayerAction::DidntTakeTurn,
+            }
+        }
+
+        (Key { printable: 't', .. }, true) => {
+            // furnish the current room from a template
+            let templates = game.config.room_templates.clone();
+            let names: Vec<_> = templates.iter().map(|template| &template.name).collect();
+            let header = format!("{}\n", t("template_prompt", &[]));
+            match menu(&header, &names, INVENTORY_WIDTH, tcod) {
+                Some(choice) if apply_template(game, &templates[choice]) > 0 => {
+                    PlayerAction::TookTurn
+                }
+                _ => PlayerAction::DidntTakeTurn,
+            }
+        }
+
+        (Key { printable: '`', .. }, true) if game.config.debug_console => {
+            if let Some(line) = text_input(&t("debug_prompt", &[]), 50, tcod) {
+                match parse_debug_command(&line) {
+                    Ok(command) => run_debug_command(command, game, tcod),
+                    Err(e) => game
+                        .log
+                        .add(t("debug_error", &[("error", &e)]), colors::RED),
+                }
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { code: F9, .. }, _) if game.config.debug_console => {
+            // show or hide the whole map; the explored tiles stay as they are
+            tcod.transients.reveal_all = !tcod.transients.reveal_all;
+            tcod.con.clear();
+            tcod.transients.lit_from = None;
+            render_all(tcod, game, true);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: '<', .. }, true) => {
+            // go down stairs, if the player is on them
+            let player_on_stairs = game
+                .objects_with_tag(TAG_STAIRS)
+                .any(|id| game.objects[id].pos() == game.objects[PLAYER].pos());
+            if player_on_stairs && ready_to_descend(game) {
+                next_level(tcod, game);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'c', .. }, true) => {
+            // show character information
+            let player = &game.objects[PLAYER];
+            if let Some(stats) = player.stats.as_ref() {
+                let msg = t(
+                    "character_info",
+                    &[
+                        ("hunger", &stats.hunger),
+                        ("energy", &stats.energy),
+                        ("comfort", &stats.comfort),
+                        ("fun", &stats.fun),
+                        ("hygiene", &stats.hygiene),
+                        ("social", &stats.social),
+                        ("bladder", &stats.bladder),
+                        ("room", &stats.room),
+                        ("seed", &game.seed_label()),
+                    ],
+                );
+                msgbox(&msg, CHARACTER_SCREEN_WIDTH, tcod);
+            }
+
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'f', .. }, true) => {
+            // find the nearest place to take care of a need
+            show_nearest_source(game, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'r', .. }, true) => {
+            // repair broken furniture next to the player
+            if repair_furniture(game) {
+                PlayerAction::TookTurn
+            } else {
+                PlayerAction::DidntTakeTurn
+            }
+        }
+
+        (Key { printable: 'u', .. }, true) => {
+            undo_turn(game);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: '?', .. }, _) => {
+            help_screen(tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'l', .. }, _) => {
+            // every message so far, newest first
+            let entries: Vec<(String, Color)> = game.log.iter().rev().cloned().collect();
+            list_screen(&t("history_messages", &[]), &entries, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'e', .. }, true) => {
+            // list what the player can see around them
+            let entries: Vec<(String, Color)> = game
+                .objects
+                .iter()
+                .filter(|object| {
+                    object.footprint().into_iter().any(|(x, y)| {
+                        tcod.transients.fov.is_in_fov(x, y)
+                            || (object.always_visible && game.map[x as usize][y as usize].explored)
+                    })
+                })
+                .map(|object| {
+                    let text = t(
+                        "examine_entry",
+                        &[("name", &object.name), ("x", &object.x), ("y", &object.y)],
+                    );
+                    (text, object.color)
+                })
+                .collect();
+            list_screen(&t("examine_title", &[]), &entries, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 's', .. }, true) => {
+            game.sprinting = !game.sprinting;
+            if game.sprinting {
+                game.log.add(t("sprint_on", &[]), colors::LIGHT_YELLOW);
+            } else {
+                game.log.add(t("sprint_off", &[]), colors::LIGHT_GREY);
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'k', .. }, _) => {
+            show_codex(game, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'n', .. }, true) => {
+            // how the needs changed since the last save
+            show_needs_since_save(game, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { printable: 'v', .. }, true) => {
+            // view how the needs changed over time
+            render_history_graph(game, tcod);
+            PlayerAction::DidntTakeTurn
+        }
+
+        (Key { code: F12, .. }, _) => {
+            // export the current map as text
+            match export_map(game, "map.txt") {
+                Ok(()) => game.log.add(
+                    t("map_exported", &[("path", &"map.txt")]),
+                    colors::LIGHT_GREY,
+                ),
+                Err(e) => game
+                    .log
+                    .add(t("map_export_failed", &[("error", &e)]), colors::RED),
+            }
+            PlayerAction::DidntTakeTurn
+        }
+
+        _ => PlayerAction::DidntTakeTurn,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlayerAction {
+    TookTurn,
+    DidntTakeTurn,
+    Pause,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PauseChoice {
+    Resume,
+    Save,
+    Options,
+    MainMenu,
+    Quit,
+}
+
+impl PauseChoice {
+    const ALL: [PauseChoice; 5] = [
+        PauseChoice::Resume,
+        PauseChoice::Save,
+        PauseChoice::Options,
+        PauseChoice::MainMenu,
+        PauseChoice::Quit,
+    ];
+}
+
+impl std::fmt::Display for PauseChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let key = match *self {
+            PauseChoice::Resume => "pause_resume",
+            PauseChoice::Save => "pause_save",
+            PauseChoice::Options => "pause_options",
+            PauseChoice::MainMenu => "pause_main_menu",
+            PauseChoice::Quit => "pause_quit",
+        };
+        write!(f, "{}", t(key, &[]))
+    }
+}
+
+/// map the index picked in the pause menu to its choice; anything else resumes
+fn pause_choice(index: Option<usize>) -> PauseChoice {
+    index
+        .and_then(|index| PauseChoice::ALL.get(index).cloned())
+        .unwrap_or(PauseChoice::Resume)
+}
+
+fn pause_menu(game: &Game, tcod: &mut Tcod) -> PauseChoice {
+    let header = format!(
+        "{}\n",
+        t(
+            "menu_paused",
+            &[("turn", &game.turn), ("level", &game.dungeon_level)]
+        )
+    );
+    let options: Vec<String> = PauseChoice::ALL.iter().map(|c| c.to_string()).collect();
+    pause_choice(menu(&header, &options, 30, tcod))
+}
+
+/// the auto-save intervals the options menu cycles through
+const AUTOSAVE_CHOICES: &[u32] = &[0, 50, 100, 200, 500];
+
+/// let the player tweak settings in-game; changes are written back to the config file
+fn options_menu(game: &mut Game, tcod: &mut Tcod) {
+    loop {
+        let autosave = match game.config.autosave_interval {
+            0 => t("option_autosave_off", &[]),
+            n => t("option_autosave_every", &[("turns", &n)]),
+        };
+        let options = &[t("option_autosave", &[("value", &autosave)])];
+        let header = format!("{}\n", t("menu_options", &[]));
+        match menu(&header, options, 40, tcod) {
+            Some(0) => {
+                let current = AUTOSAVE_CHOICES
+                    .iter()
+                    .position(|&n| n == game.config.autosave_interval)
+                    .unwrap_or(0);
+                game.config.autosave_interval =
+                    AUTOSAVE_CHOICES[(current + 1) % AUTOSAVE_CHOICES.len()];
+            }
+            _ => break,
+        }
+    }
+
+    if let Err(e) = save_config(&game.config) {
+        game.log
+            .add(t("options_save_failed", &[("error", &e)]), colors::RED);
+    }
+}
+
+fn player_death(player: &mut Object, log: &mut Messages) {
+    // the game ended!
+    log.add(t("you_died", &[]), colors::RED);
+
+    // for added effect, transform the player into a corpse!
+    player.char = '%';
+    player.color = colors::DARK_RED;
+}
+
+fn npc_death(npc: &mut Object, log: &mut Messages) {
+    // transform it into a nasty corpse! it doesn't block, can't be
+    // attacked and doesn't move
+    log.add(t("npc_died", &[("name", &npc.name)]), colors::ORANGE);
+    npc.char = '%';
+    npc.color = colors::DARK_RED;
+    npc.blocks = false;
+    npc.stats = None;
+    npc.ai = None;
+    npc.name = t("npc_remains", &[("name", &npc.name)]);
+}
+
+struct Tcod {
+    root: Root,
+    con: Offscreen,
+    panel: Offscreen,
+    mouse: Mouse,
+    transients: Transients,
+    overlays: Overlays,
+}
+
+impl Tcod {
+    /// Forget everything left on screen from the previous game. None of it is
+    /// saved, so a new or loaded game starts from a clean slate.
+    pub fn reset_transients(&mut self) {
+        self.transients.reset();
+    }
+}
+
+/// What's on screen that belongs to the game being played rather than the
+/// window: particles, shakes and flashes, queued keys, the camera and the
+/// field of view.
+struct Transients {
+    fov: FovMap,
+    particles: Vec<Particle>,
+    key_repeat: KeyRepeat,
+    key_queue: VecDeque<Key>, // presses not handled yet, oldest first
+    effects: ScreenEffects,
+    frame: u32, // frames drawn so far, for animations
+    camera: Camera,
+    reveal_all: bool,             // debug: draw the whole map as if it were in view
+    lit_from: Option<(i32, i32)>, // where the map was last lit from; None to redraw all of it
+}
+
+impl Transients {
+    pub fn new() -> Self {
+        Transients {
+            fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+            particles: vec![],
+            key_repeat: Default::default(),
+            key_queue: VecDeque::new(),
+            effects: Default::default(),
+            frame: 0,
+            camera: Default::default(),
+            reveal_all: false,
+            lit_from: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Transients::new();
+    }
+}
+
+/// The modal windows open on top of the game, newest last. Opening one saves
+/// the screen and dims it, and closing it puts the saved screen back, so a
+/// window opened from another one dims it too, and one reopened in a loop
+/// doesn't darken the screen any further each time round.
+struct Overlays {
+    alpha: f32, // how opaque the windows' backgrounds are
+    dim: f32,   // how far whatever is under the top window fades to black
+    saved: Vec<Offscreen>,
+}
+
+impl Overlays {
+    pub fn new(alpha: f32, dim: f32) -> Self {
+        Overlays {
+            alpha: alpha,
+            dim: dim,
+            saved: vec![],
+        }
+    }
+
+    /// Save the screen and dim it, ready for a new window on top.
+    pub fn push(&mut self, root: &mut Root) {
+        let mut saved = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+        blit(
+            root,
+            (0, 0),
+            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            &mut saved,
+            (0, 0),
+            1.0,
+            1.0,
+        );
+        self.saved.push(saved);
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let background = dim_color(root.get_char_background(x, y), self.dim);
+                let foreground = dim_color(root.get_char_foreground(x, y), self.dim);
+                root.set_char_background(x, y, background, BackgroundFlag::Set);
+                root.set_char_foreground(x, y, foreground);
+            }
+        }
+    }
+
+    /// Close the top window, bringing back the screen as it was before it opened.
+    pub fn pop(&mut self, root: &mut Root) {
+        if let Some(saved) = self.saved.pop() {
+            blit(
+                &saved,
+                (0, 0),
+                (SCREEN_WIDTH, SCREEN_HEIGHT),
+                root,
+                (0, 0),
+                1.0,
+                1.0,
+            );
+        }
+    }
+
+    /// Draw the top window's contents at `pos` on the screen.
+    pub fn draw(&self, window: &Offscreen, size: (i32, i32), root: &mut Root, pos: (i32, i32)) {
+        blit(window, (0, 0), size, root, pos, 1.0, self.alpha);
+    }
+}
+
+/// `color` faded towards black by `amount`, from 0 (unchanged) to 1 (black)
+fn dim_color(color: Color, amount: f32) -> Color {
+    lerp_color(color, colors::BLACK, amount)
+}
+
+/// The top-left map tile shown on screen. It only moves once the player walks
+/// out of the dead zone, a rectangle in the middle of the view, so that short
+/// walks around don't scroll the map every step.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Camera {
+    x: i32,
+    y: i32,
+}
+
+impl Camera {
+    pub fn follow(
+        &mut self,
+        (x, y): (i32, i32),
+        view_height: i32,
+        dead_zone_width: i32,
+        dead_zone_height: i32,
+    ) {
+        self.x = follow_axis(self.x, x, VIEW_WIDTH, dead_zone_width, MAP_WIDTH);
+        self.y = follow_axis(self.y, y, view_height, dead_zone_height, MAP_HEIGHT);
+    }
+
+    /// the map tile under a console cell of the view
+    pub fn to_map(&self, x: i32, y: i32) -> (i32, i32) {
+        (x + self.x, y + self.y)
+    }
+
+    /// the console cell of the view a map tile is drawn at
+    pub fn to_screen(&self, x: i32, y: i32) -> (i32, i32) {
+        (x - self.x, y - self.y)
+    }
+}
+
+/// Scroll one axis of the camera just enough to bring `target` back into the
+/// dead zone, without showing anything past the edges of the map.
+fn follow_axis(camera: i32, target: i32, view: i32, dead_zone: i32, map: i32) -> i32 {
+    let dead_zone = cmp::max(cmp::min(dead_zone, view), 1);
+    let zone_start = camera + (view - dead_zone) / 2;
+    let zone_end = zone_start + dead_zone - 1;
+    let camera = if target < zone_start {
+        camera - (zone_start - target)
+    } else if target > zone_end {
+        camera + (target - zone_end)
+    } else {
+        camera
+    };
+    cmp::max(cmp::min(camera, map - view), 0)
+}
+
+/// Frames left on the full-screen effects. Counted down by `render_all`.
+#[derive(Clone, Copy, Debug, Default)]
+struct ScreenEffects {
+    shake_frames: i32,
+    flash_frames: i32,
+}
+
+impl ScreenEffects {
+    pub fn shake(&mut self) {
+        self.shake_frames = SHAKE_FRAMES;
+    }
+
+    pub fn flash(&mut self) {
+        self.flash_frames = FLASH_FRAMES;
+    }
+
+    /// advance one frame
+    pub fn tick(&mut self) {
+        self.shake_frames = cmp::max(self.shake_frames - 1, 0);
+        self.flash_frames = cmp::max(self.flash_frames - 1, 0);
+    }
+}
+
+/// how far the map may jump with this many frames of shaking left; it calms
+/// down as the shake runs out
+fn shake_offset(frames_left: i32) -> i32 {
+    if frames_left <= 0 {
+        return 0;
+    }
+    (SHAKE_MAGNITUDE * frames_left + SHAKE_FRAMES - 1) / SHAKE_FRAMES
+}
+
+/// Remembers the movement key being held down so the step can be repeated
+/// while it stays down.
+#[derive(Clone, Copy, Debug, Default)]
+struct KeyRepeat {
+    key: Option<Key>,
+    frames_held: i32,
+}
+
+impl KeyRepeat {
+    pub fn press(&mut self, key: Key) {
+        self.key = Some(key);
+        self.frames_held = 0;
+    }
+
+    pub fn release(&mut self, code: KeyCode) {
+        if self.key.map_or(false, |key| key.code == code) {
+            *self = KeyRepeat::default();
+        }
+    }
+
+    /// advance one frame, returning the held key whenever it's time to repeat it
+    pub fn tick(&mut self) -> Option<Key> {
+        let key = self.key?;
+        self.frames_held += 1;
+        if should_repeat(self.frames_held) {
+            Some(key)
+        } else {
+            None
+        }
+    }
+}
+
+/// whether a key held down for this many frames should fire again
+fn should_repeat(frames_held: i32) -> bool {
+    frames_held >= KEY_REPEAT_DELAY && (frames_held - KEY_REPEAT_DELAY) % KEY_REPEAT_INTERVAL == 0
+}
+
+/// which way a movement key goes
+fn key_step(code: KeyCode) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::*;
+    match code {
+        Up | NumPad8 => Some((0, -1)),
+        Down | NumPad2 => Some((0, 1)),
+        Left | NumPad4 => Some((-1, 0)),
+        Right | NumPad6 => Some((1, 0)),
+        Home | NumPad7 => Some((-1, -1)),
+        PageUp | NumPad9 => Some((1, -1)),
+        End | NumPad1 => Some((-1, 1)),
+        PageDown | NumPad3 => Some((1, 1)),
+        _ => None,
+    }
+}
+
+/// The oldest key press not handled yet. Anything other than a move may open
+/// a menu, so the keys typed after it were probably meant for that menu and
+/// are dropped rather than replayed afterwards.
+fn next_queued_key(queue: &mut VecDeque<Key>) -> Option<Key> {
+    let key = queue.pop_front()?;
+    if !is_movement_key(key.code) {
+        queue.clear();
+    }
+    Some(key)
+}
+
+fn is_movement_key(code: KeyCode) -> bool {
+    use tcod::input::KeyCode::*;
+    match code {
+        Up | Down | Left | Right | Home | End | PageUp | PageDown => true,
+        NumPad1 | NumPad2 | NumPad3 | NumPad4 | NumPad6 | NumPad7 | NumPad8 | NumPad9 => true,
+        _ => false,
+    }
+}
+
+/// A short-lived visual effect drawn on top of the map, like the sparkle of a
+/// used item. Particles are purely cosmetic and never saved.
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    x: f32,
+    y: f32,
+    dx: f32,
+    dy: f32,
+    char: char,
+    color: Color,
+    lifetime: i32, // frames left before the particle disappears
+}
+
+impl Particle {
+    pub fn pos(&self) -> (i32, i32) {
+        (self.x.round() as i32, self.y.round() as i32)
+    }
+}
+
+/// add a particle at the given position, drifting by (dx, dy) every frame
+fn spawn_particle(
+    tcod: &mut Tcod,
+    (x, y): (i32, i32),
+    (dx, dy): (f32, f32),
+    char: char,
+    color: Color,
+    lifetime: i32,
+) {
+    tcod.transients.particles.push(Particle {
+        x: x as f32,
+        y: y as f32,
+        dx: dx,
+        dy: dy,
+        char: char,
+        color: color,
+        lifetime: lifetime,
+    });
+}
+
+/// move every particle along and drop the ones that have run out of time
+fn age_particles(particles: &mut Vec<Particle>) {
+    for particle in particles.iter_mut() {
+        particle.x += particle.dx;
+        particle.y += particle.dy;
+        particle.lifetime -= 1;
+    }
+    particles.retain(|particle| particle.lifetime > 0);
+}
+
+#[derive(Serialize, Deserialize)]
+struct Game {
+    map: Map,
+    log: Messages,
+    inventory: Vec<Object>,
+    dungeon_level: u32,
+    objects: Vec<Object>,
+    #[serde(default)]
+    difficulty: Difficulty,
+    #[serde(default)]
+    turn: u32,
+    #[serde(default)]
+    need_history: NeedHistory,
+    #[serde(default)]
+    rng: GameRng,
+    #[serde(default = "water_on_by_default")]
+    water_on: bool,
+    #[serde(default)]
+    weather: Weather,
+    #[serde(default)]
+    biome: Biome, // of the current level
+    #[serde(default)]
+    rooms: Vec<Rect>, // of the current level, empty in saves from before they were kept
+    // settings come from the config file, not from the save
+    #[serde(skip)]
+    config: Config,
+    #[serde(skip)]
+    undo: VecDeque<UndoSnapshot>, // most recent turn last
+    #[serde(skip)]
+    handlers: Vec<EventHandler>,
+    #[serde(skip)]
+    spawners: Vec<Spawner>,
+    #[serde(skip)]
+    walk_map: Option<WalkMap>, // for this turn's pathfinding, built when first needed
+    #[serde(default)]
+    next_object_id: u64,
+    #[serde(default)]
+    tutorial: bool,
+    #[serde(default)]
+    hints_shown: HashSet<Hint>,
+    #[serde(default)]
+    good_turns: u32, // in a row with every need at or above the milestone threshold
+    #[serde(default)]
+    milestones: u32, // reached so far
+    #[serde(default)]
+    seed_text: Option<String>, // the phrase the seed was made from, if there was one
+    // the id of the item in each slot; the items' `equipped` flags are what's
+    // saved, this is rebuilt from them
+    #[serde(skip)]
+    equipped: HashMap<Slot, u64>,
+    #[serde(default)]
+    save_mode: SaveMode,
+    #[serde(default)]
+    last_advice: Option<u32>, // turn the advisor last said something
+    #[serde(default)]
+    sprinting: bool, // moving covers two tiles, for some energy
+    #[serde(default)]
+    active_events: Vec<ActiveEvent>,
+    // the needs as they were in the last save, to compare against
+    #[serde(skip)]
+    saved_needs: Option<[i32; 8]>,
+    #[serde(default)]
+    codex: Codex,
+}
+
+impl Game {
+    /// This turn's walkability map, worked out the first time it's asked for.
+    fn walk_map(&mut self) -> &WalkMap {
+        let (map, objects) = (&self.map, &self.objects);
+        self.walk_map
+            .get_or_insert_with(|| WalkMap::new(map, objects))
+    }
+
+    /// the seed as shown to the player: the phrase if there is one, and the number
+    pub fn seed_label(&self) -> String {
+        match self.seed_text {
+            Some(ref text) => format!("\"{}\" ({})", text, self.rng.seed),
+            None => self.rng.seed.to_string(),
+        }
+    }
+}
+
+/// The tutorial's hints, each shown the first time its moment comes up.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+enum Hint {
+    Welcome,
+    FirstMove,
+    LowNeed,
+    Furniture,
+    ItemHere,
+}
+
+impl Hint {
+    /// string table key of its text
+    fn key(self) -> &'static str {
+        match self {
+            Hint::Welcome => "hint_welcome",
+            Hint::FirstMove => "hint_first_move",
+            Hint::LowNeed => "hint_low_need",
+            Hint::Furniture => "hint_furniture",
+            Hint::ItemHere => "hint_item_here",
+        }
+    }
+}
+
+/// in tutorial mode, log a hint unless it has been shown before
+fn show_hint(hint: Hint, game: &mut Game) {
+    if game.tutorial && game.hints_shown.insert(hint) {
+        game.log.add(t(hint.key(), &[]), colors::LIGHT_YELLOW);
+    }
+}
+
+/// Something that happened in the game that other systems may want to react to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GameEvent {
+    NeedCritical(Need),
+    ItemUsed(Item),
+    LevelDescended(u32),
+    Death(u64), // id of the object that died
+    FurnitureUsed(FurnitureKind),
+    Milestone(u32), // how many have been reached so far
+}
+
+type EventHandler = Box<dyn FnMut(&GameEvent, &mut Game)>;
+
+/// Adds objects to a freshly generated room: given the room, the map and the
+/// level, it pushes whatever it likes onto the objects.
+type Spawner = Box<dyn FnMut(Rect, &Map, u32, &mut Vec<Object>)>;
+
+/// Everything a turn can change, so that it can be taken back. The map is
+/// left out since turns don't change it, and going down the stairs clears
+/// the snapshots. The RNG goes in too, so the NPCs replay the same way.
+/// Sprinting is left out as well: it's a setting the player toggles without
+/// spending a turn, not something a turn changes.
+#[derive(Clone)]
+struct UndoSnapshot {
+    objects: Vec<Object>,
+    inventory: Vec<Object>,
+    log_len: usize,
+    turn: u32,
+    need_history: NeedHistory,
+    rng: GameRng,
+    water_on: bool,
+    weather: Weather,
+    active_events: Vec<ActiveEvent>,
+    good_turns: u32,
+    milestones: u32,
+    last_advice: Option<u32>,
+    codex: Codex,
+}
+
+impl UndoSnapshot {
+    pub fn take(game: &Game) -> Self {
+        UndoSnapshot {
+            objects: game.objects.clone(),
+            inventory: game.inventory.clone(),
+            log_len: game.log.len(),
+            turn: game.turn,
+            need_history: game.need_history.clone(),
+            rng: game.rng.clone(),
+            water_on: game.water_on,
+            weather: game.weather,
+            active_events: game.active_events.clone(),
+            good_turns: game.good_turns,
+            milestones: game.milestones,
+            last_advice: game.last_advice,
+            codex: game.codex.clone(),
+        }
+    }
+
+    pub fn restore(self, game: &mut Game) {
+        game.objects = self.objects;
+        game.inventory = self.inventory;
+        game.rebuild_equipped();
+        game.log.truncate(self.log_len);
+        game.turn = self.turn;
+        game.need_history = self.need_history;
+        game.rng = self.rng;
+        game.water_on = self.water_on;
+        game.weather = self.weather;
+        game.active_events = self.active_events;
+        game.good_turns = self.good_turns;
+        game.milestones = self.milestones;
+        game.last_advice = self.last_advice;
+        game.codex = self.codex;
+    }
+}
+
+fn push_undo(game: &mut Game, snapshot: UndoSnapshot) {
+    if game.undo.len() >= UNDO_DEPTH {
+        game.undo.pop_front();
+    }
+    game.undo.push_back(snapshot);
+}
+
+/// take back the player's last turn
+fn undo_turn(game: &mut Game) {
+    match game.undo.pop_back() {
+        Some(snapshot) => {
+            snapshot.restore(game);
+            game.log.add(t("undone", &[]), colors::LIGHT_GREY);
+        }
+        None => game.log.add(t("nothing_to_undo", &[]), colors::LIGHT_GREY),
+    }
+}
+
+impl Game {
+    /// Take the object at `index` off the map. The last object moves into its
+    /// place, so any other index held onto may now point at something else.
+    /// Returns None, leaving the objects alone, for an index that's out of
+    /// range or is the player's.
+    pub fn take_object(&mut self, index: usize) -> Option<Object> {
+        if index == PLAYER || index >= self.objects.len() {
+            return None;
+        }
+        Some(self.objects.swap_remove(index))
+    }
+
+    /// remember the needs as they are now, just saved or loaded
+    pub fn mark_saved(&mut self) {
+        self.saved_needs = self.objects[PLAYER].stats.map(|stats| need_values(&stats));
+    }
+
+    pub fn event_active(&self, event: RandomEvent) -> bool {
+        self.active_events
+            .iter()
+            .any(|active| active.event == event)
+    }
+
+    /// Give every object that doesn't have an id yet (fresh from the map
+    /// generator or an old save) the next one from the counter.
+    pub fn assign_object_ids(&mut self) {
+        for object in self.objects.iter_mut().chain(self.inventory.iter_mut()) {
+            if object.id == 0 {
+                self.next_object_id += 1;
+                object.id = self.next_object_id;
+            }
+        }
+    }
+
+    /// Equip the inventory item at `index`, keeping `equipped` up to date.
+    pub fn equip(&mut self, index: usize) {
+        self.inventory[index].equip(&mut self.log);
+        self.sync_equipped(index);
+    }
+
+    /// Unequip the inventory item at `index`, keeping `equipped` up to date.
+    pub fn unequip(&mut self, index: usize) {
+        self.inventory[index].unequip(&mut self.log);
+        self.sync_equipped(index);
+    }
+
+    /// bring the slot of the inventory item at `index` in line with its flag
+    fn sync_equipped(&mut self, index: usize) {
+        let item = &self.inventory[index];
+        if let Some(equipment) = item.equipment {
+            if equipment.equipped {
+                self.equipped.insert(equipment.slot, item.id);
+            } else if self.equipped.get(&equipment.slot) == Some(&item.id) {
+                self.equipped.remove(&equipment.slot);
+            }
+        }
+    }
+
+    /// Work out what's in each slot from the items' own flags, which are what
+    /// gets saved. Needed whenever the inventory is replaced wholesale.
+    pub fn rebuild_equipped(&mut self) {
+        self.equipped.clear();
+        for item in &self.inventory {
+            if let Some(equipment) = item.equipment.filter(|e| e.equipped) {
+                self.equipped.insert(equipment.slot, item.id);
+            }
+        }
+    }
+
+    /// where in the inventory the item equipped in `slot` is, if there is one
+    pub fn equipped_in_slot(&self, slot: Slot) -> Option<usize> {
+        let id = *self.equipped.get(&slot)?;
+        self.inventory.iter().position(|item| item.id == id)
+    }
+
+    /// a summary of every object on the map, the player first
+    pub fn describe_all(&self) -> Vec<ObjectSummary> {
+        self.objects.iter().map(Object::summary).collect()
+    }
+
+    /// where the object with this id currently is in `objects`
+    pub fn index_of(&self, id: u64) -> Option<usize> {
+        self.objects.iter().position(|object| object.id == id)
+    }
+
+    /// call `handler` for every event fired from now on
+    pub fn on<F: FnMut(&GameEvent, &mut Game) + 'static>(&mut self, handler: F) {
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// call `spawner` for every room of every level generated from now on
+    pub fn on_spawn<F: FnMut(Rect, &Map, u32, &mut Vec<Object>) + 'static>(&mut self, spawner: F) {
+        self.spawners.push(Box::new(spawner));
+    }
+
+    /// let every registered handler react to `event`
+    pub fn emit(&mut self, event: GameEvent) {
+        // the handlers are taken out while they run so they can get at the
+        // whole game; any they register in the meantime are kept too
+        let mut handlers = std::mem::replace(&mut self.handlers, vec![]);
+        for handler in handlers.iter_mut() {
+            handler(&event, self);
+        }
+        handlers.append(&mut self.handlers);
+        self.handlers = handlers;
+    }
+
+    /// indices of the objects carrying a tag
+    pub fn objects_with_tag<'a>(&'a self, tag: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(move |(_, object)| object.has_tag(tag))
+            .map(|(id, _)| id)
+    }
+}
+
+/// Player-tweakable settings, read from `lardum.json` in the working
+/// directory. Any field left out of the file keeps its default value.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Config {
+    autosave_interval: u32, // turns between auto-saves, 0 disables them
+    starting_loadout: Vec<LoadoutEntry>,
+    screen_effects: bool, // shake and flash the screen at dramatic moments
+    language: String,     // which `lang/<code>.json` the game's text comes from
+    torch_flicker: bool,  // make the lit area shimmer a little
+    wake_hour: u32,       // when sleeping in a bed at night lasts until
+    camera_dead_zone: (i32, i32), // how far the player can walk before the view scrolls
+    rooms: RoomParams,
+    high_contrast: bool, // starker colors and solid walls for legibility
+    auto_sort_inventory: Option<SortKey>, // re-sort after every pickup, if set
+    room_templates: Vec<RoomTemplate>,
+    milestone: Milestone,
+    debug_console: bool, // allow opening the debug console with `
+    start_level: u32,    // level a new game starts on, for trying out the deeper ones
+    panel: PanelParams,
+    decay_curves: HashMap<Need, DecayCurve>, // needs left out drain linearly
+    grace_turns: u32, // turns at the start of a game before the needs start draining
+    diagnostic_log: bool, // write what happens to lardum.log, for bug reports
+    seed: Option<String>, // phrase new games are generated from; random if None
+    advisor: bool,    // suggest what to do about the lowest need now and then
+    overlay_alpha: f32, // opacity of menus' backgrounds, from 0 to 1
+    overlay_dim: f32, // how much the screen behind a menu darkens, from 0 to 1
+    starting_stats: StartingStats,
+    events: EventParams,
+    pet: bool,                          // start with a cat that follows the player around
+    stairs_need_threshold: Option<i32>, // every need has to be above this to go down; None to allow any time
+}
+
+/// How often random events happen, and which.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct EventParams {
+    interval: u32, // turns between chances of an event; 0 turns them off
+    chance: f64,   // that an event happens when it gets the chance
+    table: Vec<EventEntry>,
+}
+
+/// One event in the table, and how likely it is next to the others.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct EventEntry {
+    event: RandomEvent,
+    weight: u32,
+}
+
+impl Default for EventParams {
+    fn default() -> Self {
+        EventParams {
+            interval: 60,
+            chance: 0.1,
+            table: vec![
+                EventEntry {
+                    event: RandomEvent::PowerOutage,
+                    weight: 2,
+                },
+                EventEntry {
+                    event: RandomEvent::Leak,
+                    weight: 2,
+                },
+                EventEntry {
+                    event: RandomEvent::Gift,
+                    weight: 1,
+                },
+            ],
+        }
+    }
+}
+
+/// The player's needs at the start of a new game.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct StartingStats {
+    max: i32,                  // the most any need can be filled to
+    needs: HashMap<Need, i32>, // needs left out start full
+}
+
+impl Default for StartingStats {
+    fn default() -> Self {
+        StartingStats {
+            max: 100,
+            needs: HashMap::new(),
+        }
+    }
+}
+
+impl StartingStats {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max < 1 {
+            return Err(format!(
+                "starting_stats.max must be at least 1, not {}",
+                self.max
+            ));
+        }
+        for (need, &value) in &self.needs {
+            if value < 0 || value > self.max {
+                return Err(format!(
+                    "starting_stats.needs.{:?} must be between 0 and {}, not {}",
+                    need, self.max, value
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// the player's stats for a new game
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats {
+            base_max_all_stats: self.max,
+            hunger: self.max,
+            comfort: self.max,
+            hygiene: self.max,
+            bladder: self.max,
+            energy: self.max,
+            fun: self.max,
+            social: self.max,
+            room: self.max,
+            on_death: DeathCallback::Player,
+        };
+        for (&need, &value) in &self.needs {
+            *stats.need_mut(need) = value;
+        }
+        stats
+    }
+}
+
+/// Size of the panel at the bottom of the screen with the log and the bars.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct PanelParams {
+    height: i32,
+    msg_width: i32, // the log wraps at this many characters
+}
+
+impl Default for PanelParams {
+    fn default() -> Self {
+        PanelParams {
+            height: 8,
+            msg_width: SCREEN_WIDTH - MSG_X,
+        }
+    }
+}
+
+impl PanelParams {
+    /// the screen row the panel starts at, which is also how tall the map view is
+    pub fn y(&self) -> i32 {
+        SCREEN_HEIGHT - self.height
+    }
+
+    /// lines of the log shown at once
+    pub fn msg_lines(&self) -> i32 {
+        self.height - 1
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.height < PANEL_MIN_HEIGHT || self.height > SCREEN_HEIGHT / 2 {
+            return Err(format!(
+                "panel.height must be between {} and {}, not {}",
+                PANEL_MIN_HEIGHT,
+                SCREEN_HEIGHT / 2,
+                self.height
+            ));
+        }
+        if self.msg_width < 10 || self.msg_width > SCREEN_WIDTH - MSG_X {
+            return Err(format!(
+                "panel.msg_width must be between 10 and {}, not {}",
+                SCREEN_WIDTH - MSG_X,
+                self.msg_width
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How long, and how well, the needs have to be looked after for a milestone.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct Milestone {
+    turns: u32,     // in a row; 0 turns milestones off
+    threshold: i32, // every need has to stay at least this high
+}
+
+impl Default for Milestone {
+    fn default() -> Self {
+        Milestone {
+            turns: 500,
+            threshold: 60,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            autosave_interval: 100,
+            starting_loadout: vec![LoadoutEntry {
+                item: Item::Dagger,
+                count: 1,
+            }],
+            screen_effects: true,
+            language: "en".into(),
+            torch_flicker: false,
+            wake_hour: 7,
+            camera_dead_zone: (20, 10),
+            rooms: RoomParams::default(),
+            high_contrast: false,
+            auto_sort_inventory: None,
+            room_templates: default_room_templates(),
+            milestone: Milestone::default(),
+            debug_console: false,
+            start_level: 1,
+            panel: PanelParams::default(),
+            decay_curves: HashMap::new(),
+            grace_turns: 30,
+            diagnostic_log: false,
+            seed: None,
+            advisor: false,
+            overlay_alpha: 0.7,
+            overlay_dim: 0.5,
+            starting_stats: StartingStats::default(),
+            events: EventParams::default(),
+            pet: false,
+            stairs_need_threshold: None,
+        }
+    }
+}
+
+/// Parameters for the house generator.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+struct RoomParams {
+    min_size: i32,
+    max_size: i32,
+    max_rooms: i32, // rooms the generator tries to fit in; overlapping ones are dropped
+    style: MapStyle,
+}
+
+/// How the house generator lays out the rooms.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum MapStyle {
+    Scattered, // random rooms wherever they fit, joined in a chain
+    Bsp,       // the map split up into pieces with a room in each
+}
+
+impl Default for RoomParams {
+    fn default() -> Self {
+        RoomParams {
+            min_size: 6,
+            max_size: 10,
+            max_rooms: 30,
+            style: MapStyle::Scattered,
+        }
+    }
+}
+
+impl RoomParams {
+    /// check the generator can actually work with these
+    pub fn validate(&self) -> Result<(), String> {
+        // a room needs some floor inside its walls
+        if self.min_size < 3 {
+            return Err(format!(
+                "rooms.min_size must be at least 3, not {}",
+                self.min_size
+            ));
+        }
+        if self.min_size > self.max_size {
+            return Err(format!(
+                "rooms.min_size ({}) is bigger than rooms.max_size ({})",
+                self.min_size, self.max_size
+            ));
+        }
+        if self.max_size >= cmp::min(MAP_WIDTH, MAP_HEIGHT) {
+            return Err(format!(
+                "rooms.max_size must be less than {} to fit the map",
+                cmp::min(MAP_WIDTH, MAP_HEIGHT)
+            ));
+        }
+        if self.max_rooms < 1 {
+            return Err("rooms.max_rooms must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How many of an item the player starts a new game with.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct LoadoutEntry {
+    item: Item,
+    count: u32,
+}
+
+fn save_config(config: &Config) -> Result<(), LardumError> {
+    let json_config = serde_json::to_string_pretty(config)?;
+    let mut file = File::create(CONFIG_PATH)?;
+    file.write_all(json_config.as_bytes())?;
+    Ok(())
+}
+
+fn load_config() -> Result<Config, LardumError> {
+    let mut json_config = String::new();
+    let mut file = match File::open(CONFIG_PATH) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e.into()),
+    };
+    file.read_to_string(&mut json_config)?;
+    let config = serde_json::from_str::<Config>(&json_config)
+        .map_err(|e| LardumError::BadConfig(format!("{}: {}", CONFIG_PATH, e)))?;
+    config
+        .rooms
+        .validate()
+        .and_then(|_| config.panel.validate())
+        .and_then(|_| config.starting_stats.validate())
+        .map_err(|e| LardumError::BadConfig(format!("{}: {}", CONFIG_PATH, e)))?;
+    for &(name, value) in &[
+        ("overlay_alpha", config.overlay_alpha),
+        ("overlay_dim", config.overlay_dim),
+    ] {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(LardumError::BadConfig(format!(
+                "{}: {} must be between 0 and 1",
+                CONFIG_PATH, name
+            )));
+        }
+    }
+    if !(0.0..=1.0).contains(&config.events.chance) {
+        return Err(LardumError::BadConfig(format!(
+            "{}: events.chance must be between 0 and 1",
+            CONFIG_PATH
+        )));
+    }
+    if config.start_level < 1 {
+        return Err(LardumError::BadConfig(format!(
+            "{}: start_level must be at least 1",
+            CONFIG_PATH
+        )));
+    }
+    Ok(config)
+}
+
+/// English text is built in, so there's always something to fall back on.
+const ENGLISH: &str = include_str!("../lang/en.json");
+
+/// The game's user-facing text, keyed by message id.
+struct StringTable {
+    strings: HashMap<String, String>, // the chosen language
+    english: HashMap<String, String>,
+}
+
+impl StringTable {
+    fn english() -> Self {
+        let english: HashMap<String, String> =
+            serde_json::from_str(ENGLISH).expect("lang/en.json is not a valid string table");
+        StringTable {
+            strings: english.clone(),
+            english: english,
+        }
+    }
+
+    fn lookup(&self, key: &str) -> String {
+        self.strings
+            .get(key)
+            .or_else(|| self.english.get(key))
+            .cloned()
+            .unwrap_or_else(|| key.to_string())
+    }
+}
+
+thread_local! {
+    static STRINGS: RefCell<StringTable> = RefCell::new(StringTable::english());
+}
+
+/// Switch the game's text over to `lang/<code>.json`. Strings missing from the
+/// file keep falling back to English.
+fn set_language(code: &str) -> Result<(), LardumError> {
+    let strings = if code == "en" {
+        StringTable::english().strings
+    } else {
+        let path = format!("{}/{}.json", LANG_DIR, code);
+        let json = fs::read_to_string(&path)
+            .map_err(|e| LardumError::BadConfig(format!("language {}: {}", code, e)))?;
+        serde_json::from_str(&json)
+            .map_err(|e| LardumError::BadConfig(format!("{}: {}", path, e)))?
+    };
+    STRINGS.with(|table| table.borrow_mut().strings = strings);
+    Ok(())
+}
+
+/// Translate a message id, filling in each `{name}` placeholder from `args`.
+fn t(key: &str, args: &[(&str, &dyn fmt::Display)]) -> String {
+    let mut text = STRINGS.with(|table| table.borrow().lookup(key));
+    for (name, value) in args {
+        text = text.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    text
+}
+
+thread_local! {
+    static DIAG_LOG: RefCell<Option<File>> = RefCell::new(None);
+}
+
+/// Start appending diagnostics to `path`. Until this is called `diag` does
+/// nothing at all.
+fn open_diag_log(path: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    DIAG_LOG.with(|log| *log.borrow_mut() = Some(file));
+    Ok(())
+}
+
+/// Write one line to the diagnostic log, if it's open: seconds since the
+/// epoch, the event and its details. This is for bug reports, not the
+/// player, so a failed write is ignored rather than getting in the way.
+fn diag(event: &str, details: &str) {
+    DIAG_LOG.with(|log| {
+        if let Some(file) = log.borrow_mut().as_mut() {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let _ = writeln!(
+                file,
+                "{}.{:03} {} {}",
+                now.as_secs(),
+                now.subsec_millis(),
+                event,
+                details
+            );
+        }
+    });
+}
+
+trait MessageLog {
+    fn add<T: Into<String>>(&mut self, message: T, color: Color);
+}
+
+impl MessageLog for Vec<(String, Color)> {
+    fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        self.push((message.into(), color));
+    }
+}
+
+fn water_on_by_default() -> bool {
+    true
+}
+
+/// Start a new game. `spawners` are hooked into object spawning from the very
+/// first level on, the same as registering them with `Game::on_spawn` later.
+fn new_game(
+    tcod: &mut Tcod,
+    difficulty: Difficulty,
+    level: u32,
+    tutorial: bool,
+    seed: Option<&str>,
+    config: Config,
+    spawners: Vec<Spawner>,
+) -> Game {
+    let mut game = create_game(difficulty, level, tutorial, seed, config, spawners);
+
+    tcod.reset_transients();
+    initialise_fov(&game.map, tcod);
+    diag(
+        "new_game",
+        &format!(
+            "seed={} level={} difficulty={:?} biome={:?}",
+            game.seed_label(),
+            level,
+            difficulty,
+            game.biome
+        ),
+    );
+
+    // a warm welcoming message!
+    game.log.add(t("welcome", &[]), colors::RED);
+    show_hint(Hint::Welcome, &mut game);
+
+    game
+}
+
+/// The game itself, player, first level and starting inventory, without
+/// anything on screen yet.
+fn create_game(
+    difficulty: Difficulty,
+    level: u32,
+    tutorial: bool,
+    seed: Option<&str>,
+    config: Config,
+    mut spawners: Vec<Spawner>,
+) -> Game {
+    // create object representing the player
+    let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
+    player.alive = true;
+    player.stats = Some(config.starting_stats.stats());
+
+    let mut objects = vec![player];
+    // a seed phrase makes the same house every time, otherwise it's a surprise
+    let mut rng = GameRng::new(seed.map_or_else(|| rand::thread_rng().gen(), seed_from_str));
+    let biome = Biome::for_level(level, &mut rng);
+    // generate map (at this point it's not drawn to the screen)
+    let (map, rooms) = make_map(
+        &mut objects,
+        level,
+        difficulty,
+        biome,
+        &config.rooms,
+        &mut spawners,
+        &mut rng,
+    );
+
+    let mut game = Game {
+        map: map,
+        rooms: rooms,
+        // create the list of game messages and their colors, starts empty
+        log: vec![],
+        inventory: vec![],
+        dungeon_level: level,
+        // the list of objects with just the player
+        objects: objects,
+        difficulty: difficulty,
+        turn: 0,
+        need_history: Default::default(),
+        rng: rng,
+        water_on: true,
+        weather: Weather::Clear,
+        biome: biome,
+        config: config,
+        undo: VecDeque::new(),
+        handlers: vec![],
+        spawners: spawners,
+        walk_map: None,
+        next_object_id: 0,
+        tutorial: tutorial,
+        hints_shown: HashSet::new(),
+        good_turns: 0,
+        milestones: 0,
+        seed_text: seed.map(str::to_string),
+        equipped: HashMap::new(),
+        save_mode: SaveMode::default(),
+        last_advice: None,
+        sprinting: false,
+        active_events: vec![],
+        saved_needs: None,
+        codex: Codex::default(),
+    };
+
+    // initial equipment, as listed in the config
+    for entry in game.config.starting_loadout.clone() {
+        for _ in 0..entry.count {
+            if game.inventory.len() >= INVENTORY_CAPACITY {
+                break;
+            }
+            stash_item(make_item(entry.item, 0, 0), &mut game);
+        }
+    }
+    if game.config.pet {
+        place_pet(make_pet(), &mut game);
+    }
+    game.assign_object_ids();
+    game
+}
+
+fn make_pet() -> Object {
+    let mut pet = Object::new(0, 0, 'f', "cat", colors::ORANGE, false);
+    pet.alive = true;
+    pet.ai = Some(Ai::Follow);
+    pet.render_layer = RenderLayer::Actor;
+    pet
+}
+
+/// Put the pet down on a free tile next to the player, or right on top of
+/// them if there's no room; it doesn't block, so either way is fine.
+fn place_pet(mut pet: Object, game: &mut Game) {
+    let (player_x, player_y) = game.objects[PLAYER].pos();
+    let mut spot = (player_x, player_y);
+    'search: for dy in -1..2 {
+        for dx in -1..2 {
+            let (x, y) = (player_x + dx, player_y + dy);
+            if (dx, dy) != (0, 0) && !is_blocked(x, y, &game.map, &game.objects) {
+                spot = (x, y);
+                break 'search;
+            }
+        }
+    }
+    pet.set_pos(spot.0, spot.1);
+    game.objects.push(pet);
+}
+
+fn initialise_fov(map: &Map, tcod: &mut Tcod) {
+    // create the FOV map, according to the generated map
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            tcod.transients.fov.set(
+                x,
+                y,
+                !map[x as usize][y as usize].block_sight,
+                !map[x as usize][y as usize].blocked,
+            );
+        }
+    }
+
+    // unexplored areas start black (which is the default background color)
+    tcod.con.clear();
+    tcod.transients.lit_from = None;
+}
+
+/// Run the game until the player leaves it, returning whether they want to go
+/// back to the main menu or quit altogether.
+fn play_game(game: &mut Game, tcod: &mut Tcod) -> PauseChoice {
+    // force FOV "recompute" first time through the game loop
+    let mut previous_player_position = (-1, -1);
+
+    while !tcod.root.window_closed() {
+        poll_input(tcod);
+
+        // keep walking while a movement key is held down
+        if tcod.transients.key_queue.is_empty() {
+            if let Some(held) = tcod.transients.key_repeat.tick() {
+                tcod.transients.key_queue.push_back(held);
+            }
+        }
+
+        // render the screen
+        let fov_recompute = previous_player_position != (game.objects[PLAYER].pos());
+        render_all(tcod, game, fov_recompute);
+
+        tcod.root.flush();
+
+        // erase all objects at their old locations, before they move
+        for object in game.objects.iter_mut() {
+            object.clear(&mut tcod.con)
+        }
+
+        // same for the particles, which then drift and fade for the next frame
+        for particle in &tcod.transients.particles {
+            let (x, y) = particle.pos();
+            if in_map(x, y) {
+                tcod.con.put_char(x, y, ' ', BackgroundFlag::None);
+            }
+        }
+        age_particles(&mut tcod.transients.particles);
+
+        // handle every key pressed since the last frame, in order, and exit
+        // the game if needed
+        previous_player_position = game.objects[PLAYER].pos();
+        while let Some(key) = next_queued_key(&mut tcod.transients.key_queue) {
+            // remember how things were, in case the player takes the turn back
+            let before = UndoSnapshot::take(game);
+            let was_alive = game.objects[PLAYER].alive;
+            let player_action = handle_keys(key, tcod, game);
+            if player_action == PlayerAction::Pause {
+                match pause_menu(game, tcod) {
+                    PauseChoice::Resume => {}
+                    // a save made after dying would replace the one to go back to
+                    PauseChoice::Save if !game.objects[PLAYER].alive => {
+                        game.log.add(t("save_after_death", &[]), colors::LIGHT_GREY)
+                    }
+                    PauseChoice::Save => match save_game(game) {
+                        Ok(()) => {
+                            game.mark_saved();
+                            game.log.add(t("game_saved", &[]), colors::LIGHT_GREY)
+                        }
+                        Err(e) => game
+                            .log
+                            .add(t("save_failed", &[("error", &e)]), colors::RED),
+                    },
+                    PauseChoice::Options => options_menu(game, tcod),
+                    choice @ PauseChoice::MainMenu | choice @ PauseChoice::Quit => {
+                        let saved = if game.objects[PLAYER].alive {
+                            save_game(game)
+                        } else {
+                            Ok(())
+                        };
+                        if let Err(e) = saved {
+                            game.log
+                                .add(t("save_failed", &[("error", &e)]), colors::RED);
+                            continue;
+                        }
+                        tcod.root.clear();
+                        tcod.root.flush();
+                        return choice;
+                    }
+                }
+            }
+
+            // let the rest of the house catch up with the player's turn
+            if game.objects[PLAYER].alive && player_action == PlayerAction::TookTurn {
+                push_undo(game, before);
+                let critical = advance_turn(&tcod.transients.fov, game);
+                if game.config.screen_effects {
+                    if !game.objects[PLAYER].alive {
+                        tcod.transients.effects.flash();
+                    } else if !critical.is_empty() {
+                        tcod.transients.effects.shake();
+                    }
+                }
+            }
+
+            if was_alive && !game.objects[PLAYER].alive {
+                handle_player_death(game, &[SAVE_PATH, AUTOSAVE_PATH]);
+            }
+        }
+    }
+
+    PauseChoice::MainMenu
+}
+
+/// Deal with the saves at `paths` once the player has died: permadeath
+/// deletes them, a casual game keeps the last one to go back to.
+fn handle_player_death(game: &mut Game, paths: &[&str]) {
+    match game.save_mode {
+        SaveMode::Casual => game.log.add(t("death_casual", &[]), colors::LIGHT_GREY),
+        SaveMode::Permadeath => match delete_saves(paths) {
+            Ok(()) => game.log.add(t("death_permadeath", &[]), colors::LIGHT_GREY),
+            Err(e) => {
+                diag("error", &format!("deleting saves: {}", e));
+                game.log
+                    .add(t("save_delete_failed", &[("error", &e)]), colors::RED);
+            }
+        },
+    }
+}
+
+/// remove the saves at `paths`, those of them that are there
+fn delete_saves(paths: &[&str]) -> io::Result<()> {
+    for path in paths {
+        match fs::remove_file(path) {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Drain every input event that came in since the last frame: the mouse is
+/// tracked and key presses are queued up in order, so quick successive moves
+/// at a low frame rate aren't lost.
+fn poll_input(tcod: &mut Tcod) {
+    while let Some((_, event)) = input::check_for_event(input::MOUSE | input::KEY) {
+        match event {
+            Event::Mouse(m) => tcod.mouse = m,
+            Event::Key(k) if k.pressed => {
+                if is_movement_key(k.code) {
+                    tcod.transients.key_repeat.press(k);
+                } else {
+                    // any other command (which may open a menu and swallow the
+                    // release event) stops the repeat
+                    tcod.transients.key_repeat = KeyRepeat::default();
+                }
+                if tcod.transients.key_queue.len() < KEY_QUEUE_CAPACITY {
+                    tcod.transients.key_queue.push_back(k);
+                }
+            }
+            Event::Key(k) => tcod.transients.key_repeat.release(k.code),
+        }
+    }
+}
+
+/// Let the rest of the house catch up with one turn of the player's. Returns
+/// the needs that just turned critical.
+fn advance_turn(fov: &FovMap, game: &mut Game) -> Vec<Need> {
+    game.turn += 1;
+    update_weather(game);
+    update_events(game);
+    let critical = tick_needs(game);
+    give_advice(game);
+    tick_npc_needs(game);
+    npc_turns(fov, game);
+    update_furniture_occupancy(game);
+    // guests that made it back out the door are done visiting
+    game.objects.retain(|object| {
+        !matches!(
+            object.ai,
+            Some(Ai::Guest {
+                stage: GuestStage::Gone,
+                ..
+            })
+        )
+    });
+    maybe_invite_guest(fov, game);
+    tick_timers(game);
+
+    // don't replace the last save with one of the player's corpse
+    if game.objects[PLAYER].alive && should_autosave(game.turn, game.config.autosave_interval) {
+        match save_game_to(game, AUTOSAVE_PATH) {
+            Ok(()) => game.mark_saved(),
+            Err(e) => game
+                .log
+                .add(t("autosave_failed", &[("error", &e)]), colors::RED),
+        }
+    }
+    critical
+}
+
+/// Count down every object's timers by a turn, forgetting the ones that run out.
+fn tick_timers(game: &mut Game) {
+    for object in game.objects.iter_mut().chain(game.inventory.iter_mut()) {
+        for turns in object.timers.values_mut() {
+            *turns -= 1;
+        }
+        object.timers.retain(|_, turns| *turns > 0);
+    }
+}
+
+/// Everything that can go wrong while saving, loading or exporting a game.
+#[derive(Debug)]
+enum LardumError {
+    Io(io::Error),
+    Serde(serde_json::Error),
+    SaveCorrupt(String),
+    NoSave,
+    Cancelled,
+    BadConfig(String),
+    GameOver, // a permadeath game whose player has died
+    MissingFont(String),
+}
+
+impl fmt::Display for LardumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LardumError::Io(ref e) => write!(f, "I/O error: {}", e),
+            LardumError::Serde(ref e) => write!(f, "serialization error: {}", e),
+            LardumError::SaveCorrupt(ref reason) => write!(f, "the save is corrupt: {}", reason),
+            LardumError::NoSave => write!(f, "no saved game found"),
+            LardumError::Cancelled => write!(f, "cancelled"),
+            LardumError::BadConfig(ref reason) => write!(f, "invalid configuration: {}", reason),
+            LardumError::GameOver => write!(f, "that game is over"),
+            LardumError::MissingFont(ref path) => write!(
+                f,
+                "the font {} is missing; run lardum from the directory that holds its assets folder",
+                path
+            ),
+        }
+    }
+}
+
+impl Error for LardumError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LardumError::Io(ref e) => Some(e),
+            LardumError::Serde(ref e) => Some(e),
+            LardumError::SaveCorrupt(_)
+            | LardumError::NoSave
+            | LardumError::Cancelled
+            | LardumError::BadConfig(_)
+            | LardumError::GameOver
+            | LardumError::MissingFont(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for LardumError {
+    fn from(e: io::Error) -> Self {
+        LardumError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for LardumError {
+    fn from(e: serde_json::Error) -> Self {
+        LardumError::Serde(e)
+    }
+}
+
+fn save_game(game: &Game) -> Result<(), LardumError> {
+    save_game_to(game, SAVE_PATH)
+}
+
+/// Save to `path` atomically: the game is written to a temporary file first
+/// and then renamed over the old save, so a crash never leaves half a save.
+fn save_game_to(game: &Game, path: &str) -> Result<(), LardumError> {
+    let save_file = SaveFile {
+        thumbnail: thumbnail(game),
+        game: game,
+    };
+    let save_data = serde_json::to_string(&save_file)?;
+    let tmp_path = format!("{}.tmp", path);
+    let saved = with_retries(SAVE_ATTEMPTS, SAVE_RETRY_DELAY_MS, || {
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(save_data.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    });
+    match saved {
+        Ok(()) => diag("save", &format!("path={} turn={}", path, game.turn)),
+        Err(ref e) => diag("error", &format!("saving {}: {}", path, e)),
+    }
+    saved?;
+    Ok(())
+}
+
+/// Run `op` up to `attempts` times until it succeeds, waiting a little longer
+/// after each failure, so a file that is locked for a moment doesn't cost the
+/// save. Returns the last error if every attempt fails.
+fn with_retries<T>(
+    attempts: u32,
+    delay_ms: u64,
+    mut op: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = delay_ms;
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= attempts => return Err(e),
+            Err(_) => {
+                thread::sleep(Duration::from_millis(delay));
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// What goes into a save file: the game, plus a small picture of the map so
+/// the load menu can tell saves apart. Loading a `Game` skips the picture.
+#[derive(Serialize)]
+struct SaveFile<'a> {
+    thumbnail: Vec<String>,
+    #[serde(flatten)]
+    game: &'a Game,
+}
+
+/// Just enough of a save to show it in the load menu.
+#[derive(Deserialize)]
+struct SavePreview {
+    #[serde(default)]
+    thumbnail: Vec<String>,
+    #[serde(default)]
+    turn: u32,
+    dungeon_level: u32,
+}
+
+/// A miniature of the explored map, one character per `THUMBNAIL_SCALE`
+/// square of tiles: `@` where the player is, `.` where there's explored
+/// floor, `#` for explored walls and blank for the unknown.
+fn thumbnail(game: &Game) -> Vec<String> {
+    let (player_x, player_y) = game.objects[PLAYER].pos();
+    (0..MAP_HEIGHT / THUMBNAIL_SCALE)
+        .map(|row| {
+            (0..MAP_WIDTH / THUMBNAIL_SCALE)
+                .map(|column| {
+                    let xs = column * THUMBNAIL_SCALE..(column + 1) * THUMBNAIL_SCALE;
+                    let ys = row * THUMBNAIL_SCALE..(row + 1) * THUMBNAIL_SCALE;
+                    if xs.contains(&player_x) && ys.contains(&player_y) {
+                        return '@';
+                    }
+                    let tiles: Vec<&Tile> = xs
+                        .flat_map(|x| ys.clone().map(move |y| (x, y)))
+                        .map(|(x, y)| &game.map[x as usize][y as usize])
+                        .filter(|tile| tile.explored)
+                        .collect();
+                    if tiles.is_empty() {
+                        ' '
+                    } else if tiles.iter().any(|tile| !tile.blocked) {
+                        '.'
+                    } else {
+                        '#'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn load_preview(path: &str) -> Option<SavePreview> {
+    let json = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Ask which save to continue when there's more than one, showing each one's
+/// thumbnail. Returns the path of the chosen save.
+fn choose_save(tcod: &mut Tcod) -> Result<&'static str, LardumError> {
+    let newest = newest_save().ok_or(LardumError::NoSave)?;
+    // the newest one goes first
+    let mut paths = vec![newest];
+    paths.extend(
+        [SAVE_PATH, AUTOSAVE_PATH]
+            .iter()
+            .filter(|&&path| path != newest && fs::metadata(path).is_ok()),
+    );
+    if paths.len() == 1 {
+        return Ok(newest);
+    }
+
+    tcod.root.clear();
+    let mut options = vec![];
+    for (index, &path) in paths.iter().enumerate() {
+        let name = t(
+            if path == AUTOSAVE_PATH {
+                "save_slot_auto"
+            } else {
+                "save_slot_manual"
+            },
+            &[],
+        );
+        let option = match load_preview(path) {
+            Some(preview) => {
+                // draw the thumbnails side by side across the top of the screen
+                let x = 2 + index as i32 * (MAP_WIDTH / THUMBNAIL_SCALE + 4);
+                tcod.root.set_default_foreground(colors::LIGHT_GREY);
+                for (row, line) in preview.thumbnail.iter().enumerate() {
+                    tcod.root.print_ex(
+                        x,
+                        3 + row as i32,
+                        BackgroundFlag::None,
+                        TextAlignment::Left,
+                        line,
+                    );
+                }
+                let label = format!("({}) {}", (b'a' + index as u8) as char, name);
+                tcod.root.set_default_foreground(colors::WHITE);
+                tcod.root
+                    .print_ex(x, 1, BackgroundFlag::None, TextAlignment::Left, label);
+                t(
+                    "save_slot",
+                    &[
+                        ("name", &name),
+                        ("turn", &preview.turn),
+                        ("level", &preview.dungeon_level),
+                    ],
+                )
+            }
+            None => name,
+        };
+        options.push(option);
+    }
+
+    let header = format!("{}\n", t("menu_choose_save", &[]));
+    match menu(&header, &options, 40, tcod) {
+        Some(index) => Ok(paths[index]),
+        None => Err(LardumError::Cancelled),
+    }
+}
+
+/// whether the turn that just ended is due for an auto-save
+fn should_autosave(turn: u32, interval: u32) -> bool {
+    interval > 0 && turn > 0 && turn % interval == 0
+}
+
+/// the most recently written save file, manual or automatic
+fn newest_save() -> Option<&'static str> {
+    let modified = |path: &str| fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(SAVE_PATH), modified(AUTOSAVE_PATH)) {
+        (Some(manual), Some(auto)) if auto > manual => Some(AUTOSAVE_PATH),
+        (Some(_), _) => Some(SAVE_PATH),
+        (None, Some(_)) => Some(AUTOSAVE_PATH),
+        (None, None) => None,
+    }
+}
+
+fn load_game(path: &str) -> Result<Game, LardumError> {
+    let mut json_save_state = String::new();
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Err(LardumError::NoSave),
+        Err(e) => return Err(e.into()),
+    };
+    file.read_to_string(&mut json_save_state)?;
+    let mut game = serde_json::from_str::<Game>(&json_save_state)
+        .map_err(|e| LardumError::SaveCorrupt(e.to_string()))?;
+    tag_untagged_objects(&mut game.objects);
+    game.assign_object_ids();
+    // with permadeath there's no going back to a game that has ended
+    let dead = game
+        .objects
+        .get(PLAYER)
+        .map_or(false, |player| !player.alive);
+    if game.save_mode == SaveMode::Permadeath && dead {
+        return Err(LardumError::GameOver);
+    }
+    validate_and_repair(&mut game);
+    game.rebuild_equipped();
+    // saves from before the codex still know what the player is carrying
+    for kind in game.inventory.iter().filter_map(|item| item.item) {
+        game.codex.add_item(kind);
+    }
+    game.mark_saved();
+    Ok(game)
+}
+
+/// Fix up equipment a hand-edited or old save got wrong: only items in the
+/// inventory can be equipped, and only one per slot (the first one listed
+/// keeps it). Everything fixed is logged.
+fn validate_and_repair(game: &mut Game) {
+    let mut fixed = vec![];
+
+    for object in game.objects.iter_mut() {
+        if let Some(equipment) = object.equipment.as_mut() {
+            if equipment.equipped {
+                equipment.equipped = false;
+                fixed.push(t("repair_orphaned_equipment", &[("item", &object.name)]));
+            }
+        }
+    }
+
+    let mut taken_slots = vec![];
+    for item in game.inventory.iter_mut() {
+        if let Some(equipment) = item.equipment.as_mut() {
+            if !equipment.equipped {
+                continue;
+            }
+            if taken_slots.contains(&equipment.slot) {
+                equipment.equipped = false;
+                fixed.push(t(
+                    "repair_double_equipped",
+                    &[("item", &item.name), ("slot", &equipment.slot)],
+                ));
+            } else {
+                taken_slots.push(equipment.slot);
+            }
+        }
+    }
+
+    // two blockers sharing a tile break movement; the first one (the player,
+    // if involved) stays put and the rest get nudged aside
+    for index in 0..game.objects.len() {
+        if !game.objects[index].blocks {
+            continue;
+        }
+        let footprint = game.objects[index].footprint();
+        let overlaps = game.objects[..index]
+            .iter()
+            .any(|other| other.blocks && footprint.iter().any(|&(x, y)| other.covers(x, y)));
+        if !overlaps {
+            continue;
+        }
+        if let Some((x, y)) = free_spot_near(game, index) {
+            game.objects[index].set_pos(x, y);
+            fixed.push(t(
+                "repair_overlap",
+                &[("name", &game.objects[index].name), ("x", &x), ("y", &y)],
+            ));
+        }
+    }
+
+    for message in fixed {
+        game.log.add(message, colors::ORANGE);
+    }
+}
+
+/// An adjacent position where the whole footprint of the object fits without
+/// touching walls or any other blocking object.
+fn free_spot_near(game: &Game, index: usize) -> Option<(i32, i32)> {
+    let object = &game.objects[index];
+    let (x, y) = object.pos();
+    for dy in -1..2 {
+        for dx in -1..2 {
+            if (dx, dy) == (0, 0) {
+                continue;
+            }
+            let fits = object.footprint().iter().all(|&(tile_x, tile_y)| {
+                let (tile_x, tile_y) = (tile_x + dx, tile_y + dy);
+                in_map(tile_x, tile_y)
+                    && !game.map[tile_x as usize][tile_y as usize].blocked
+                    && !game.objects.iter().enumerate().any(|(other, object)| {
+                        other != index && object.blocks && object.covers(tile_x, tile_y)
+                    })
+            });
+            if fits {
+                return Some((x + dx, y + dy));
+            }
+        }
+    }
+    None
+}
+
+/// Saves from before tags existed have none; work them out from the objects.
+fn tag_untagged_objects(objects: &mut [Object]) {
+    for object in objects.iter_mut().filter(|object| object.tags.is_empty()) {
+        if object.name == "stairs" {
+            object.add_tag(TAG_STAIRS);
+        }
+        if object.furniture.is_some() {
+            object.add_tag(TAG_FURNITURE);
+        }
+        let npc = object.ai.is_some() && object.ai != Some(Ai::Follow);
+        if object.furniture.is_some() || object.door.is_some() || npc {
+            object.add_tag(TAG_INTERACTIVE);
+        }
+    }
+}
+
+/// Render the map and the objects on it as plain text, one line per row: `#`
+/// for walls, `.` for floor and each object's own character on top.
+fn map_to_ascii(game: &Game) -> String {
+    let mut rows: Vec<Vec<char>> = (0..MAP_HEIGHT)
+        .map(|y| {
+            (0..MAP_WIDTH)
+                .map(|x| {
+                    if game.map[x as usize][y as usize].blocked {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect();
+
+    // higher layers (like the player) go last so they end up on top
+    let mut to_draw: Vec<_> = game.objects.iter().collect();
+    to_draw.sort_by(|o1, o2| draw_order(o1, o2));
+    for object in to_draw {
+        for (x, y) in object.footprint() {
+            if in_map(x, y) {
+                rows[y as usize][x as usize] = object.char;
+            }
+        }
+    }
+
+    let mut ascii = String::new();
+    for row in rows {
+        ascii.extend(row);
+        ascii.push('\n');
+    }
+    ascii
+}
+
+/// Write an ASCII dump of the current map to `path`, for debugging and sharing layouts.
+fn export_map(game: &Game, path: &str) -> Result<(), LardumError> {
+    let mut file = File::create(path)?;
+    file.write_all(map_to_ascii(game).as_bytes())?;
+    Ok(())
+}
+
+fn main_menu(tcod: &mut Tcod, config: &mut Config) {
+    while !tcod.root.window_closed() {
+        tcod.root.set_default_foreground(colors::LIGHT_YELLOW);
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT / 2 - 4,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "LARDUM",
+        );
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT - 2,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            "By Matt Fisher",
+        );
+
+        // show options and wait for the player's choice
+        let choices = &[
+            t("menu_new_game", &[]),
+            t("menu_continue", &[]),
+            t("menu_quit", &[]),
+        ];
+        let choice = menu("", choices, 24, tcod);
+
+        match choice {
+            Some(0) => {
+                // new game
+                let choice = difficulty_menu(tcod).and_then(|difficulty| {
+                    tutorial_menu(tcod).and_then(|tutorial| {
+                        save_mode_menu(tcod).map(|mode| (difficulty, tutorial, mode))
+                    })
+                });
+                if let Some((difficulty, tutorial, mode)) = choice {
+                    let level = config.start_level;
+                    let seed = config.seed.clone();
+                    let mut game = new_game(
+                        tcod,
+                        difficulty,
+                        level,
+                        tutorial,
+                        seed.as_deref(),
+                        config.clone(),
+                        vec![],
+                    );
+                    game.save_mode = mode;
+                    let choice = play_game(&mut game, tcod);
+                    // keep any options changed during the game
+                    *config = game.config;
+                    if choice == PauseChoice::Quit {
+                        break;
+                    }
+                }
+            }
+            Some(1) => {
+                // pick the manual or the automatic save, if there are both
+                match choose_save(tcod).and_then(load_game) {
+                    Ok(mut game) => {
+                        diag(
+                            "load",
+                            &format!("seed={} level={}", game.seed_label(), game.dungeon_level),
+                        );
+                        game.config = config.clone();
+                        tcod.reset_transients();
+                        initialise_fov(&game.map, tcod);
+                        let choice = play_game(&mut game, tcod);
+                        *config = game.config;
+                        if choice == PauseChoice::Quit {
+                            break;
+                        }
+                    }
+                    Err(LardumError::Cancelled) => continue,
+                    Err(LardumError::NoSave) => {
+                        msgbox(&format!("\n{}\n", t("menu_no_save", &[])), 24, tcod);
+                        continue;
+                    }
+                    Err(e) => {
+                        diag("error", &format!("loading: {}", e));
+                        msgbox(
+                            &format!("\n{}\n", t("menu_load_failed", &[("error", &e)])),
+                            40,
+                            tcod,
+                        );
+                        continue;
+                    }
+                }
+            }
+            Some(2) => {
+                // quit
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Make sure the font is where tcod will look for it. tcod can't report a
+/// missing font in any helpful way, so this has to happen before it starts.
+fn check_font(path: &str) -> Result<(), LardumError> {
+    if Path::new(path).is_file() {
+        Ok(())
+    } else {
+        Err(LardumError::MissingFont(path.to_string()))
+    }
+}
+
+fn main() {
+    let mut config = match load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("lardum: {}", e);
+            process::exit(1);
+        }
+    };
+    if let Err(e) = set_language(&config.language) {
+        eprintln!("lardum: {}", e);
+        process::exit(1);
+    }
+    if config.diagnostic_log {
+        // the game runs fine without it, so don't give up over it
+        if let Err(e) = open_diag_log(DIAG_LOG_PATH) {
+            eprintln!("lardum: {}: {}", DIAG_LOG_PATH, e);
+        }
+    }
+
+    if let Err(e) = check_font(FONT_PATH) {
+        eprintln!("lardum: {}", e);
+        process::exit(1);
+    }
+
+    let root = Root::initializer()
+        .font(FONT_PATH, FontLayout::Tcod)
+        .font_type(FontType::Greyscale)
+        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .title("Lardum")
+        .init();
+    tcod::system::set_fps(LIMIT_FPS);
+
+    let mut tcod = Tcod {
+        root: root,
+        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
+        // as big as the panel can be configured to get
+        panel: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT / 2),
+        mouse: Default::default(),
+        transients: Transients::new(),
+        overlays: Overlays::new(config.overlay_alpha, config.overlay_dim),
+    };
+
+    main_menu(&mut tcod, &mut config);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a game on a fixed seed, with the default config
+    fn test_game() -> Game {
+        create_game(
+            Difficulty::Normal,
+            1,
+            false,
+            Some("test"),
+            Config::default(),
+            vec![],
+        )
+    }
+
+    /// the test game with the map walled up except for one room, (2, 2) to
+    /// (5, 3), and nothing in it but the player, in its top left corner, with
+    /// an empty inventory and log
+    fn one_room_game() -> Game {
+        let mut game = test_game();
+        game.inventory.clear();
+        game.equipped.clear();
+        game.log.clear();
+        game.map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        create_room(Rect::new(1, 1, 5, 3), &mut game.map);
+        game.objects.truncate(1);
+        game.objects[PLAYER].set_pos(2, 2);
+        game.rooms = vec![Rect::new(1, 1, 5, 3)];
+        game
+    }
+
+    /// a housemate like the ones the map generator places
+    fn npc_at(x: i32, y: i32) -> Object {
+        let mut npc = Object::new(x, y, '@', "Alex", colors::LIGHT_BLUE, true);
+        npc.alive = true;
+        npc.stats = Some(Stats {
+            base_max_all_stats: 100,
+            hunger: 100,
+            comfort: 100,
+            hygiene: 100,
+            bladder: 100,
+            energy: 100,
+            fun: 100,
+            social: 100,
+            room: 100,
+            on_death: DeathCallback::NPC,
+        });
+        npc.ai = Some(Ai::Basic);
+        npc.add_tag(TAG_INTERACTIVE);
+        npc
+    }
+
+    /// let `turns` turns go by as far as the player's needs are concerned
+    fn tick_turns(game: &mut Game, turns: u32) {
+        for _ in 0..turns {
+            game.turn += 1;
+            tick_needs(game);
+        }
+    }
+
+    fn total_needs(game: &Game) -> i32 {
+        need_values(&game.objects[PLAYER].stats.unwrap())
+            .iter()
+            .sum()
+    }
+
+    fn fov_for(map: &Map) -> FovMap {
+        let mut fov = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                let tile = &map[x as usize][y as usize];
+                fov.set(x, y, !tile.block_sight, !tile.blocked);
+            }
+        }
+        fov
+    }
+
+    #[test]
+    fn partial_repaint_matches_a_full_repaint() {
+        let mut game = test_game();
+        let mut fov = fov_for(&game.map);
+        let mut partial = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+        let mut full = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+
+        let (x, y) = game.objects[PLAYER].pos();
+        fov.compute_fov(x, y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        paint_tiles(&mut partial, &fov, &mut game, None, None, false);
+        paint_tiles(&mut full, &fov, &mut game, None, None, false);
+
+        // take a few steps and repaint after each, one way and the other
+        let mut lit_from = (x, y);
+        for &(dx, dy) in &[(1, 0), (1, 1), (0, 1), (-1, 0)] {
+            let (x, y) = game.objects[PLAYER].pos();
+            if !is_wall(&game.map, x + dx, y + dy) {
+                game.objects[PLAYER].set_pos(x + dx, y + dy);
+            }
+            let (x, y) = game.objects[PLAYER].pos();
+            fov.compute_fov(x, y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+            paint_tiles(&mut partial, &fov, &mut game, Some(lit_from), None, false);
+            paint_tiles(&mut full, &fov, &mut game, None, None, false);
+            lit_from = (x, y);
+
+            for x in 0..MAP_WIDTH {
+                for y in 0..MAP_HEIGHT {
+                    assert_eq!(
+                        partial.get_char_background(x, y),
+                        full.get_char_background(x, y),
+                        "tile ({}, {})",
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ascii_export_shows_walls_floor_and_objects() {
+        let mut game = one_room_game();
+        let mut stairs = Object::new(5, 3, '<', "stairs", colors::WHITE, false);
+        stairs.render_layer = RenderLayer::Floor;
+        game.objects.push(stairs);
+
+        let ascii = map_to_ascii(&game);
+        let corner: Vec<String> = ascii
+            .lines()
+            .take(5)
+            .map(|line| line.chars().take(8).collect())
+            .collect();
+        assert_eq!(
+            corner,
+            vec!["########", "########", "##@...##", "##...<##", "########"]
+        );
+        assert_eq!(ascii.lines().count(), MAP_HEIGHT as usize);
+        assert!(ascii.lines().all(|line| line.len() == MAP_WIDTH as usize));
+    }
+
+    #[test]
+    fn talking_raises_social_once_per_cooldown() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(3, 2));
+        game.objects[PLAYER].stats.as_mut().unwrap().social = 10;
+
+        talk_to(1, &mut game);
+        assert_eq!(
+            game.objects[PLAYER].stats.unwrap().social,
+            10 + TALK_SOCIAL_GAIN
+        );
+
+        // straight away again, they've had enough
+        talk_to(1, &mut game);
+        assert_eq!(
+            game.objects[PLAYER].stats.unwrap().social,
+            10 + TALK_SOCIAL_GAIN
+        );
+
+        for _ in 0..TALK_COOLDOWN {
+            tick_timers(&mut game);
+        }
+        talk_to(1, &mut game);
+        assert_eq!(
+            game.objects[PLAYER].stats.unwrap().social,
+            10 + 2 * TALK_SOCIAL_GAIN
+        );
+    }
+
+    #[test]
+    fn hard_drains_needs_faster_than_easy() {
+        let mut easy = one_room_game();
+        easy.difficulty = Difficulty::Easy;
+        easy.config.grace_turns = 0;
+        let mut hard = one_room_game();
+        hard.difficulty = Difficulty::Hard;
+        hard.config.grace_turns = 0;
+
+        tick_turns(&mut easy, 100);
+        tick_turns(&mut hard, 100);
+        assert!(total_needs(&hard) < total_needs(&easy));
+    }
+
+    #[test]
+    fn errors_say_what_went_wrong() {
+        let io = LardumError::from(io::Error::new(io::ErrorKind::NotFound, "gone"));
+        assert_eq!(io.to_string(), "I/O error: gone");
+        let serde = LardumError::from(serde_json::from_str::<u32>("nope").unwrap_err());
+        assert!(serde.to_string().starts_with("serialization error: "));
+        assert_eq!(
+            LardumError::SaveCorrupt("no player".into()).to_string(),
+            "the save is corrupt: no player"
+        );
+        assert_eq!(LardumError::NoSave.to_string(), "no saved game found");
+        assert_eq!(LardumError::Cancelled.to_string(), "cancelled");
+        assert_eq!(
+            LardumError::BadConfig("bad".into()).to_string(),
+            "invalid configuration: bad"
+        );
+        assert_eq!(LardumError::GameOver.to_string(), "that game is over");
+        assert!(LardumError::MissingFont("font.png".into())
+            .to_string()
+            .starts_with("the font font.png is missing"));
+    }
+
+    #[test]
+    fn a_loaded_game_generates_the_same_next_level() {
+        let mut game = test_game();
+        // use the RNG a little first, as playing would
+        for _ in 0..10 {
+            game.rng.gen_range(0..100);
+        }
+        let saved = serde_json::to_string(&game).unwrap();
+        let mut loaded: Game = serde_json::from_str(&saved).unwrap();
+
+        let next_level = |game: &mut Game| {
+            let params = game.config.rooms.clone();
+            let (map, _) = make_map(
+                &mut game.objects,
+                2,
+                game.difficulty,
+                game.biome,
+                &params,
+                &mut [],
+                &mut game.rng,
+            );
+            game.map = map;
+            map_to_ascii(game)
+        };
+        assert_eq!(next_level(&mut game), next_level(&mut loaded));
+    }
+
+    #[test]
+    fn off_the_map_counts_as_blocked() {
+        let mut game = one_room_game();
+        let map = game.map.clone();
+        for &(x, y) in &[
+            (-1, 0),
+            (0, -1),
+            (MAP_WIDTH, 0),
+            (0, MAP_HEIGHT),
+            (i32::MIN, i32::MAX),
+        ] {
+            assert!(is_blocked(x, y, &map, &game.objects), "({}, {})", x, y);
+        }
+        // the edges themselves are in the map, and walls here
+        assert!(is_blocked(0, 0, &map, &game.objects));
+        assert!(is_blocked(
+            MAP_WIDTH - 1,
+            MAP_HEIGHT - 1,
+            &map,
+            &game.objects
+        ));
+        assert!(!is_blocked(3, 3, &map, &game.objects));
+
+        // walking off the map stays put instead of panicking
+        game.map[0][0] = Tile::empty();
+        game.objects[PLAYER].set_pos(0, 0);
+        move_by(PLAYER, -1, -1, &game.map, &mut game.objects);
+        assert_eq!(game.objects[PLAYER].pos(), (0, 0));
+    }
+
+    #[test]
+    fn switching_language_changes_strings_and_falls_back_to_english() {
+        let english = t("welcome", &[]);
+        let mut other = HashMap::new();
+        other.insert("welcome".to_string(), "Bienvenue chez vous !".to_string());
+        // the table is per thread, so this doesn't leak into other tests
+        STRINGS.with(|table| table.borrow_mut().strings = other);
+
+        assert_eq!(t("welcome", &[]), "Bienvenue chez vous !");
+        assert_eq!(t("cancelled", &[]), "Cancelled");
+        assert_eq!(t("no_such_message", &[]), "no_such_message");
+
+        set_language("en").unwrap();
+        assert_eq!(t("welcome", &[]), english);
+        assert!(set_language("no-such-language").is_err());
+    }
+
+    #[test]
+    fn undo_restores_position_and_needs() {
+        let mut game = one_room_game();
+        game.config.grace_turns = 0;
+        let before_pos = game.objects[PLAYER].pos();
+        let before_needs = need_values(&game.objects[PLAYER].stats.unwrap());
+
+        let snapshot = UndoSnapshot::take(&game);
+        push_undo(&mut game, snapshot);
+        move_by(PLAYER, 1, 0, &game.map, &mut game.objects);
+        tick_turns(&mut game, 50);
+        assert_ne!(game.objects[PLAYER].pos(), before_pos);
+        assert_ne!(
+            need_values(&game.objects[PLAYER].stats.unwrap()),
+            before_needs
+        );
+
+        undo_turn(&mut game);
+        assert_eq!(game.objects[PLAYER].pos(), before_pos);
+        assert_eq!(
+            need_values(&game.objects[PLAYER].stats.unwrap()),
+            before_needs
+        );
+        assert_eq!(game.turn, 0);
+        assert!(game.undo.is_empty());
+    }
+
+    #[test]
+    fn torch_flicker_stays_in_bounds() {
+        for frame in 0..20_000 {
+            let brightness = torch_flicker(frame);
+            assert!(
+                brightness >= 1.0 - TORCH_FLICKER && brightness <= 1.0,
+                "frame {}: {}",
+                frame,
+                brightness
+            );
+        }
+        assert!(torch_flicker(u32::MAX) <= 1.0);
+    }
+
+    #[test]
+    fn handlers_fire_on_their_event() {
+        use std::rc::Rc;
+
+        let mut game = one_room_game();
+        game.config.grace_turns = 0;
+        let seen = Rc::new(RefCell::new(vec![]));
+        let log = Rc::clone(&seen);
+        game.on(move |event, _| {
+            if let GameEvent::NeedCritical(need) = *event {
+                log.borrow_mut().push(need);
+            }
+        });
+
+        game.objects[PLAYER].stats.as_mut().unwrap().energy = CRITICAL_NEED;
+        tick_turns(&mut game, 50);
+        assert_eq!(*seen.borrow(), vec![Need::Energy]);
+
+        // the rest don't reach the handler
+        game.emit(GameEvent::LevelDescended(2));
+        assert_eq!(seen.borrow().len(), 1);
+    }
+
+    #[test]
+    fn sleeping_lasts_until_wake_time_and_restores_energy() {
+        let mut game = one_room_game();
+        game.config.autosave_interval = 0;
+        game.objects.push(make_furniture(FurnitureKind::Bed, 4, 3));
+        // late in the evening, so the night goes past midnight
+        game.turn = minutes_until(START_TIME, 23 * 60);
+        game.objects[PLAYER].stats.as_mut().unwrap().energy = 30;
+
+        let fov = fov_for(&game.map);
+        sleep_until_morning(1, &mut game, &fov);
+        assert_eq!(clock_minutes(game.turn), game.config.wake_hour * 60);
+        assert!(game.objects[PLAYER].stats.unwrap().energy > 30);
+        // and the bed is free again
+        assert_eq!(game.objects[1].furniture.unwrap().occupied_by, None);
+    }
+
+    #[test]
+    fn repair_unequips_the_second_item_in_a_slot() {
+        let mut game = one_room_game();
+        for &item in &[Item::Shield, Item::Dagger] {
+            let mut object = make_item(item, 0, 0);
+            object.equipment.as_mut().unwrap().equipped = true;
+            game.inventory.push(object);
+        }
+        validate_and_repair(&mut game);
+        let equipped: Vec<bool> = game
+            .inventory
+            .iter()
+            .map(|item| item.equipment.unwrap().equipped)
+            .collect();
+        assert_eq!(equipped, vec![true, false]);
+        assert_eq!(game.log.len(), 1);
+    }
+
+    #[test]
+    fn repair_unequips_equipment_lying_on_the_map() {
+        let mut game = one_room_game();
+        let mut sword = make_item(Item::Sword, 3, 3);
+        sword.equipment.as_mut().unwrap().equipped = true;
+        game.objects.push(sword);
+        validate_and_repair(&mut game);
+        assert!(!game.objects[1].equipment.unwrap().equipped);
+        assert_eq!(game.log.len(), 1);
+    }
+
+    #[test]
+    fn camera_only_scrolls_when_leaving_the_dead_zone() {
+        // the view is as wide as the map, so only rows scroll: with a 30 row
+        // view and a 10 row dead zone, that's map rows 20 to 29 from here
+        let mut camera = Camera { x: 0, y: 10 };
+        camera.follow((50, 25), 30, 20, 10);
+        assert_eq!(camera, Camera { x: 0, y: 10 });
+        camera.follow((50, 29), 30, 20, 10);
+        assert_eq!(camera, Camera { x: 0, y: 10 });
+
+        // one step past the bottom of the zone scrolls by one
+        camera.follow((50, 30), 30, 20, 10);
+        assert_eq!(camera, Camera { x: 0, y: 11 });
+        camera.follow((50, 15), 30, 20, 10);
+        assert_eq!(camera, Camera { x: 0, y: 5 });
+
+        // but never past the edges of the map
+        camera.follow((50, 0), 30, 20, 10);
+        assert_eq!(camera.y, 0);
+        camera.follow((50, MAP_HEIGHT - 1), 30, 20, 10);
+        assert_eq!(camera.y, MAP_HEIGHT - 30);
+    }
+
+    #[test]
+    fn ids_survive_swap_remove() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(3, 2));
+        game.objects.push(npc_at(4, 2));
+        game.objects.push(npc_at(5, 2));
+        game.assign_object_ids();
+        let last = game.objects[3].id;
+        let removed = game.objects[1].id;
+
+        // the last object moves into the gap, but its id still finds it
+        game.objects.swap_remove(1);
+        assert_eq!(game.index_of(last), Some(1));
+        assert_eq!(game.objects[1].id, last);
+        assert_eq!(game.index_of(removed), None);
+
+        // new objects get fresh ids, never one that was used before
+        game.objects.push(npc_at(3, 3));
+        game.assign_object_ids();
+        assert!(game.objects[3].id > last);
+    }
+
+    #[test]
+    fn cold_weather_drains_comfort_faster() {
+        let mut clear = one_room_game();
+        clear.config.grace_turns = 0;
+        let mut cold = one_room_game();
+        cold.config.grace_turns = 0;
+        cold.weather = Weather::Cold;
+
+        tick_turns(&mut clear, 100);
+        tick_turns(&mut cold, 100);
+        let comfort = |game: &Game| game.objects[PLAYER].stats.unwrap().comfort;
+        assert!(comfort(&cold) < comfort(&clear));
+        // and leaves the needs it has nothing to do with alone
+        let fun = |game: &Game| game.objects[PLAYER].stats.unwrap().fun;
+        assert_eq!(fun(&cold), fun(&clear));
+    }
+
+    #[test]
+    fn stat_bars_go_where_they_were_hand_placed() {
+        let left = SCREEN_WIDTH - BAR_WIDTH * 2 - BAR_LEFT_PADDING;
+        let right = SCREEN_WIDTH - BAR_WIDTH;
+        let expected: Vec<(i32, i32)> = [left, right]
+            .iter()
+            .flat_map(|&x| (1..5).map(move |row| (x, BAR_TOP_PADDING + row)))
+            .collect();
+        let positions: Vec<(i32, i32)> = (0..STAT_BARS.len())
+            .map(|index| stat_bar_position(index, STAT_BARS.len()))
+            .collect();
+        assert_eq!(positions, expected);
+        let needs: Vec<Need> = STAT_BARS.iter().map(|bar| bar.need).collect();
+        assert_eq!(needs, Need::ALL.to_vec());
+    }
+
+    #[test]
+    fn queued_keys_are_all_handled_in_order() {
+        let mut game = one_room_game();
+        let press = |code| Key {
+            code: code,
+            pressed: true,
+            ..Default::default()
+        };
+        let mut queue: VecDeque<Key> = vec![press(KeyCode::Right), press(KeyCode::Down)].into();
+
+        // both presses of the same frame get through, one after the other
+        while let Some(key) = next_queued_key(&mut queue) {
+            let (dx, dy) = key_step(key.code).unwrap();
+            move_by(PLAYER, dx, dy, &game.map, &mut game.objects);
+        }
+        assert_eq!(game.objects[PLAYER].pos(), (3, 3));
+
+        // but not what's typed after a command that isn't a move
+        let mut queue: VecDeque<Key> = vec![press(KeyCode::Escape), press(KeyCode::Down)].into();
+        assert_eq!(next_queued_key(&mut queue).unwrap().code, KeyCode::Escape);
+        assert_eq!(next_queued_key(&mut queue), None);
+    }
+
+    #[test]
+    fn a_smelly_player_drives_npcs_away() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(4, 2));
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        // freshly washed, the NPC comes over for a chat
+        ai_basic(1, &fov, &mut game);
+        assert_eq!(game.objects[1].pos(), (3, 2));
+
+        game.objects[1].set_pos(4, 2);
+        game.objects[PLAYER].stats.as_mut().unwrap().hygiene = 0;
+        assert!(stink_radius(&game.objects[PLAYER]) >= 2.0);
+        ai_basic(1, &fov, &mut game);
+        assert_eq!(game.objects[1].pos(), (5, 2));
+    }
+
+    /// the rooms the generator makes with `params` for each of a few seeds
+    fn room_counts(params: &RoomParams) -> Vec<usize> {
+        let mut objects = test_game().objects;
+        (0..8)
+            .map(|seed| {
+                let mut rng = GameRng::new(seed);
+                let (_, rooms) = make_map(
+                    &mut objects,
+                    1,
+                    Difficulty::Normal,
+                    Biome::House,
+                    params,
+                    &mut [],
+                    &mut rng,
+                );
+                rooms.len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn room_params_change_the_layout() {
+        let small = RoomParams {
+            min_size: 3,
+            max_size: 5,
+            max_rooms: 60,
+            ..Default::default()
+        };
+        let large = RoomParams {
+            min_size: 12,
+            max_size: 20,
+            max_rooms: 8,
+            ..Default::default()
+        };
+        let average =
+            |counts: Vec<usize>| counts.iter().sum::<usize>() as f32 / counts.len() as f32;
+        assert!(average(room_counts(&small)) > average(room_counts(&large)) + 10.0);
+
+        let backwards = RoomParams {
+            min_size: 8,
+            max_size: 6,
+            ..Default::default()
+        };
+        assert!(backwards.validate().is_err());
+        let too_big = RoomParams {
+            max_size: MAP_HEIGHT,
+            ..Default::default()
+        };
+        assert!(too_big.validate().is_err());
+        assert!(RoomParams::default().validate().is_ok());
+    }
+
+    #[test]
+    fn guests_leave_after_their_visit() {
+        let mut game = one_room_game();
+        game.config.autosave_interval = 0;
+        let mut guest = npc_at(4, 3);
+        guest.ai = Some(Ai::Guest {
+            stage: GuestStage::Staying,
+            turns_left: 3,
+            exit: (5, 3),
+        });
+        game.objects.push(guest);
+        game.assign_object_ids();
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        let guests = |game: &Game| {
+            game.objects
+                .iter()
+                .filter(|object| matches!(object.ai, Some(Ai::Guest { .. })))
+                .count()
+        };
+        for _ in 0..3 {
+            advance_turn(&fov, &mut game);
+        }
+        assert_eq!(guests(&game), 1);
+        for _ in 0..3 {
+            advance_turn(&fov, &mut game);
+        }
+        assert_eq!(guests(&game), 0);
+    }
+
+    #[test]
+    fn fireballs_only_catch_whats_in_the_blast() {
+        let mut game = one_room_game();
+        game.objects[PLAYER].set_pos(20, 20);
+        game.objects.push(npc_at(12, 10));
+        game.objects.push(npc_at(10, 13));
+        game.objects.push(npc_at(14, 10));
+
+        let caught = objects_in_radius(&game.objects, (10, 10), FIREBALL_RADIUS);
+        assert_eq!(caught, vec![1, 2]);
+        for id in caught {
+            burn_object(id, &mut game);
+        }
+        let comfort = |id: usize| game.objects[id].stats.unwrap().comfort;
+        assert_eq!(comfort(1), 100 - FIREBALL_COMFORT_LOSS);
+        assert_eq!(comfort(2), 100 - FIREBALL_COMFORT_LOSS);
+        assert_eq!(comfort(3), 100);
+        assert_eq!(comfort(PLAYER), 100);
+    }
+
+    #[test]
+    fn inventory_header_shows_how_full_it_is() {
+        let mut game = one_room_game();
+        for _ in 0..3 {
+            game.inventory
+                .push(Object::new(0, 0, '!', "soap", colors::WHITE, false));
+        }
+        let header = inventory_header("Pick an item:", game.inventory.len());
+        assert!(header.starts_with("Pick an item:"));
+        assert!(header.contains("(3/26)"), "{:?}", header);
+    }
+
+    #[test]
+    fn seeded_placement_is_exact() {
+        // no Tcod anywhere, just a room and a seed
+        let mut game = one_room_game();
+        let room = Rect::new(10, 10, 12, 8);
+        create_room(room, &mut game.map);
+        let mut rng = GameRng::new(42);
+        place_objects(
+            room,
+            &game.map,
+            &mut game.objects,
+            4,
+            Difficulty::Normal,
+            Biome::House,
+            &mut rng,
+        );
+        let placed: Vec<_> = game.objects[1..]
+            .iter()
+            .map(|object| (object.name.as_str(), object.pos()))
+            .collect();
+        assert_eq!(placed.len(), 3);
+        assert_eq!(
+            placed,
+            vec![
+                ("fridge", (20, 15)),
+                ("Dina", (21, 11)),
+                ("scroll of confusion", (19, 12)),
+            ]
+        );
+        // and all of it inside the room, clear of its center
+        for &(_, (x, y)) in &placed {
+            assert!(x > room.x1 && x < room.x2 && y > room.y1 && y < room.y2);
+            assert_ne!((x, y), room.center());
+        }
+    }
+
+    #[test]
+    fn using_items_without_stats_doesnt_panic() {
+        let mut game = one_room_game();
+        game.objects[PLAYER].stats = None;
+
+        // cast_heal and friends give up here, before touching the screen
+        for &item in &[Item::Heal, Item::Lightning, Item::Confuse] {
+            assert!(!restore_player_need(item, &mut game));
+        }
+        drain_player_need(Need::Social, 10, &mut game);
+        tick_turns(&mut game, 5);
+        assert!(game.objects[PLAYER].stats.is_none());
+        assert!(game
+            .log
+            .iter()
+            .any(|(message, _)| *message == t("player_no_stats", &[])));
+    }
+
+    #[test]
+    fn furniture_has_one_user_at_a_time() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(4, 2));
+        game.objects.push(npc_at(4, 3));
+        game.objects.push(make_furniture(FurnitureKind::Bed, 5, 2));
+        game.assign_object_ids();
+        let (first, second) = (game.objects[1].id, game.objects[2].id);
+
+        let bed = game.objects[3].furniture.as_mut().unwrap();
+        assert!(bed.claim(first, 2));
+        assert!(!bed.claim(second, 2));
+        assert!(bed.occupied_by_other(second));
+        assert!(!bed.occupied_by_other(first));
+
+        // still in use after one turn, free once the first is done with it
+        update_furniture_occupancy(&mut game);
+        assert_eq!(game.objects[3].furniture.unwrap().occupied_by, Some(first));
+        update_furniture_occupancy(&mut game);
+        let bed = game.objects[3].furniture.as_mut().unwrap();
+        assert_eq!(bed.occupied_by, None);
+        assert!(bed.claim(second, 2));
+    }
+
+    #[test]
+    fn crafting_follows_the_recipes() {
+        let mut game = one_room_game();
+        for &item in &[Item::Heal, Item::Dagger, Item::Heal] {
+            game.inventory.push(make_item(item, 0, 0));
+        }
+
+        // a healing potion and a dagger don't go together
+        assert!(!craft(0, 1, &mut game));
+        assert_eq!(game.inventory.len(), 3);
+        // nor does an item with itself
+        assert!(!craft(0, 0, &mut game));
+        assert_eq!(game.inventory.len(), 3);
+
+        assert!(craft(2, 0, &mut game));
+        let items: Vec<_> = game.inventory.iter().map(|object| object.item).collect();
+        assert_eq!(items, vec![Some(Item::Dagger), Some(Item::Lightning)]);
+        assert_eq!(game.codex.recipes, vec![(Item::Heal, Item::Heal)]);
+    }
+
+    /// pick up each of `items`, in order, from under the player
+    fn pick_up_all(game: &mut Game, items: &[Item]) -> Vec<String> {
+        for &item in items {
+            game.objects.push(make_item(item, 2, 2));
+            let id = game.objects.len() - 1;
+            pick_item_up(id, game);
+        }
+        game.inventory
+            .iter()
+            .map(|object| object.name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn auto_sort_keeps_the_inventory_sorted() {
+        let items = [Item::Sword, Item::Heal, Item::Confuse, Item::Dagger];
+
+        let mut game = one_room_game();
+        let unsorted = pick_up_all(&mut game, &items);
+        let mut sorted = unsorted.clone();
+        sorted.sort();
+        assert_ne!(unsorted, sorted);
+
+        let mut game = one_room_game();
+        game.config.auto_sort_inventory = Some(SortKey::Name);
+        assert_eq!(pick_up_all(&mut game, &items), sorted);
+        // the sword and dagger were both put on as they came in
+        assert_eq!(game.equipped.len(), 2);
+        for &id in game.equipped.values() {
+            let index = game.inventory.iter().position(|o| o.id == id).unwrap();
+            assert!(game.inventory[index].equipment.unwrap().equipped);
+        }
+    }
+
+    #[test]
+    fn templates_furnish_the_players_room() {
+        let mut game = one_room_game();
+        let room = Rect::new(10, 10, 8, 6);
+        create_room(room, &mut game.map);
+        game.rooms.push(room);
+        game.objects[PLAYER].set_pos(16, 14);
+
+        let living_room = &default_room_templates()[2];
+        assert_eq!(apply_template(&mut game, living_room), 3);
+        let placed: Vec<_> = game.objects[1..]
+            .iter()
+            .map(|object| (object.furniture.unwrap().kind, object.pos()))
+            .collect();
+        assert_eq!(
+            placed,
+            vec![
+                (FurnitureKind::Sofa, (11, 11)),
+                (FurnitureKind::Television, (11, 13)),
+                (FurnitureKind::Fridge, (14, 11)),
+            ]
+        );
+
+        // pieces that land on furniture or outside the room are left out
+        let template = RoomTemplate {
+            name: "Odds and ends".into(),
+            furniture: vec![
+                TemplatePiece {
+                    kind: FurnitureKind::Bed,
+                    x: 0,
+                    y: 0,
+                },
+                TemplatePiece {
+                    kind: FurnitureKind::Toilet,
+                    x: 20,
+                    y: 0,
+                },
+                TemplatePiece {
+                    kind: FurnitureKind::Shower,
+                    x: 6,
+                    y: 4,
+                },
+            ],
+        };
+        assert_eq!(apply_template(&mut game, &template), 1);
+        assert_eq!(game.objects.len(), 5);
+        assert_eq!(game.objects[4].pos(), (17, 15));
+    }
+
+    #[test]
+    fn the_low_need_hint_fires_once() {
+        let hints = |game: &Game| {
+            game.log
+                .iter()
+                .filter(|(message, _)| *message == t("hint_low_need", &[]))
+                .count()
+        };
+
+        let mut game = one_room_game();
+        game.config.grace_turns = 0;
+        game.tutorial = true;
+        game.objects[PLAYER].stats.as_mut().unwrap().hunger = HINT_LOW_NEED + 1;
+        tick_turns(&mut game, 1);
+        assert_eq!(hints(&game), 0);
+        tick_turns(&mut game, 30);
+        assert!(game.objects[PLAYER].stats.unwrap().hunger < HINT_LOW_NEED);
+        assert_eq!(hints(&game), 1);
+
+        // and never outside the tutorial
+        game.tutorial = false;
+        game.hints_shown.clear();
+        tick_turns(&mut game, 5);
+        assert_eq!(hints(&game), 1);
+    }
+
+    #[test]
+    fn milestones_take_good_turns_in_a_row() {
+        let mut game = one_room_game();
+        // needs hold steady through the grace period
+        game.config.milestone = Milestone {
+            turns: 5,
+            threshold: 50,
+        };
+
+        tick_turns(&mut game, 4);
+        assert_eq!((game.good_turns, game.milestones), (4, 0));
+        // a need dipping under the threshold starts the count over
+        game.objects[PLAYER].stats.as_mut().unwrap().fun = 10;
+        tick_turns(&mut game, 1);
+        assert_eq!(game.good_turns, 0);
+        game.objects[PLAYER].stats.as_mut().unwrap().fun = 100;
+
+        tick_turns(&mut game, 3);
+        let snapshot = UndoSnapshot::take(&game);
+        push_undo(&mut game, snapshot);
+        tick_turns(&mut game, 2);
+        assert_eq!((game.good_turns, game.milestones), (0, 1));
+
+        // taking the turn back takes the milestone back too
+        undo_turn(&mut game);
+        assert_eq!((game.good_turns, game.milestones), (3, 0));
+    }
+
+    #[test]
+    fn the_map_edge_is_always_wall() {
+        let mut objects = test_game().objects;
+        for &style in &[MapStyle::Scattered, MapStyle::Bsp] {
+            let params = RoomParams {
+                style,
+                ..Default::default()
+            };
+            for seed in 0..8 {
+                let (map, _) = make_map(
+                    &mut objects,
+                    1,
+                    Difficulty::Normal,
+                    Biome::House,
+                    &params,
+                    &mut [],
+                    &mut GameRng::new(seed),
+                );
+                for x in 0..MAP_WIDTH {
+                    for y in 0..MAP_HEIGHT {
+                        let edge = x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1;
+                        if edge {
+                            assert!(
+                                map[x as usize][y as usize].blocked,
+                                "{:?} seed {}: ({}, {})",
+                                style, seed, x, y
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn console_commands_parse() {
+        use DebugCommand::*;
+        assert_eq!(
+            parse_debug_command("set hunger 10"),
+            Ok(SetNeed(Need::Hunger, 10))
+        );
+        assert_eq!(
+            parse_debug_command("set SOCIAL -5"),
+            Ok(SetNeed(Need::Social, -5))
+        );
+        assert_eq!(parse_debug_command("give Sword"), Ok(Give(Item::Sword)));
+        assert_eq!(parse_debug_command("  tp 10   5 "), Ok(Teleport(10, 5)));
+        assert_eq!(parse_debug_command("level +2"), Ok(Descend(2)));
+        assert_eq!(parse_debug_command("level 1"), Ok(Descend(1)));
+        assert_eq!(parse_debug_command("describe"), Ok(Describe));
+
+        for line in &[
+            "",
+            "set thirst 10",
+            "set hunger lots",
+            "set hunger",
+            "give unicorn",
+            "tp 10",
+            "tp x 5",
+            "level 0",
+            "level -1",
+            "fly",
+        ] {
+            assert!(parse_debug_command(line).is_err(), "{:?}", line);
+        }
+        assert_eq!(
+            parse_debug_command("tp 1 two"),
+            Err("'two' is not a number".to_string())
+        );
+    }
+
+    #[test]
+    fn drops_find_the_next_free_tile_or_stay_put() {
+        let mut game = one_room_game();
+        game.inventory.push(make_item(Item::Heal, 0, 0));
+        game.objects.push(make_item(Item::Dagger, 2, 2));
+        game.objects.push(make_item(Item::Confuse, 3, 2));
+
+        // the player's tile and the one next to it are taken, so it goes below
+        drop_item(0, &mut game);
+        assert!(game.inventory.is_empty());
+        assert_eq!(game.objects.last().unwrap().pos(), (2, 3));
+
+        // with every neighbouring floor tile taken the item stays in the bag
+        game.objects
+            .push(make_furniture(FurnitureKind::Toilet, 3, 3));
+        game.inventory.push(make_item(Item::Heal, 0, 0));
+        let objects = game.objects.len();
+        assert_eq!(find_drop_spot(&game), None);
+        drop_item(0, &mut game);
+        assert_eq!(game.inventory.len(), 1);
+        assert_eq!(game.objects.len(), objects);
+        assert!(game.log.iter().any(
+            |(message, _)| *message == t("drop_no_room", &[("item", &game.inventory[0].name)])
+        ));
+    }
+
+    #[test]
+    fn menu_selection_wraps_at_both_ends() {
+        assert_eq!(wrap_selection(0, 1, 4), 1);
+        assert_eq!(wrap_selection(3, 1, 4), 0);
+        assert_eq!(wrap_selection(0, -1, 4), 3);
+        assert_eq!(wrap_selection(2, -1, 4), 1);
+        // a single option stays selected whichever way
+        assert_eq!(wrap_selection(0, 1, 1), 0);
+        assert_eq!(wrap_selection(0, -1, 1), 0);
+    }
+
+    #[test]
+    fn accelerating_decay_drains_faster_when_low() {
+        let rate = 10.0;
+        let linear = |value| apply_decay(value, 100, DecayCurve::Linear, rate);
+        let accelerating = |value| apply_decay(value, 100, DecayCurve::Accelerating, rate);
+
+        assert_eq!(linear(100), rate);
+        assert_eq!(linear(10), rate);
+        // about the same as linear when full, nearly twice as fast when empty
+        assert_eq!(accelerating(100), rate);
+        assert!(accelerating(10) > 1.8 * rate);
+        assert!(accelerating(10) > accelerating(90));
+        assert_eq!(accelerating(0), 2.0 * rate);
+
+        assert_eq!(apply_decay(90, 100, DecayCurve::Step, rate), 0.5 * rate);
+        assert_eq!(apply_decay(10, 100, DecayCurve::Step, rate), 1.5 * rate);
+        // values out of range don't run away
+        assert_eq!(
+            apply_decay(-20, 100, DecayCurve::Accelerating, rate),
+            2.0 * rate
+        );
+    }
+
+    #[test]
+    fn needs_hold_steady_through_the_grace_period() {
+        let mut game = one_room_game();
+        game.config.grace_turns = 40;
+        let full = total_needs(&game);
+
+        tick_turns(&mut game, 40);
+        assert_eq!(total_needs(&game), full);
+        tick_turns(&mut game, 40);
+        assert!(total_needs(&game) < full);
+    }
+
+    #[test]
+    fn declining_to_use_an_item_keeps_it() {
+        let mut game = one_room_game();
+        game.inventory.push(make_item(Item::Lightning, 0, 0));
+        game.inventory.push(make_item(Item::Heal, 0, 0));
+        let before: Vec<_> = game
+            .inventory
+            .iter()
+            .map(|o| (o.id, o.name.clone()))
+            .collect();
+
+        assert_eq!(item_to_use(0, &mut game, |_| false), None);
+        let after: Vec<_> = game
+            .inventory
+            .iter()
+            .map(|o| (o.id, o.name.clone()))
+            .collect();
+        assert_eq!(after, before);
+        assert!(game
+            .log
+            .iter()
+            .any(|(message, _)| *message == t("cancelled", &[])));
+
+        assert_eq!(item_to_use(0, &mut game, |_| true), Some(Item::Lightning));
+        // everyday items don't ask at all
+        assert_eq!(
+            item_to_use(1, &mut game, |_| panic!("asked about a potion")),
+            Some(Item::Heal)
+        );
+    }
+
+    #[test]
+    fn seed_phrases_hash_the_same_every_time() {
+        assert_eq!(seed_from_str("cozy-home"), seed_from_str("cozy-home"));
+        assert_ne!(seed_from_str("cozy-home"), seed_from_str("cosy-home"));
+        assert_ne!(seed_from_str("ab"), seed_from_str("ba"));
+        // the published FNV-1a test vectors, so it can't drift between versions
+        assert_eq!(seed_from_str(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(seed_from_str("a"), 0xaf63_dc4c_8601_ec8c);
+
+        // and the phrase is kept alongside the number
+        let game = test_game();
+        assert_eq!(game.seed_text.as_deref(), Some("test"));
+        assert_eq!(game.rng.seed, seed_from_str("test"));
+    }
 
-    // create a list with the names of all objects at the mouse's coordinates and in FOV
-    let names = objects
-        .iter()
-        .filter(|obj| obj.pos() == (x, y) && fov_map.is_in_fov(obj.x, obj.y))
-        .map(|obj| obj.name.clone())
-        .collect::<Vec<_>>();
+    #[test]
+    fn lerp_color_runs_from_dark_to_light() {
+        let dark = Color::new(0, 100, 200);
+        let light = Color::new(200, 200, 0);
+        assert_eq!(lerp_color(dark, light, 0.0), dark);
+        assert_eq!(lerp_color(dark, light, 0.5), Color::new(100, 150, 100));
+        assert_eq!(lerp_color(dark, light, 1.0), light);
+        // out of range is held at the ends
+        assert_eq!(lerp_color(dark, light, -1.0), dark);
+        assert_eq!(lerp_color(dark, light, 2.0), light);
+    }
 
-    names.join(", ") // join the names, separated by commas
-}
+    #[test]
+    fn the_interact_key_depends_on_whats_there() {
+        let mut game = one_room_game();
+        assert_eq!(resolve_context(&game), ContextAction::Nothing);
+
+        // furniture within reach
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 3, 3));
+        assert_eq!(resolve_context(&game), ContextAction::UseFurniture(1));
+
+        // an item underfoot comes before that
+        game.objects.push(make_item(Item::Heal, 2, 2));
+        assert_eq!(resolve_context(&game), ContextAction::PickUp(2));
+
+        // and stairs before anything
+        let mut stairs = Object::new(2, 2, '<', "stairs", colors::WHITE, false);
+        stairs.add_tag(TAG_STAIRS);
+        game.objects.push(stairs);
+        assert_eq!(resolve_context(&game), ContextAction::Descend);
+    }
 
-fn render_all(tcod: &mut Tcod, game: &mut Game, fov_recompute: bool) {
-    if fov_recompute {
-        // recompute FOV if needed (the player moved or something)
-        let player = &game.objects[PLAYER];
-        tcod.fov
-            .compute_fov(player.x, player.y, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+    #[test]
+    fn the_equipped_map_follows_the_flags() {
+        let mut game = one_room_game();
+        game.inventory.push(make_item(Item::Sword, 0, 0));
+        game.inventory.push(make_item(Item::Dagger, 0, 0));
+        game.inventory.push(make_item(Item::Shield, 0, 0));
+        game.assign_object_ids();
+
+        // what the flags say is in each slot, worked out the slow way
+        let from_flags = |game: &Game| {
+            let mut slots = HashMap::new();
+            for item in &game.inventory {
+                if let Some(equipment) = item.equipment.filter(|e| e.equipped) {
+                    assert!(slots.insert(equipment.slot, item.id).is_none());
+                }
+            }
+            slots
+        };
 
-        // go through all tiles, and set their background color
-        for y in 0..MAP_HEIGHT {
-            for x in 0..MAP_WIDTH {
-                let visible = tcod.fov.is_in_fov(x, y);
-                let wall = game.map[x as usize][y as usize].block_sight;
-                let color = match (visible, wall) {
-                    // outside of field of view:
-                    (false, true) => COLOR_DARK_WALL,
-                    (false, false) => COLOR_DARK_GROUND,
-                    // inside fov:
-                    (true, true) => COLOR_LIGHT_WALL,
-                    (true, false) => COLOR_LIGHT_GROUND,
-                };
+        game.equip(0);
+        game.equip(2);
+        assert_eq!(game.equipped, from_flags(&game));
+        assert_eq!(game.equipped.len(), 2);
+        // swap the shield for the dagger, which goes in the same hand
+        game.unequip(2);
+        assert_eq!(game.equipped, from_flags(&game));
+        game.equip(1);
+        assert_eq!(game.equipped, from_flags(&game));
+        assert_eq!(game.equipped[&Slot::LeftHand], game.inventory[1].id);
+        assert_eq!(
+            get_equipped_in_slot(Slot::LeftHand, &game.inventory),
+            Some(1)
+        );
+
+        // a load rebuilds it from the flags alone
+        let equipped = game.equipped.clone();
+        game.equipped.clear();
+        game.rebuild_equipped();
+        assert_eq!(game.equipped, equipped);
+    }
 
-                let explored = &mut game.map[x as usize][y as usize].explored;
-                if visible {
-                    // since it's visible, explore it
-                    *explored = true;
+    #[test]
+    fn death_keeps_or_deletes_the_saves_by_mode() {
+        for &mode in &SaveMode::ALL {
+            let dir = std::env::temp_dir();
+            let save = dir.join(format!("lardum-test-{:?}-{}.sav", mode, std::process::id()));
+            let auto = dir.join(format!(
+                "lardum-test-{:?}-{}-auto.sav",
+                mode,
+                std::process::id()
+            ));
+            let paths = [save.to_str().unwrap(), auto.to_str().unwrap()];
+
+            let mut game = one_room_game();
+            game.save_mode = mode;
+            for path in &paths {
+                save_game_to(&game, path).unwrap();
+            }
+            game.objects[PLAYER].alive = false;
+            handle_player_death(&mut game, &paths);
+
+            match mode {
+                SaveMode::Casual => {
+                    // the last save is still there to go back to
+                    let loaded = load_game(paths[0]).unwrap();
+                    assert!(loaded.objects[PLAYER].alive);
+                    assert!(auto.exists());
                 }
-                if *explored {
-                    // show explored tiles only (any visible tile is explored already)
-                    tcod.con
-                        .set_char_background(x, y, color, BackgroundFlag::Set);
+                SaveMode::Permadeath => {
+                    assert!(!save.exists() && !auto.exists());
+                    assert!(matches!(load_game(paths[0]), Err(LardumError::NoSave)));
+                    // and a save of the corpse can't be continued either
+                    save_game_to(&game, paths[0]).unwrap();
+                    assert!(matches!(load_game(paths[0]), Err(LardumError::GameOver)));
                 }
             }
+            delete_saves(&paths).unwrap();
         }
     }
 
-    let mut to_draw: Vec<_> = game
-        .objects
-        .iter()
-        .filter(|o| {
-            tcod.fov.is_in_fov(o.x, o.y)
-                || (o.always_visible && game.map[o.x as usize][o.y as usize].explored)
-        })
-        .collect();
-    // sort so that non-blocking objects come first
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
-    // draw the objects in the list
-    for object in &to_draw {
-        object.draw(&mut tcod.con);
+    #[test]
+    fn filtering_keeps_only_matching_entries() {
+        let names = ["Fridge", "sofa", "healing potion", "Soap"];
+        assert_eq!(filter_entries(&names, "so"), vec![1, 3]);
+        assert_eq!(filter_entries(&names, "FRIDGE"), vec![0]);
+        assert_eq!(filter_entries(&names, "unicorn"), Vec::<usize>::new());
+        assert_eq!(filter_entries(&names, ""), vec![0, 1, 2, 3]);
+
+        // works on the log's messages just the same
+        let mut game = one_room_game();
+        game.log.add("You feel hungry.".to_string(), colors::RED);
+        game.log
+            .add("You ate a sandwich.".to_string(), colors::GREEN);
+        let messages: Vec<&str> = game
+            .log
+            .iter()
+            .map(|(message, _)| message.as_str())
+            .collect();
+        assert_eq!(filter_entries(&messages, "hung"), vec![0]);
     }
 
-    // blit the contents of "con" to the root console
-    blit(
-        &mut tcod.con,
-        (0, 0),
-        (MAP_WIDTH, MAP_HEIGHT),
-        &mut tcod.root,
-        (0, 0),
-        1.0,
-        1.0,
-    );
+    #[test]
+    fn a_missing_font_is_reported_by_path() {
+        let path = "assets/no_such_font.png";
+        match check_font(path) {
+            Err(LardumError::MissingFont(missing)) => assert_eq!(missing, path),
+            other => panic!("expected a missing font, got {:?}", other),
+        }
+        assert!(check_font(path).unwrap_err().to_string().contains(path));
+        // a directory isn't a font either
+        assert!(check_font(std::env::temp_dir().to_str().unwrap()).is_err());
+        assert!(check_font(file!()).is_ok());
+    }
 
-    // prepare to render the GUI panel
-    tcod.panel.set_default_background(colors::BLACK);
-    tcod.panel.clear();
+    #[test]
+    fn the_advisor_picks_the_lowest_need() {
+        let mut game = one_room_game();
+        game.turn = 100;
+        assert_eq!(advise(&game), None);
+
+        let stats = game.objects[PLAYER].stats.as_mut().unwrap();
+        stats.energy = ADVICE_LOW_NEED - 5;
+        stats.hunger = ADVICE_LOW_NEED - 10;
+        assert_eq!(advise(&game), Some(t(Need::Hunger.advice_key(), &[])));
+
+        // and holds off for a while after saying something
+        game.config.advisor = true;
+        give_advice(&mut game);
+        assert_eq!(game.last_advice, Some(100));
+        game.turn += 1;
+        assert_eq!(advise(&game), None);
+        game.turn += ADVICE_COOLDOWN;
+        assert_eq!(advise(&game), Some(t(Need::Hunger.advice_key(), &[])));
+    }
 
-    // print the game messages, one line at a time
-    let mut y = MSG_HEIGHT as i32;
-    for &(ref msg, color) in game.log.iter().rev() {
-        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
-        y -= msg_height;
-        if y < 0 {
-            break;
-        }
-        tcod.panel.set_default_foreground(color);
-        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
-    }
-
-    // show the player's stats
-    let hunger = game.objects[PLAYER].stats.map_or(0, |p| p.hunger);
-    let max_hunger = game.objects[PLAYER].max_hunger();
-    let comfort = game.objects[PLAYER].stats.map_or(0, |p| p.comfort);
-    let max_comfort = game.objects[PLAYER].max_comfort();
-    let hygiene = game.objects[PLAYER].stats.map_or(0, |p| p.hygiene);
-    let max_hygiene = game.objects[PLAYER].max_hygiene();
-    let bladder = game.objects[PLAYER].stats.map_or(0, |p| p.bladder);
-    let max_bladder = game.objects[PLAYER].max_bladder();
-    let energy = game.objects[PLAYER].stats.map_or(0, |p| p.energy);
-    let max_energy = game.objects[PLAYER].max_energy();
-    let fun = game.objects[PLAYER].stats.map_or(0, |p| p.fun);
-    let max_fun = game.objects[PLAYER].max_fun();
-    let social = game.objects[PLAYER].stats.map_or(0, |p| p.social);
-    let max_social = game.objects[PLAYER].max_social();
-    let room = game.objects[PLAYER].stats.map_or(0, |p| p.room);
-    let max_room = game.objects[PLAYER].max_room();
-
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH * 2 - BAR_LEFT_PADDING,
-        BAR_TOP_PADDING + 1,
-        BAR_WIDTH,
-        "Hunger",
-        hunger,
-        max_hunger,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH * 2 - BAR_LEFT_PADDING,
-        BAR_TOP_PADDING + 2,
-        BAR_WIDTH,
-        "Comfort",
-        comfort,
-        max_comfort,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH * 2 - BAR_LEFT_PADDING,
-        BAR_TOP_PADDING + 3,
-        BAR_WIDTH,
-        "Hygiene",
-        hygiene,
-        max_hygiene,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH * 2 - BAR_LEFT_PADDING,
-        BAR_TOP_PADDING + 4,
-        BAR_WIDTH,
-        "Bladder",
-        bladder,
-        max_bladder,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH,
-        BAR_TOP_PADDING + 1,
-        BAR_WIDTH,
-        "Energy",
-        energy,
-        max_energy,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH,
-        BAR_TOP_PADDING + 2,
-        BAR_WIDTH,
-        "Fun",
-        fun,
-        max_fun,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH,
-        BAR_TOP_PADDING + 3,
-        BAR_WIDTH,
-        "Social",
-        social,
-        max_social,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
-    render_bar(
-        &mut tcod.panel,
-        SCREEN_WIDTH - BAR_WIDTH,
-        BAR_TOP_PADDING + 4,
-        BAR_WIDTH,
-        "Room",
-        room,
-        max_room,
-        colors::LIGHT_GREEN,
-        colors::DARKER_GREEN,
-    );
+    #[test]
+    fn bsp_maps_are_connected() {
+        let params = RoomParams {
+            style: MapStyle::Bsp,
+            ..Default::default()
+        };
+        for seed in 0..8 {
+            let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+            let mut objects = test_game().objects;
+            let rooms = make_map_bsp(
+                &mut map,
+                &mut objects,
+                1,
+                Difficulty::Normal,
+                Biome::House,
+                &params,
+                &mut GameRng::new(seed),
+            );
+            // pieces stop being split once they're at most twice the
+            // largest room long, and are seldom much smaller than a room
+            let area = (MAP_WIDTH * MAP_HEIGHT) as usize;
+            let largest = params.max_size as usize;
+            assert!(
+                rooms.len() >= area / (2 * largest).pow(2),
+                "seed {}: {} rooms",
+                seed,
+                rooms.len()
+            );
+            assert!(
+                rooms.len() <= area / largest.pow(2),
+                "seed {}: {} rooms",
+                seed,
+                rooms.len()
+            );
 
-    // display names of objects under the mouse
-    tcod.panel.set_default_foreground(colors::LIGHT_GREY);
-    tcod.panel.print_ex(
-        1,
-        0,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        get_names_under_mouse(tcod.mouse, &mut game.objects, &tcod.fov),
-    );
+            // every floor tile can be walked to from the first room, without
+            // any help from ensure_connected
+            let reachable = flood_fill_reachable(&map, rooms[0].center());
+            for x in 0..MAP_WIDTH {
+                for y in 0..MAP_HEIGHT {
+                    let floor = !map[x as usize][y as usize].blocked;
+                    assert_eq!(
+                        reachable[x as usize][y as usize], floor,
+                        "seed {}: ({}, {})",
+                        seed, x, y
+                    );
+                }
+            }
+        }
+    }
 
-    // blit the contents of `panel` to the root console
-    blit(
-        &tcod.panel,
-        (0, 0),
-        (SCREEN_WIDTH, PANEL_HEIGHT),
-        &mut tcod.root,
-        (0, PANEL_Y),
-        1.0,
-        1.0,
-    );
-}
+    #[test]
+    fn timers_count_down_and_expire() {
+        let mut game = one_room_game();
+        game.objects[PLAYER].set_timer(TIMER_INSULT, 2);
+        assert_eq!(game.objects[PLAYER].timer(TIMER_INSULT), 2);
+
+        tick_timers(&mut game);
+        assert_eq!(game.objects[PLAYER].timer(TIMER_INSULT), 1);
+        assert!(game.objects[PLAYER].timer_running(TIMER_INSULT));
+        tick_timers(&mut game);
+        assert!(!game.objects[PLAYER].timer_running(TIMER_INSULT));
+        assert!(game.objects[PLAYER].timers.is_empty());
+
+        // setting one to zero stops it
+        game.objects[PLAYER].set_timer(TIMER_INSULT, 5);
+        game.objects[PLAYER].set_timer(TIMER_INSULT, 0);
+        assert_eq!(game.objects[PLAYER].timer(TIMER_INSULT), 0);
+    }
 
-fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
-    assert!(
-        options.len() <= 26,
-        "Cannot have a menu with more than 26 options."
-    );
+    #[test]
+    fn animation_frames_advance_and_wrap() {
+        let ticks = ANIMATION_FRAME_TICKS;
+        assert_eq!(animation_frame(0, 3), 0);
+        assert_eq!(animation_frame(ticks - 1, 3), 0);
+        assert_eq!(animation_frame(ticks, 3), 1);
+        assert_eq!(animation_frame(2 * ticks, 3), 2);
+        assert_eq!(animation_frame(3 * ticks, 3), 0);
+        assert_eq!(animation_frame(u32::MAX, 1), 0);
+        // no frames at all doesn't divide by zero
+        assert_eq!(animation_frame(ticks, 0), 0);
+    }
 
-    // calculate total height for the header (after auto-wrap) and one line per option
-    let header_height = if header.is_empty() {
-        0
-    } else {
-        root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
-    };
-    let height = options.len() as i32 + header_height;
+    #[test]
+    fn using_all_stops_once_the_need_is_full() {
+        let mut game = one_room_game();
+        for _ in 0..5 {
+            game.inventory.push(make_item(Item::Heal, 0, 0));
+        }
+        game.inventory.push(make_item(Item::Dagger, 0, 0));
+        let (need, amount) = Item::Heal.restores().unwrap();
+        let maximum = game.objects[PLAYER].max_need(need);
+        *game.objects[PLAYER].stats.as_mut().unwrap().need_mut(need) = maximum - 2 * amount - 5;
+
+        // what apply_item does with a potion, minus the particles
+        let used = use_until_full(Item::Heal, need, &mut game, |inventory_id, game| {
+            assert!(restore_player_need(Item::Heal, game));
+            game.inventory.remove(inventory_id);
+            UseResult::UsedUp
+        });
+        assert_eq!(used, 3);
+        assert_eq!(game.objects[PLAYER].stats.unwrap().need(need), maximum);
+        let left: Vec<_> = game.inventory.iter().map(|object| object.item).collect();
+        assert_eq!(
+            left,
+            vec![Some(Item::Heal), Some(Item::Heal), Some(Item::Dagger)]
+        );
+    }
 
-    // create an off-screen console that represents the menu's window
-    let mut window = Offscreen::new(width, height);
+    #[test]
+    fn repair_separates_stacked_blockers() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(3, 2));
+        game.objects.push(npc_at(3, 2));
+        game.objects.push(npc_at(2, 2)); // on top of the player
+        validate_and_repair(&mut game);
+
+        let mut tiles: Vec<_> = game.objects.iter().map(|object| object.pos()).collect();
+        // the player and the first of the stacked pair stay where they were
+        assert_eq!(tiles[PLAYER], (2, 2));
+        assert_eq!(tiles[1], (3, 2));
+        for &(x, y) in &tiles {
+            assert!(!game.map[x as usize][y as usize].blocked);
+        }
+        tiles.sort();
+        tiles.dedup();
+        assert_eq!(tiles.len(), 4);
+        assert_eq!(game.log.len(), 2);
+    }
 
-    // print the header, with auto-wrap
-    window.set_default_foreground(colors::WHITE);
-    window.print_rect_ex(
-        0,
-        0,
-        width,
-        height,
-        BackgroundFlag::None,
-        TextAlignment::Left,
-        header,
-    );
+    #[test]
+    fn new_players_get_the_configured_stats() {
+        let mut config = Config::default();
+        config.starting_stats.max = 80;
+        config.starting_stats.needs.insert(Need::Energy, 10);
+        config.starting_stats.needs.insert(Need::Hunger, 35);
+        assert!(config.starting_stats.validate().is_ok());
+        let game = create_game(Difficulty::Normal, 1, false, Some("test"), config, vec![]);
+
+        let stats = game.objects[PLAYER].stats.unwrap();
+        assert_eq!(stats.base_max_all_stats, 80);
+        assert_eq!(stats.energy, 10);
+        assert_eq!(stats.hunger, 35);
+        assert_eq!(stats.fun, 80);
+
+        // nothing above the maximum, and nothing below zero
+        let mut stats = StartingStats::default();
+        stats.needs.insert(Need::Fun, 101);
+        assert!(stats.validate().is_err());
+        stats.needs.insert(Need::Fun, -1);
+        assert!(stats.validate().is_err());
+    }
 
-    // print all the options
-    for (index, option_text) in options.iter().enumerate() {
-        let menu_letter = (b'a' + index as u8) as char;
-        let text = format!("({}) {}", menu_letter, option_text.as_ref());
-        window.print_ex(
-            0,
-            header_height + index as i32,
-            BackgroundFlag::None,
-            TextAlignment::Left,
-            text,
-        );
+    #[test]
+    fn sprinting_covers_two_tiles_for_some_energy() {
+        let mut game = one_room_game();
+        let energy = |game: &Game| game.objects[PLAYER].stats.unwrap().energy;
+        let full = energy(&game);
+
+        walk(1, 0, &mut game);
+        assert_eq!(game.objects[PLAYER].pos(), (3, 2));
+        assert_eq!(energy(&game), full);
+
+        game.sprinting = true;
+        walk(1, 0, &mut game);
+        assert_eq!(game.objects[PLAYER].pos(), (5, 2));
+        assert_eq!(energy(&game), full - SPRINT_ENERGY_COST);
+
+        // with a wall right after the first tile it's just the one
+        game.objects[PLAYER].set_pos(4, 3);
+        walk(1, 0, &mut game);
+        assert_eq!(game.objects[PLAYER].pos(), (5, 3));
+        assert_eq!(energy(&game), full - SPRINT_ENERGY_COST);
     }
 
-    // blit the contents of "window" to the root console
-    let x = SCREEN_WIDTH / 2 - width / 2;
-    let y = SCREEN_HEIGHT / 2 - height / 2;
-    tcod::console::blit(&mut window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+    #[test]
+    fn the_map_passes_draw_a_known_scene() {
+        let mut game = one_room_game();
+        create_room(Rect::new(20, 20, 4, 4), &mut game.map);
+        game.objects.push(npc_at(4, 2));
+        game.objects.push(make_item(Item::Heal, 4, 2));
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 5, 3));
+        game.objects.push(npc_at(21, 21));
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        let mut con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+        paint_tiles(&mut con, &fov, &mut game, None, None, false);
+        paint_objects(&mut con, &fov, &game, false, 0);
+
+        // each object with its own char and color, the NPC over the potion
+        let cell = |x, y| (con.get_char(x, y), con.get_char_foreground(x, y));
+        assert_eq!(
+            cell(2, 2),
+            (game.objects[PLAYER].char, game.objects[PLAYER].color)
+        );
+        assert_eq!(cell(4, 2), ('@', colors::LIGHT_BLUE));
+        assert_eq!(cell(5, 3), (game.objects[3].char, game.objects[3].color));
+        assert_eq!(con.get_char(3, 3), ' ');
+
+        // lit walls and floor fade with the distance from the torch
+        let (dark, light) = tile_colors(true, false, game.biome);
+        assert_eq!(
+            con.get_char_background(1, 1),
+            lerp_color(dark, light, torch_light(-1, -1))
+        );
+        let (dark, light) = tile_colors(false, false, game.biome);
+        assert_eq!(
+            con.get_char_background(5, 3),
+            lerp_color(dark, light, torch_light(3, 1))
+        );
 
-    // present the root console to the player and wait for a key-press
-    root.flush();
-    let key = root.wait_for_keypress(true);
+        // and nothing at all of the room the player hasn't seen
+        assert_eq!(con.get_char(21, 21), ' ');
+        assert_eq!(con.get_char_background(21, 21), colors::BLACK);
+    }
 
-    // convert the ASCII code to an index; if it corresponds to an option, return it
-    if key.printable.is_alphabetic() {
-        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
-        if index < options.len() {
-            Some(index)
-        } else {
-            None
+    #[test]
+    fn pestering_turns_friends_hostile() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(3, 2));
+        talk_to(1, &mut game);
+        assert_eq!(game.objects[1].attitude(), Attitude::Friendly);
+
+        // keep bothering them while they'd rather be left alone
+        let mut attitudes = vec![];
+        while game.objects[1].attitude() != Attitude::Hostile {
+            talk_to(1, &mut game);
+            attitudes.push(game.objects[1].attitude());
+            assert!(attitudes.len() < 20, "never turned hostile");
         }
-    } else {
-        None
+        let relationship = game.objects[1].relationship;
+        assert!(relationship <= RELATIONSHIP_HOSTILE);
+        assert!(relationship - RELATIONSHIP_PESTER > RELATIONSHIP_HOSTILE);
+        assert!(attitudes.contains(&Attitude::Neutral));
+
+        // now they come to have a go at the player
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        game.objects[1].timers.clear();
+        let social = game.objects[PLAYER].stats.unwrap().social;
+        ai_basic(1, &fov, &mut game);
+        assert_eq!(
+            game.objects[PLAYER].stats.unwrap().social,
+            social - INSULT_SOCIAL_LOSS
+        );
     }
-}
 
-fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
-    // how a menu with each item of the inventory as an option
-    let options = if inventory.len() == 0 {
-        vec!["Inventory is empty.".into()]
-    } else {
-        inventory
-            .iter()
-            .map(|item| {
-                // show additional information, in case it's equipped
-                match item.equipment {
-                    Some(equipment) if equipment.equipped => {
-                        format!("{} (on {})", item.name, equipment.slot)
-                    }
-                    _ => item.name.clone(),
+    /// the events that started over `turns` turns, with the turn each one did
+    fn events_over(game: &mut Game, turns: u32) -> Vec<(u32, RandomEvent)> {
+        let mut started = vec![];
+        for _ in 0..turns {
+            game.turn += 1;
+            let gifts = game.objects.len();
+            update_events(game);
+            if game.objects.len() > gifts {
+                started.push((game.turn, RandomEvent::Gift));
+            }
+            for active in &game.active_events {
+                if Some(active.turns_left) == active.event.duration() {
+                    started.push((game.turn, active.event));
                 }
-            })
-            .collect()
-    };
+            }
+        }
+        started
+    }
 
-    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+    #[test]
+    fn seeded_events_come_in_a_known_order() {
+        let mut game = one_room_game();
+        game.config.events.interval = 10;
+        game.config.events.chance = 0.5;
+        game.rng = GameRng::new(7);
+        let params = game.config.events.clone();
+        use RandomEvent::*;
+        assert_eq!(
+            events_over(&mut game, 400),
+            vec![
+                (10, PowerOutage),
+                (20, Leak),
+                (120, Gift),
+                (150, PowerOutage),
+                (180, Gift),
+                (210, Leak),
+                (270, PowerOutage),
+                (280, Gift),
+                (350, Gift),
+                (400, PowerOutage),
+            ]
+        );
 
-    // if an item was chosen, return it
-    if inventory.len() > 0 {
-        inventory_index
-    } else {
-        None
+        // the same again, watching the first outage run out
+        let mut game = one_room_game();
+        game.config.events = params;
+        game.rng = GameRng::new(7);
+        events_over(&mut game, 10 + POWER_OUTAGE_TURNS as u32 - 1);
+        assert!(game.event_active(PowerOutage));
+        assert!(!game
+            .log
+            .iter()
+            .any(|(m, _)| *m == t("event_power_back", &[])));
+        events_over(&mut game, 1);
+        assert!(!game.event_active(PowerOutage));
+        assert!(game
+            .log
+            .iter()
+            .any(|(m, _)| *m == t("event_power_back", &[])));
     }
-}
 
-fn msgbox(text: &str, width: i32, root: &mut Root) {
-    let options: &[&str] = &[];
-    menu(text, options, width, root);
-}
+    #[test]
+    fn the_pet_keeps_up_and_cheers_the_player() {
+        let mut game = one_room_game();
+        game.objects.push(make_pet());
+        game.objects[1].set_pos(5, 3);
+        let far = game.objects[1].distance_to(&game.objects[PLAYER]);
+        ai_follow(1, &mut game);
+        assert!(game.objects[1].distance_to(&game.objects[PLAYER]) < far);
+        // it moved, but keeps its own char rather than a facing arrow
+        assert!(game.objects[1].facing.is_some());
+        assert_eq!(game.objects[1].glyph(0), 'f');
+
+        // within reach it stays put
+        game.objects[1].set_pos(3, 3);
+        ai_follow(1, &mut game);
+        assert_eq!(game.objects[1].pos(), (3, 3));
+
+        // and once every interval it tops up fun and social, and in between
+        // it doesn't
+        let mut alone = one_room_game();
+        for game in [&mut game, &mut alone].iter_mut() {
+            game.config.grace_turns = 0;
+            let stats = game.objects[PLAYER].stats.as_mut().unwrap();
+            stats.fun = 50;
+            stats.social = 50;
+        }
+        for turn in 1..=PET_CHEER_INTERVAL * 3 {
+            tick_turns(&mut game, 1);
+            tick_turns(&mut alone, 1);
+            let (with, without) = (
+                game.objects[PLAYER].stats.unwrap(),
+                alone.objects[PLAYER].stats.unwrap(),
+            );
+            let cheered = (turn / PET_CHEER_INTERVAL) as i32 * PET_CHEER;
+            assert_eq!(with.fun, without.fun + cheered);
+            assert_eq!(with.social, without.social + cheered);
+            assert_eq!(with.energy, without.energy);
+        }
+    }
 
-fn handle_keys(key: Key, tcod: &mut Tcod, game: &mut Game) -> PlayerAction {
-    use tcod::input::KeyCode::*;
+    #[test]
+    fn names_under_the_mouse_dont_depend_on_object_order() {
+        let mut game = one_room_game();
+        game.objects.push(make_item(Item::Heal, 4, 2));
+        game.objects.push(npc_at(4, 2));
+        game.objects.push(make_item(Item::Dagger, 4, 2));
+        game.objects.push(make_item(Item::Sword, 4, 2));
+        game.assign_object_ids();
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        let mouse = Mouse {
+            cx: 4,
+            cy: 2,
+            ..Default::default()
+        };
+        let names = |objects: &[Object]| {
+            get_names_under_mouse(mouse, Camera::default(), MAP_HEIGHT, objects, &fov)
+        };
 
-    let player_alive = game.objects[PLAYER].alive;
-    match (key, player_alive) {
-        (
-            Key {
-                code: Enter,
-                alt: true,
-                ..
-            },
-            _,
-        ) => {
-            // Alt+Enter: toggle fullscreen
-            let fullscreen = tcod.root.is_fullscreen();
-            tcod.root.set_fullscreen(!fullscreen);
-            PlayerAction::DidntTakeTurn
-        }
-        (Key { code: Escape, .. }, _) => PlayerAction::Exit, // exit game
+        let mut shuffled = game.objects.clone();
+        shuffled.swap_remove(1);
+        shuffled.push(game.objects[1].clone());
+        shuffled[1..].reverse();
+        assert_eq!(names(&shuffled), names(&game.objects));
+        assert_eq!(names(&game.objects), "healing potion, Alex, dagger, sword");
+
+        // and they're drawn the same way round too
+        let draw = |objects: Vec<Object>| {
+            let mut con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+            let mut scene = one_room_game();
+            scene.objects = objects;
+            paint_objects(&mut con, &fov, &scene, false, 0);
+            (con.get_char(4, 2), con.get_char_foreground(4, 2))
+        };
+        let mut items_only = game.objects.clone();
+        items_only.remove(2);
+        let mut reversed = items_only.clone();
+        reversed[1..].reverse();
+        assert_eq!(draw(items_only), draw(reversed));
+    }
 
-        // movement keys
-        (Key { code: Up, .. }, true) | (Key { code: NumPad8, .. }, true) => {
-            move_by(PLAYER, 0, -1, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
-        }
-        (Key { code: Down, .. }, true) | (Key { code: NumPad2, .. }, true) => {
-            move_by(PLAYER, 0, 1, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
-        }
-        (Key { code: Left, .. }, true) | (Key { code: NumPad4, .. }, true) => {
-            move_by(PLAYER, -1, 0, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
-        }
-        (Key { code: Right, .. }, true) | (Key { code: NumPad6, .. }, true) => {
-            move_by(PLAYER, 1, 0, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
-        }
-        (Key { code: Home, .. }, true) | (Key { code: NumPad7, .. }, true) => {
-            move_by(PLAYER, -1, -1, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
+    #[test]
+    fn splitting_a_stack_drops_part_of_it() {
+        let mut game = one_room_game();
+        let mut potions = make_item(Item::Heal, 0, 0);
+        potions.count = 5;
+        game.inventory.push(potions);
+        game.assign_object_ids();
+
+        assert!(split_stack(0, 2, &mut game));
+        assert_eq!(game.inventory[0].count, 3);
+        let pile = game.objects.last().unwrap();
+        assert_eq!((pile.count, pile.pos()), (2, (2, 2)));
+        assert_ne!(pile.id, game.inventory[0].id);
+
+        // more than there is, or none at all, drops nothing
+        assert!(!split_stack(0, 4, &mut game));
+        assert!(!split_stack(0, 0, &mut game));
+        assert_eq!(game.inventory[0].count, 3);
+        assert_eq!(game.objects.len(), 2);
+
+        // and the whole stack is just a drop
+        assert!(split_stack(0, 3, &mut game));
+        assert!(game.inventory.is_empty());
+        assert_eq!(game.objects.last().unwrap().count, 3);
+    }
+
+    #[test]
+    fn disconnected_rooms_get_dug_out() {
+        let mut game = one_room_game();
+        let island = Rect::new(30, 20, 6, 5);
+        create_room(island, &mut game.map);
+        let rooms = vec![game.rooms[0], island];
+        let (x, y) = island.center();
+        assert!(!flood_fill_reachable(&game.map, (2, 2))[x as usize][y as usize]);
+
+        ensure_connected(&mut game.map, &rooms, (2, 2));
+        let reachable = flood_fill_reachable(&game.map, (2, 2));
+        assert!(reachable[x as usize][y as usize]);
+        // and every floor tile of it, not just the middle
+        for x in island.x1 + 1..island.x2 {
+            for y in island.y1 + 1..island.y2 {
+                assert!(reachable[x as usize][y as usize], "({}, {})", x, y);
+            }
         }
-        (Key { code: PageUp, .. }, true) | (Key { code: NumPad9, .. }, true) => {
-            move_by(PLAYER, 1, -1, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
+    }
+
+    #[test]
+    fn need_deltas_report_ups_and_downs() {
+        let before = [50, 60, 70, 80, 90, 100, 40, 30];
+        let after = [55, 60, 20, 80, 100, 90, 40, 0];
+        let deltas = need_deltas(&before, &after);
+        assert_eq!(deltas.len(), Need::ALL.len());
+        assert_eq!(deltas[0], (Need::ALL[0], 5));
+        assert_eq!(deltas[1], (Need::ALL[1], 0));
+        assert_eq!(deltas[2], (Need::ALL[2], -50));
+        assert_eq!(deltas[4], (Need::ALL[4], 10));
+        assert_eq!(deltas[5], (Need::ALL[5], -10));
+        assert_eq!(deltas[7], (Need::ALL[7], -30));
+
+        // what it's compared against is what the last save had
+        let mut game = one_room_game();
+        game.mark_saved();
+        game.objects[PLAYER].stats.as_mut().unwrap().fun -= 20;
+        let now = need_values(&game.objects[PLAYER].stats.unwrap());
+        let deltas = need_deltas(&game.saved_needs.unwrap(), &now);
+        assert!(deltas.contains(&(Need::Fun, -20)));
+        assert_eq!(deltas.iter().filter(|&&(_, delta)| delta != 0).count(), 1);
+    }
+
+    #[test]
+    fn take_object_checks_what_it_is_given() {
+        let mut game = one_room_game();
+        game.objects.push(make_item(Item::Heal, 3, 2));
+        game.objects.push(npc_at(4, 2));
+        game.assign_object_ids();
+        let npc = game.objects[2].id;
+
+        // the last object fills the gap
+        let potion = game.take_object(1).unwrap();
+        assert_eq!(potion.item, Some(Item::Heal));
+        assert_eq!(game.objects.len(), 2);
+        assert_eq!(game.objects[1].id, npc);
+
+        assert!(game.take_object(2).is_none());
+        assert!(game.take_object(usize::MAX).is_none());
+        assert!(game.take_object(PLAYER).is_none());
+        assert_eq!(game.objects.len(), 2);
+        assert_eq!(game.objects[PLAYER].pos(), (2, 2));
+    }
+
+    #[test]
+    fn low_needs_keep_the_player_off_the_stairs() {
+        let mut game = one_room_game();
+        game.objects[PLAYER].stats.as_mut().unwrap().hygiene = 30;
+        // off by default
+        assert!(ready_to_descend(&mut game));
+
+        game.config.stairs_need_threshold = Some(40);
+        assert!(!ready_to_descend(&mut game));
+        assert_eq!(
+            game.log.iter().last().unwrap().0,
+            t("stairs_needs_low", &[("need", &Need::Hygiene)])
+        );
+
+        game.objects[PLAYER].stats.as_mut().unwrap().hygiene = 90;
+        assert!(ready_to_descend(&mut game));
+    }
+
+    #[test]
+    fn spawners_add_to_every_room() {
+        // a spawner that leaves an object with `name` in the middle of each room
+        fn marker(name: &'static str) -> impl FnMut(Rect, &Map, u32, &mut Vec<Object>) {
+            move |room, _, _, objects| {
+                let (x, y) = room.center();
+                objects.push(Object::new(x, y, '?', name, colors::WHITE, false));
+            }
         }
-        (Key { code: End, .. }, true) | (Key { code: NumPad1, .. }, true) => {
-            move_by(PLAYER, -1, 1, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
+        let markers = |game: &Game, name: &str| {
+            let found: Vec<_> = game
+                .objects
+                .iter()
+                .filter(|object| object.name == name)
+                .map(|object| object.pos())
+                .collect();
+            assert_eq!(found.len(), game.rooms.len());
+            for room in &game.rooms {
+                assert!(found.contains(&room.center()));
+            }
+        };
+
+        let mut game = create_game(
+            Difficulty::Normal,
+            1,
+            false,
+            Some("test"),
+            Config::default(),
+            vec![Box::new(marker("first"))],
+        );
+        markers(&game, "first");
+
+        // ones registered later join in from the next level on
+        game.on_spawn(marker("second"));
+        let params = game.config.rooms.clone();
+        let (map, rooms) = make_map(
+            &mut game.objects,
+            2,
+            game.difficulty,
+            game.biome,
+            &params,
+            &mut game.spawners,
+            &mut game.rng,
+        );
+        game.map = map;
+        game.rooms = rooms;
+        markers(&game, "first");
+        markers(&game, "second");
+    }
+
+    #[test]
+    fn the_targeting_cursor_stays_in_view() {
+        let game = one_room_game();
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        let player = &game.objects[PLAYER];
+        let press = |cursor, code| targeting_key(cursor, code, &fov, player, None);
+
+        assert_eq!(press((2, 2), KeyCode::Right), ((3, 2), None));
+        // the wall is lit, but nothing behind it
+        assert_eq!(press((2, 2), KeyCode::Left), ((1, 2), None));
+        assert_eq!(press((1, 2), KeyCode::Left), ((1, 2), None));
+        assert_eq!(press((2, 2), KeyCode::Up).0, (2, 1));
+        assert_eq!(press((2, 1), KeyCode::Up).0, (2, 1));
+
+        assert_eq!(press((4, 3), KeyCode::Enter), ((4, 3), Some((4, 3))));
+        assert_eq!(press((4, 3), KeyCode::NumPadEnter).1, Some((4, 3)));
+        // and out of range, Enter doesn't pick anything
+        let (_, picked) = targeting_key((5, 3), KeyCode::Enter, &fov, player, Some(2.0));
+        assert_eq!(picked, None);
+    }
+
+    #[test]
+    fn new_kinds_of_item_go_in_the_codex_once() {
+        let mut game = one_room_game();
+        game.codex = Codex::default();
+        for _ in 0..3 {
+            game.objects.push(make_item(Item::Heal, 2, 2));
+            let id = game.objects.len() - 1;
+            pick_item_up(id, &mut game);
         }
-        (Key { code: PageDown, .. }, true) | (Key { code: NumPad3, .. }, true) => {
-            move_by(PLAYER, 1, 1, &game.map, &mut game.objects);
-            PlayerAction::TookTurn
+        assert_eq!(game.inventory.len(), 3);
+        assert_eq!(game.codex.items, vec![Item::Heal]);
+
+        game.objects.push(make_item(Item::Sword, 2, 2));
+        pick_item_up(1, &mut game);
+        assert_eq!(game.codex.items, vec![Item::Heal, Item::Sword]);
+        assert!(!game.codex.add_item(Item::Sword));
+
+        // and it comes back the same from a save
+        let saved = serde_json::to_string(&game.codex).unwrap();
+        let loaded: Codex = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.items, game.codex.items);
+    }
+
+    #[test]
+    fn guests_who_cant_reach_the_player_give_up() {
+        let mut game = one_room_game();
+        game.config.autosave_interval = 0;
+        // a second room with no way through to the player's
+        create_room(Rect::new(20, 10, 5, 3), &mut game.map);
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        // the top row of the far room is the nearest the edge out of sight
+        let (x, y) = guest_entrance(&fov, &mut game).unwrap();
+        assert!((21..24).contains(&x));
+        assert_eq!(y, 11);
+
+        let mut guest = npc_at(x, y);
+        guest.name = "Sam".into();
+        guest.ai = Some(Ai::Guest {
+            stage: GuestStage::Arriving,
+            turns_left: GUEST_ARRIVE_TURNS,
+            exit: (x, y),
+        });
+        game.objects.push(guest);
+        game.assign_object_ids();
+
+        let visiting = |game: &Game| game.objects.iter().any(|object| object.name == "Sam");
+        for _ in 0..GUEST_ARRIVE_TURNS {
+            advance_turn(&fov, &mut game);
         }
-        (Key { code: NumPad5, .. }, true) => {
-            PlayerAction::TookTurn // do nothing, i.e. wait for the monster to come to you
+        assert!(visiting(&game));
+        // then they give up and walk back to where they came in
+        for _ in 0..5 {
+            advance_turn(&fov, &mut game);
         }
+        assert!(!visiting(&game));
+    }
 
-        (Key { printable: 'g', .. }, true) => {
-            // pick up an item
-            let item_id = game.objects.iter().position(|object| {
-                object.pos() == game.objects[PLAYER].pos() && object.item.is_some()
-            });
-            if let Some(item_id) = item_id {
-                pick_item_up(item_id, game);
-            }
-            PlayerAction::DidntTakeTurn
-        }
+    #[test]
+    fn a_pet_by_the_bed_doesnt_wake_the_player() {
+        let mut game = one_room_game();
+        game.config.autosave_interval = 0;
+        game.objects.push(make_furniture(FurnitureKind::Bed, 4, 3));
+        let mut pet = make_pet();
+        pet.set_pos(3, 2);
+        game.objects.push(pet);
+        game.assign_object_ids();
+        game.turn = minutes_until(START_TIME, 23 * 60);
+
+        let fov = fov_for(&game.map);
+        sleep_until_morning(1, &mut game, &fov);
+        assert_eq!(clock_minutes(game.turn), game.config.wake_hour * 60);
+        assert!(game.objects[2].distance_to(&game.objects[PLAYER]) < 2.0);
+    }
 
-        (Key { printable: 'i', .. }, true) => {
-            // show the inventory: if an item is selected, use it
-            let inventory_index = inventory_menu(
-                &game.inventory,
-                "Press the key next to an item to use it, or any other to cancel.\n",
-                &mut tcod.root,
-            );
-            if let Some(inventory_index) = inventory_index {
-                use_item(inventory_index, game, tcod);
-            }
-            PlayerAction::DidntTakeTurn
+    #[test]
+    fn a_taller_panel_shows_more_log_lines() {
+        let log: Messages = (0..30)
+            .map(|i| (format!("message {}", i), colors::WHITE))
+            .collect();
+        let shown = |height| {
+            let panel = PanelParams {
+                height,
+                ..Default::default()
+            };
+            let mut con = Offscreen::new(SCREEN_WIDTH, height);
+            paint_log(&mut con, &log, panel)
+        };
+        let short = shown(PANEL_MIN_HEIGHT);
+        let tall = shown(SCREEN_HEIGHT / 2);
+        assert_eq!(short as i32, PANEL_MIN_HEIGHT - 1);
+        assert_eq!(tall as i32, SCREEN_HEIGHT / 2 - 1);
+        assert!(tall > short);
+    }
+
+    #[test]
+    fn several_npcs_take_their_turns_together() {
+        let mut game = one_room_game();
+        create_room(Rect::new(1, 1, 20, 10), &mut game.map);
+        for &(x, y) in &[(6, 2), (7, 5), (5, 7), (8, 4)] {
+            game.objects.push(npc_at(x, y));
+        }
+        let mut confused = npc_at(8, 8);
+        confused.ai = Some(Ai::Confused {
+            previous_ai: Box::new(Ai::Basic),
+            num_turns: 5,
+        });
+        game.objects.push(confused);
+        let mut pet = make_pet();
+        pet.set_pos(6, 6);
+        game.objects.push(pet);
+        game.assign_object_ids();
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        let distances = |game: &Game| -> Vec<f32> {
+            game.objects[1..5]
+                .iter()
+                .map(|npc| npc.distance_to(&game.objects[PLAYER]))
+                .collect()
+        };
+        let before = distances(&game);
+        npc_turns(&fov, &mut game);
+        // everyone the player can see heads over in the same turn
+        for (after, before) in distances(&game).iter().zip(&before) {
+            assert!(after < before);
         }
 
-        (Key { printable: 'd', .. }, true) => {
-            // show the inventory; if an item is selected, drop it
-            let inventory_index = inventory_menu(
-                &game.inventory,
-                "Press the key next to an item to drop it, or any other to cancel.\n'",
-                &mut tcod.root,
-            );
-            if let Some(inventory_index) = inventory_index {
-                drop_item(inventory_index, game);
-            }
-            PlayerAction::DidntTakeTurn
+        for _ in 0..20 {
+            npc_turns(&fov, &mut game);
         }
-
-        (Key { printable: '<', .. }, true) => {
-            // go down stairs, if the player is on them
-            let player_on_stairs = game.objects.iter().any(|object| {
-                object.pos() == game.objects[PLAYER].pos() && object.name == "stairs"
-            });
-            if player_on_stairs {
-                next_level(tcod, game);
+        assert!(game.objects[1..].iter().all(|npc| npc.ai.is_some()));
+        // and nobody ended up on top of anybody else
+        for (i, a) in game.objects.iter().enumerate() {
+            for b in &game.objects[i + 1..] {
+                assert!(!(a.blocks && b.blocks && a.pos() == b.pos()));
             }
-            PlayerAction::DidntTakeTurn
         }
+    }
 
-        (Key { printable: 'c', .. }, true) => {
-            // show character information
-            let player = &game.objects[PLAYER];
-            if let Some(stats) = player.stats.as_ref() {
-                let msg = format!(
-                    "Character information
-
-Hunger: {}  Energy: {}
-Comfort: {} Fun: {}
-Hygiene: {} Social: {}
-Bladder: {} Room: {}",
-                    stats.hunger,
-                    stats.energy,
-                    stats.comfort,
-                    stats.fun,
-                    stats.hygiene,
-                    stats.social,
-                    stats.bladder,
-                    stats.room
-                );
-                msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
-            }
-
-            PlayerAction::DidntTakeTurn
+    #[test]
+    fn a_tired_npc_heads_for_a_bed() {
+        let mut game = one_room_game();
+        create_room(Rect::new(1, 1, 20, 10), &mut game.map);
+        game.objects.push(npc_at(4, 5));
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 3, 8));
+        game.objects.push(make_furniture(FurnitureKind::Bed, 15, 5));
+        game.assign_object_ids();
+        game.objects[1].stats.as_mut().unwrap().energy = NPC_LOW_NEED - 10;
+        let fov = fov_for(&game.map);
+
+        let to_bed = |game: &Game| game.objects[1].distance(15, 5);
+        let start = to_bed(&game);
+        npc_turns(&fov, &mut game);
+        assert!(to_bed(&game) < start);
+
+        for _ in 0..15 {
+            npc_turns(&fov, &mut game);
         }
+        let npc = game.objects[1].id;
+        assert_eq!(game.objects[3].furniture.unwrap().occupied_by, Some(npc));
+        assert!(game.objects[1].stats.unwrap().energy >= NPC_LOW_NEED);
+    }
 
-        _ => PlayerAction::DidntTakeTurn,
+    #[test]
+    fn a_bed_takes_up_both_of_its_tiles() {
+        let mut game = one_room_game();
+        game.objects.push(make_furniture(FurnitureKind::Bed, 3, 2));
+        assert_eq!(game.objects[1].footprint(), vec![(3, 2), (4, 2)]);
+        assert!(is_blocked(3, 2, &game.map, &game.objects));
+        assert!(is_blocked(4, 2, &game.map, &game.objects));
+        assert!(!is_blocked(5, 2, &game.map, &game.objects));
+
+        // it can be used from next to either end
+        for &(from, to) in &[((2, 2), (3, 2)), ((5, 2), (4, 2)), ((4, 3), (4, 2))] {
+            game.objects[PLAYER].set_pos(from.0, from.1);
+            assert!(matches!(
+                resolve_interaction(to.0, to.1, &game),
+                Interaction::UseFurniture(1)
+            ));
+        }
     }
-}
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-enum PlayerAction {
-    TookTurn,
-    DidntTakeTurn,
-    Exit,
-}
+    #[test]
+    fn a_worn_out_bed_stops_restoring_energy() {
+        let mut game = one_room_game();
+        game.objects.push(make_furniture(FurnitureKind::Bed, 3, 2));
+        let uses = FurnitureKind::Bed.max_durability().unwrap();
+        for _ in 0..uses {
+            game.objects[PLAYER].stats.as_mut().unwrap().energy = 10;
+            use_furniture(1, &mut game);
+            assert!(game.objects[PLAYER].stats.unwrap().energy > 10);
+        }
+        assert!(game.objects[1].furniture.unwrap().is_broken());
 
-fn player_death(player: &mut Object, game: &mut Game) {
-    // the game ended!
-    game.log.add("You died!", colors::RED);
+        game.objects[PLAYER].stats.as_mut().unwrap().energy = 10;
+        use_furniture(1, &mut game);
+        assert_eq!(game.objects[PLAYER].stats.unwrap().energy, 10);
+        let broken = t("furniture_broken", &[("furniture", &FurnitureKind::Bed)]);
+        assert_eq!(game.log.last().unwrap().0, broken);
+    }
 
-    // for added effect, transform the player into a corpse!
-    player.char = '%';
-    player.color = colors::DARK_RED;
-}
+    #[test]
+    fn showers_need_the_water_on() {
+        let mut game = one_room_game();
+        game.objects
+            .push(make_furniture(FurnitureKind::Shower, 3, 2));
+        game.objects[PLAYER].stats.as_mut().unwrap().hygiene = 20;
+        game.water_on = false;
+        use_furniture(1, &mut game);
+        assert_eq!(game.objects[PLAYER].stats.unwrap().hygiene, 20);
+        let dry = t(
+            "furniture_no_water",
+            &[("furniture", &FurnitureKind::Shower)],
+        );
+        assert_eq!(game.log.last().unwrap().0, dry);
+
+        // turning the valve back on fixes it
+        game.objects
+            .push(make_furniture(FurnitureKind::Valve, 5, 2));
+        use_furniture(2, &mut game);
+        assert!(game.water_on);
+        use_furniture(1, &mut game);
+        assert!(game.objects[PLAYER].stats.unwrap().hygiene > 20);
+    }
 
-fn npc_death(npc: &mut Object, game: &mut Game) {
-    // transform it into a nasty corpse! it doesn't block, can't be
-    // attacked and doesn't move
-    game.log
-        .add(format!("Oh no! {} is dead!", npc.name), colors::ORANGE);
-    npc.char = '%';
-    npc.color = colors::DARK_RED;
-    npc.blocks = false;
-    npc.stats = None;
-    npc.ai = None;
-    npc.name = format!("remains of {}", npc.name);
-}
+    #[test]
+    fn bumping_a_closed_door_opens_it() {
+        let mut game = one_room_game();
+        let mut door = Object::new(3, 2, '+', "door", colors::DARKER_ORANGE, true);
+        door.door = Some(Door { open: false });
+        game.objects.push(door);
+        assert!(matches!(
+            resolve_interaction(3, 2, &game),
+            Interaction::OpenDoor(1)
+        ));
+        open_door(1, &mut game);
+        assert!(matches!(
+            resolve_interaction(3, 2, &game),
+            Interaction::Move
+        ));
+        walk(1, 0, &mut game);
+        assert_eq!(game.objects[PLAYER].pos(), (3, 2));
+    }
 
-struct Tcod {
-    root: Root,
-    con: Offscreen,
-    panel: Offscreen,
-    fov: FovMap,
-    mouse: Mouse,
-}
+    #[test]
+    fn bumping_a_housemate_talks_to_them() {
+        let mut game = one_room_game();
+        game.objects.push(npc_at(3, 2));
+        game.objects[PLAYER].stats.as_mut().unwrap().social = 20;
+        assert!(matches!(
+            resolve_interaction(3, 2, &game),
+            Interaction::Talk(1)
+        ));
+        talk_to(1, &mut game);
+        assert!(game.objects[PLAYER].stats.unwrap().social > 20);
+        assert_eq!(game.objects[PLAYER].pos(), (2, 2));
+    }
 
-#[derive(Serialize, Deserialize)]
-struct Game {
-    map: Map,
-    log: Messages,
-    inventory: Vec<Object>,
-    dungeon_level: u32,
-    objects: Vec<Object>,
-}
+    #[test]
+    fn bumping_furniture_uses_it() {
+        let mut game = one_room_game();
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 3, 2));
+        assert!(matches!(
+            resolve_interaction(3, 2, &game),
+            Interaction::UseFurniture(1)
+        ));
+    }
 
-trait MessageLog {
-    fn add<T: Into<String>>(&mut self, message: T, color: Color);
-}
+    #[test]
+    fn stepping_onto_an_item_offers_to_pick_it_up() {
+        let mut game = one_room_game();
+        game.objects.push(make_item(Item::Heal, 3, 2));
+        assert!(matches!(
+            resolve_interaction(3, 2, &game),
+            Interaction::StepOntoItem(1)
+        ));
+
+        // turned down, it stays on the floor under the player
+        let mut asked = String::new();
+        step_onto_item(1, 1, 0, &mut game, |q| {
+            asked = q.to_string();
+            false
+        });
+        assert_eq!(
+            asked,
+            t("confirm_pick_up", &[("item", &game.objects[1].name)])
+        );
+        assert_eq!(game.objects[PLAYER].pos(), (3, 2));
+        assert_eq!(game.objects.len(), 2);
+
+        // accepted, it goes in the inventory
+        game.objects[PLAYER].set_pos(2, 2);
+        step_onto_item(1, 1, 0, &mut game, |_| true);
+        assert_eq!(game.objects[PLAYER].pos(), (3, 2));
+        assert_eq!(game.objects.len(), 1);
+        assert_eq!(game.inventory[0].item, Some(Item::Heal));
+    }
 
-impl MessageLog for Vec<(String, Color)> {
-    fn add<T: Into<String>>(&mut self, message: T, color: Color) {
-        self.push((message.into(), color));
+    #[test]
+    fn open_floor_moves_and_walls_block() {
+        let game = one_room_game();
+        assert!(matches!(
+            resolve_interaction(3, 2, &game),
+            Interaction::Move
+        ));
+        assert!(matches!(
+            resolve_interaction(1, 2, &game),
+            Interaction::Blocked
+        ));
     }
-}
 
-fn new_game(tcod: &mut Tcod) -> Game {
-    // create object representing the player
-    let mut player = Object::new(0, 0, '@', "player", colors::WHITE, true);
-    player.alive = true;
-    player.stats = Some(Stats {
-        base_max_all_stats: 100,
-        hunger: 100,
-        comfort: 100,
-        hygiene: 100,
-        bladder: 100,
-        energy: 100,
-        fun: 100,
-        social: 100,
-        room: 100,
-        on_death: DeathCallback::Player,
-    });
+    #[test]
+    fn the_nearest_source_is_the_closer_one() {
+        let mut game = one_room_game();
+        create_room(Rect::new(1, 1, 20, 10), &mut game.map);
+        for column in game.map.iter_mut() {
+            for tile in column.iter_mut() {
+                tile.explored = true;
+            }
+        }
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 15, 5));
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 6, 3));
+        game.objects.push(make_furniture(FurnitureKind::Bed, 4, 2));
+        assert_eq!(nearest_source_for(Need::Hunger, &game), Some((6, 3)));
+        assert_eq!(nearest_source_for(Need::Energy, &game), Some((4, 2)));
+        assert_eq!(nearest_source_for(Need::Hygiene, &game), None);
+
+        // one the player hasn't seen yet doesn't count
+        game.map[6][3].explored = false;
+        assert_eq!(nearest_source_for(Need::Hunger, &game), Some((15, 5)));
+    }
 
-    let mut objects = vec![player];
-    let level = 1;
+    #[test]
+    fn retries_keep_going_until_the_op_succeeds() {
+        let failing = |failures: u32| {
+            let mut calls = 0;
+            move || {
+                calls += 1;
+                if calls <= failures {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!("try {}", calls),
+                    ))
+                } else {
+                    Ok(calls)
+                }
+            }
+        };
+        assert_eq!(with_retries(4, 0, failing(0)).unwrap(), 1);
+        assert_eq!(with_retries(4, 0, failing(3)).unwrap(), 4);
+        // out of attempts, the last error comes back
+        let error = with_retries(3, 0, failing(5)).unwrap_err();
+        assert_eq!(error.to_string(), "try 3");
+    }
 
-    let mut game = Game {
-        // generate map (at this point it's not drawn to the screen)
-        map: make_map(&mut objects, level),
-        // create the list of game messages and their colors, starts empty
-        log: vec![],
-        inventory: vec![],
-        dungeon_level: level,
-        // the list of objects with just the player
-        objects: objects,
-    };
+    #[test]
+    fn tags_can_be_queried_and_survive_a_save() {
+        let mut game = one_room_game();
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 3, 2));
+        game.objects.push(npc_at(4, 2));
+        game.objects.push(make_item(Item::Heal, 5, 2));
+        game.objects[3].add_tag("shiny");
+
+        assert!(game.objects[1].has_tag(TAG_FURNITURE));
+        assert!(!game.objects[2].has_tag(TAG_FURNITURE));
+        assert_eq!(
+            game.objects_with_tag(TAG_FURNITURE).collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert_eq!(
+            game.objects_with_tag(TAG_INTERACTIVE).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(game.objects_with_tag("shiny").collect::<Vec<_>>(), vec![3]);
+        assert_eq!(game.objects_with_tag("nothing").count(), 0);
 
-    // initial equipment: a dagger
-    let mut dagger = Object::new(0, 0, '-', "dagger", colors::SKY, false);
-    dagger.item = Some(Item::Sword);
-    dagger.equipment = Some(Equipment {
-        equipped: true,
-        slot: Slot::LeftHand,
-        max_hp_bonus: 0,
-        defense_bonus: 0,
-        power_bonus: 2,
-    });
-    game.inventory.push(dagger);
+        let saved = serde_json::to_string(&game).unwrap();
+        let loaded: Game = serde_json::from_str(&saved).unwrap();
+        for (before, after) in game.objects.iter().zip(&loaded.objects) {
+            assert_eq!(before.tags, after.tags);
+        }
+        assert_eq!(
+            loaded.objects_with_tag("shiny").collect::<Vec<_>>(),
+            vec![3]
+        );
+    }
 
-    initialise_fov(&game.map, tcod);
+    #[test]
+    fn the_thumbnail_shows_the_explored_map() {
+        let mut game = one_room_game();
+        for x in 0..8 {
+            for y in 0..8 {
+                game.map[x][y].explored = true;
+            }
+        }
+        game.turn = 123;
+        let blank = |n| " ".repeat(n);
+        let columns = (MAP_WIDTH / THUMBNAIL_SCALE) as usize;
+        let mut expected = vec![blank(columns); (MAP_HEIGHT / THUMBNAIL_SCALE) as usize];
+        expected[0] = format!("@.{}", blank(columns - 2));
+        expected[1] = format!("##{}", blank(columns - 2));
+        assert_eq!(thumbnail(&game), expected);
+
+        // and it comes back out of a save for the load menu
+        let path = std::env::temp_dir().join(format!("lardum-preview-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_game_to(&game, path).unwrap();
+        let preview = load_preview(path).unwrap();
+        let _ = fs::remove_file(path);
+        assert_eq!(preview.thumbnail, expected);
+        assert_eq!(preview.turn, 123);
+        assert_eq!(preview.dungeon_level, game.dungeon_level);
+    }
 
-    // a warm welcoming message!
-    game.log.add("Welcome to your new home!", colors::RED);
+    #[test]
+    fn a_reset_forgets_the_last_games_screen() {
+        let game = one_room_game();
+        let mut transients = Transients::new();
+        transients.fov = fov_for(&game.map);
+        transients
+            .fov
+            .compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        transients.lit_from = Some((2, 2));
+        transients.reveal_all = true;
+        transients.particles.push(Particle {
+            x: 3.0,
+            y: 2.0,
+            dx: 0.0,
+            dy: 0.0,
+            char: '*',
+            color: colors::WHITE,
+            lifetime: 10,
+        });
+        transients.effects.shake();
+        transients.frame = 12;
+        transients.key_queue.push_back(Key::default());
+        transients.camera.x = 7;
+        assert!(transients.fov.is_in_fov(3, 2));
+
+        transients.reset();
+        assert!(transients.particles.is_empty());
+        assert!(!transients.fov.is_in_fov(3, 2));
+        assert_eq!(transients.lit_from, None);
+        assert!(!transients.reveal_all);
+        assert_eq!(transients.effects.shake_frames, 0);
+        assert_eq!(transients.frame, 0);
+        assert!(transients.key_queue.is_empty());
+        assert_eq!(transients.camera.x, 0);
+    }
 
-    game
-}
+    #[test]
+    fn levels_get_biomes_with_their_own_colors() {
+        let mut rng = GameRng::new(3);
+        assert_eq!(Biome::for_level(1, &mut rng), Biome::House);
+        let biomes: Vec<Biome> = (2..50)
+            .map(|level| Biome::for_level(level, &mut rng))
+            .collect();
+        for biome in Biome::ALL.iter() {
+            assert!(biomes.contains(biome));
+        }
 
-fn initialise_fov(map: &Map, tcod: &mut Tcod) {
-    // create the FOV map, according to the generated map
-    for y in 0..MAP_HEIGHT {
-        for x in 0..MAP_WIDTH {
-            tcod.fov.set(
-                x,
-                y,
-                !map[x as usize][y as usize].block_sight,
-                !map[x as usize][y as usize].blocked,
+        for &wall in &[true, false] {
+            let palettes: Vec<(Color, Color)> = Biome::ALL
+                .iter()
+                .map(|&biome| tile_colors(wall, false, biome))
+                .collect();
+            for (i, a) in palettes.iter().enumerate() {
+                assert!(palettes[i + 1..].iter().all(|b| a != b));
+            }
+            // high contrast looks the same everywhere
+            assert_eq!(
+                tile_colors(wall, true, Biome::House),
+                tile_colors(wall, true, Biome::Garden)
             );
         }
     }
 
-    // unexplored areas start black (which is the default background color)
-    tcod.con.clear();
-}
-
-fn play_game(game: &mut Game, tcod: &mut Tcod) {
-    // force FOV "recompute" first time through the game loop
-    let mut previous_player_position = (-1, -1);
+    #[test]
+    fn starting_deeper_uses_that_levels_spawn_tables() {
+        let items_on = |start_level: u32| {
+            let config = Config {
+                start_level,
+                ..Config::default()
+            };
+            let mut items = vec![];
+            for seed in &["a", "b", "c", "d"] {
+                let game = create_game(
+                    Difficulty::Normal,
+                    config.start_level,
+                    false,
+                    Some(seed),
+                    config.clone(),
+                    vec![],
+                );
+                assert_eq!(game.dungeon_level, start_level);
+                items.extend(game.objects.iter().filter_map(|object| object.item));
+            }
+            items
+        };
+        // only healing shows up on the first level
+        let first = items_on(1);
+        assert!(!first.is_empty());
+        assert!(first.iter().all(|&item| item == Item::Heal));
+        // fireballs don't turn up before level 6
+        assert!(items_on(6).contains(&Item::Fireball));
+    }
 
-    let mut key = Default::default();
+    #[test]
+    fn reveal_all_draws_what_the_player_hasnt_seen() {
+        let mut game = one_room_game();
+        create_room(Rect::new(20, 20, 4, 4), &mut game.map);
+        game.objects.push(npc_at(21, 21));
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+
+        let mut con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+        paint_tiles(&mut con, &fov, &mut game, None, None, true);
+        paint_objects(&mut con, &fov, &game, true, 0);
+        assert_eq!(con.get_char(21, 21), '@');
+        assert_ne!(con.get_char_background(22, 22), colors::BLACK);
+        // without exploring any of it
+        assert!(!game.map[21][21].explored);
+    }
 
-    while !tcod.root.window_closed() {
-        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-            Some((_, Event::Mouse(m))) => tcod.mouse = m,
-            Some((_, Event::Key(k))) => key = k,
-            _ => key = Default::default(),
-        }
+    #[test]
+    fn diagnostics_go_to_the_log_once_it_is_open() {
+        let dir = std::env::temp_dir();
+        let log_path = dir.join(format!("lardum-diag-{}.log", std::process::id()));
+        let log_path = log_path.to_str().unwrap();
+        let save_path = dir.join(format!("lardum-diag-{}.json", std::process::id()));
+        let save_path = save_path.to_str().unwrap();
+        let _ = fs::remove_file(log_path);
+
+        // nothing is written before the log is opened
+        diag("start", "too early");
+        open_diag_log(log_path).unwrap();
+        let mut game = one_room_game();
+        game.turn = 42;
+        save_game_to(&game, save_path).unwrap();
+
+        let written = fs::read_to_string(log_path).unwrap();
+        let _ = fs::remove_file(log_path);
+        let _ = fs::remove_file(save_path);
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let (time, rest) = lines[0].split_at(lines[0].find(' ').unwrap());
+        assert!(time.parse::<f64>().is_ok());
+        assert_eq!(rest, format!(" save path={} turn=42", save_path));
+    }
 
-        // render the screen
-        let fov_recompute = previous_player_position != (game.objects[PLAYER].pos());
-        render_all(tcod, game, fov_recompute);
+    #[test]
+    fn dimming_fades_colors_towards_black() {
+        let color = Color {
+            r: 200,
+            g: 100,
+            b: 51,
+        };
+        assert_eq!(dim_color(color, 0.0), color);
+        assert_eq!(dim_color(color, 1.0), colors::BLACK);
+        assert_eq!(
+            dim_color(color, 0.5),
+            Color {
+                r: 100,
+                g: 50,
+                b: 26
+            }
+        );
+        // out of range amounts are clamped rather than wrapping around
+        assert_eq!(dim_color(color, 2.0), colors::BLACK);
+        assert_eq!(dim_color(color, -1.0), color);
+    }
 
-        tcod.root.flush();
+    #[test]
+    fn the_summary_lists_the_player_first() {
+        let mut game = one_room_game();
+        game.objects
+            .push(make_furniture(FurnitureKind::Fridge, 3, 2));
+        let summaries = game.describe_all();
+        assert_eq!(summaries.len(), 2);
+
+        let player = &summaries[0];
+        assert_eq!(player.name, game.objects[PLAYER].name);
+        assert_eq!((player.x, player.y), (2, 2));
+        assert!(player.alive);
+        assert_eq!(player.stats, game.objects[PLAYER].stats);
+        assert_eq!(summaries[1].furniture, Some(FurnitureKind::Fridge));
+
+        let json = serde_json::to_value(&summaries).unwrap();
+        assert_eq!(json[0]["x"], 2);
+        assert_eq!(json[0]["alive"], true);
+    }
 
-        // erase all objects at their old locations, before they move
-        for object in game.objects.iter_mut() {
-            object.clear(&mut tcod.con)
+    #[test]
+    fn item_descriptions_fill_in_the_real_numbers() {
+        for &item in Item::ALL.iter() {
+            let description = item.description();
+            assert!(!description.is_empty());
+            assert!(!description.contains('{'), "{}", description);
         }
 
-        // handle keys and exit game if needed
-        previous_player_position = game.objects[PLAYER].pos();
-        let player_action = handle_keys(key, tcod, game);
-        if player_action == PlayerAction::Exit {
-            save_game(game).unwrap();
-            tcod.root.clear();
-            tcod.root.flush();
-            break;
+        let (_, amount) = Item::Heal.restores().unwrap();
+        assert!(Item::Heal
+            .description()
+            .ends_with(&format!("Restores {} bladder.", amount)));
+        let sword = make_item(Item::Sword, 0, 0).equipment.unwrap();
+        assert!(Item::Sword.description().ends_with(&format!(
+            "Held in the {}, it gives +{} power.",
+            sword.slot, sword.power_bonus
+        )));
+    }
+
+    #[test]
+    fn moving_turns_to_face_the_way_taken() {
+        let mut game = one_room_game();
+        assert_eq!(game.objects[PLAYER].facing, None);
+        let steps = [
+            ((1, 0), Direction::East),
+            ((0, 1), Direction::South),
+            ((-1, 0), Direction::West),
+            ((0, -1), Direction::North),
+            // diagonals count as sideways
+            ((1, 1), Direction::East),
+        ];
+        for &((dx, dy), facing) in steps.iter() {
+            walk(dx, dy, &mut game);
+            assert_eq!(game.objects[PLAYER].facing, Some(facing));
         }
+
+        // NPCs show which way they're facing
+        game.objects.push(npc_at(4, 2));
+        move_by(1, 1, 0, &game.map, &mut game.objects);
+        assert_eq!(game.objects[1].facing, Some(Direction::East));
+        assert_eq!(game.objects[1].glyph(0), '>');
+        // bumping into a wall doesn't turn anyone round
+        move_by(1, 0, 1, &game.map, &mut game.objects);
+        move_by(1, 1, 0, &game.map, &mut game.objects);
+        assert_eq!(game.objects[1].pos(), (5, 3));
+        assert_eq!(game.objects[1].facing, Some(Direction::South));
     }
-}
 
-fn save_game(game: &Game) -> Result<(), Box<Error>> {
-    let save_data = serde_json::to_string(&game)?;
-    let mut file = File::create("game.sav")?;
-    file.write_all(save_data.as_bytes())?;
-    Ok(())
-}
+    #[test]
+    fn confused_npcs_stand_out() {
+        let mut npc = npc_at(3, 2);
+        assert_eq!(npc.render_color(false), colors::LIGHT_BLUE);
+        npc.ai = Some(Ai::Confused {
+            previous_ai: Box::new(Ai::Basic),
+            num_turns: 3,
+        });
+        assert_eq!(npc.render_color(false), COLOR_CONFUSED);
+        assert_eq!(npc.render_color(true), COLOR_CONFUSED);
+
+        let mut con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+        npc.draw(&mut con, false, 0);
+        assert_eq!(con.get_char_foreground(3, 2), COLOR_CONFUSED);
+    }
 
-fn load_game() -> Result<Game, Box<Error>> {
-    let mut json_save_state = String::new();
-    let mut file = File::open("game.sav")?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<Game>(&json_save_state)?;
-    Ok(result)
-}
+    #[test]
+    fn scrolling_stays_within_the_text() {
+        let wheel = |up, down| Mouse {
+            wheel_up: up,
+            wheel_down: down,
+            ..Default::default()
+        };
+        assert_eq!(wheel_delta(&wheel(false, true)), 1);
+        assert_eq!(wheel_delta(&wheel(true, false)), -1);
+        assert_eq!(wheel_delta(&Mouse::default()), 0);
 
-fn main_menu(tcod: &mut Tcod) {
-    while !tcod.root.window_closed() {
-        tcod.root.set_default_foreground(colors::LIGHT_YELLOW);
-        tcod.root.print_ex(
-            SCREEN_WIDTH / 2,
-            SCREEN_HEIGHT / 2 - 4,
-            BackgroundFlag::None,
-            TextAlignment::Center,
-            "LARDUM",
+        // a few clicks down past the end, then back up past the start
+        let deltas = [1, 1, 1, 0, 1, 1, -1, -1, -1, -1, -1];
+        let offsets: Vec<usize> = deltas
+            .iter()
+            .scan(0, |offset, &delta| {
+                *offset = scroll_offset(*offset, delta, 4);
+                Some(*offset)
+            })
+            .collect();
+        assert_eq!(offsets, vec![1, 2, 3, 3, 4, 4, 3, 2, 1, 0, 0]);
+        assert_eq!(scroll_offset(2, 10, 4), 4);
+        assert_eq!(scroll_offset(0, 3, 0), 0);
+    }
+
+    #[test]
+    fn high_contrast_draws_solid_walls() {
+        assert_eq!(tile_char(true, true), HIGH_CONTRAST_WALL_CHAR);
+        assert_eq!(tile_char(true, false), ' ');
+        assert_eq!(tile_char(false, true), ' ');
+
+        let mut game = one_room_game();
+        game.config.high_contrast = true;
+        let mut fov = fov_for(&game.map);
+        fov.compute_fov(2, 2, TORCH_RADIUS, FOV_LIGHT_WALLS, FOV_ALGO);
+        let mut con = Offscreen::new(MAP_WIDTH, MAP_HEIGHT);
+        paint_tiles(&mut con, &fov, &mut game, None, None, false);
+        assert_eq!(con.get_char(1, 1), HIGH_CONTRAST_WALL_CHAR);
+        assert_eq!(con.get_char_background(1, 1), HC_LIGHT_WALL);
+        assert_eq!(con.get_char(3, 2), ' ');
+        assert_eq!(con.get_char_background(3, 2), HC_LIGHT_GROUND);
+    }
+
+    #[test]
+    fn comparing_equipment_shows_the_difference() {
+        let sword = make_item(Item::Sword, 0, 0).equipment.unwrap();
+        let better = Equipment {
+            power_bonus: sword.power_bonus + 2,
+            defense_bonus: 1,
+            ..sword
+        };
+        assert_eq!(
+            equipment_delta(&better, Some(&sword)),
+            "+2 power, +1 defense vs equipped"
         );
-        tcod.root.print_ex(
-            SCREEN_WIDTH / 2,
-            SCREEN_HEIGHT - 2,
-            BackgroundFlag::None,
-            TextAlignment::Center,
-            "By Matt Fisher",
+        assert_eq!(
+            equipment_delta(&sword, Some(&better)),
+            "-2 power, -1 defense vs equipped"
         );
+        assert_eq!(equipment_delta(&sword, Some(&sword)), "same as equipped");
+        assert_eq!(
+            equipment_delta(&sword, None),
+            format!("+{} power", sword.power_bonus)
+        );
+    }
 
-        // show options and wait for the player's choice
-        let choices = &["Play a new game", "Continue last game", "Quit"];
-        let choice = menu("", choices, 24, &mut tcod.root);
-
-        match choice {
-            Some(0) => {
-                // new game
-                let mut game = new_game(tcod);
-                play_game(&mut game, tcod);
+    #[test]
+    fn moods_follow_the_needs() {
+        let with = |values: &[(Need, i32)], rest: i32| {
+            let mut stats = npc_at(0, 0).stats.unwrap();
+            for &need in Need::ALL.iter() {
+                *stats.need_mut(need) = rest;
             }
-            Some(1) => {
-                // load game
-                match load_game() {
-                    Ok(mut game) => {
-                        initialise_fov(&game.map, tcod);
-                        play_game(&mut game, tcod);
+            for &(need, value) in values {
+                *stats.need_mut(need) = value;
+            }
+            compute_mood(&stats)
+        };
+        assert_eq!(with(&[], 100), Mood::Happy);
+        assert_eq!(with(&[], 70), Mood::Happy);
+        assert_eq!(with(&[], 69), Mood::Neutral);
+        assert_eq!(with(&[], 30), Mood::Sad);
+        assert_eq!(with(&[], 10), Mood::Miserable);
+
+        // the basics weigh more than the rest
+        let basics = [(Need::Hunger, 0), (Need::Bladder, 0), (Need::Energy, 0)];
+        assert_eq!(with(&basics, 100), Mood::Sad);
+        let extras = [(Need::Fun, 0), (Need::Social, 0), (Need::Room, 0)];
+        assert_eq!(with(&extras, 100), Mood::Happy);
+    }
+
+    #[test]
+    fn shakes_calm_down_as_they_run_out() {
+        let mut effects = ScreenEffects::default();
+        assert_eq!(shake_offset(effects.shake_frames), 0);
+        effects.shake();
+        let mut offsets = vec![];
+        for _ in 0..=SHAKE_FRAMES {
+            offsets.push(shake_offset(effects.shake_frames));
+            effects.tick();
+        }
+        assert_eq!(offsets[0], SHAKE_MAGNITUDE);
+        assert_eq!(*offsets.last().unwrap(), 0);
+        // it never grows again, and it keeps going right until the end
+        assert!(offsets.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert_eq!(offsets[SHAKE_FRAMES as usize - 1], 1);
+        assert_eq!(shake_offset(-3), 0);
+    }
+
+    #[test]
+    fn a_custom_loadout_fills_the_starting_inventory() {
+        let start_with = |loadout: Vec<LoadoutEntry>| {
+            let config = Config {
+                starting_loadout: loadout,
+                ..Config::default()
+            };
+            let game = create_game(Difficulty::Normal, 1, false, Some("test"), config, vec![]);
+            game.inventory
+                .iter()
+                .map(|object| (object.item.unwrap(), object.count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(start_with(vec![]), vec![]);
+        assert_eq!(
+            start_with(vec![
+                LoadoutEntry {
+                    item: Item::Heal,
+                    count: 3,
+                },
+                LoadoutEntry {
+                    item: Item::Sword,
+                    count: 1,
+                },
+            ]),
+            vec![
+                (Item::Heal, 1),
+                (Item::Heal, 1),
+                (Item::Heal, 1),
+                (Item::Sword, 1)
+            ]
+        );
+        // no more than fits
+        let swords = start_with(vec![LoadoutEntry {
+            item: Item::Sword,
+            count: 100,
+        }]);
+        assert_eq!(swords.len(), INVENTORY_CAPACITY);
+    }
+
+    #[test]
+    fn every_key_is_on_the_help_screen_once() {
+        // the keys `handle_keys` matches on, read from its source
+        let source = include_str!("main.rs");
+        let start = source.find("\nfn handle_keys(").unwrap();
+        let end = start + source[start..].find("\n}\n").unwrap();
+        let mut handled = vec![];
+        for pattern in source[start..end].split("Key {").skip(1) {
+            let pattern = &pattern[..pattern.find('}').unwrap()];
+            let field = |name: &str| {
+                let at = pattern.find(name)? + name.len();
+                let value = pattern[at..].trim_start();
+                Some(value[..value.find(',').unwrap_or(value.len())].trim())
+            };
+            let name = match (field("printable:"), field("code:")) {
+                (Some(printable), _) => printable.trim_matches('\'').to_string(),
+                (None, Some(code)) => match code {
+                    "PageUp" => "pgup".into(),
+                    "PageDown" => "pgdn".into(),
+                    "Spacebar" => "space".into(),
+                    _ if code.starts_with("NumPad") => {
+                        format!("numpad {}", &code[6..]).to_lowercase()
                     }
-                    Err(_e) => {
-                        msgbox("\nNo saved game to load.\n", 24, &mut tcod.root);
-                        continue;
+                    _ => code.to_lowercase(),
+                },
+                _ => continue,
+            };
+            let name = match field("alt:") {
+                Some("true") => format!("alt+{}", name),
+                _ => name,
+            };
+            if !handled.contains(&name) {
+                handled.push(name);
+            }
+        }
+
+        // and the ones on the help screen, "numpad 8/2/4/6" being four of them
+        let mut listed = vec![];
+        for help in KEY_HELP {
+            for group in help.keys.split(", ") {
+                // single characters are case sensitive, names aren't
+                let group = if group.len() > 1 {
+                    group.to_lowercase()
+                } else {
+                    group.to_string()
+                };
+                let (prefix, keys) = match group.rfind(' ') {
+                    Some(at) => (format!("{} ", &group[..at]), &group[at + 1..]),
+                    None => (String::new(), &group[..]),
+                };
+                for key in keys.split('/') {
+                    if key == "arrows" {
+                        listed.extend(
+                            ["up", "down", "left", "right"]
+                                .iter()
+                                .map(|k| k.to_string()),
+                        );
+                    } else {
+                        listed.push(format!("{}{}", prefix, key));
                     }
                 }
             }
-            Some(2) => {
-                // quit
-                break;
-            }
-            _ => {}
+        }
+
+        for key in &handled {
+            let times = listed.iter().filter(|listed| *listed == key).count();
+            assert_eq!(times, 1, "{} is listed {} times", key, times);
+        }
+        for key in &listed {
+            assert!(handled.contains(key), "{} isn't handled", key);
         }
     }
-}
 
-fn main() {
-    let root = Root::initializer()
-        .font("assets/consolas12x12_gs_tc.png", FontLayout::Tcod)
-        .font_type(FontType::Greyscale)
-        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
-        .title("Lardum")
-        .init();
-    tcod::system::set_fps(LIMIT_FPS);
+    #[test]
+    fn pause_menu_picks_map_to_choices() {
+        let picks: Vec<PauseChoice> = (0..5).map(|index| pause_choice(Some(index))).collect();
+        assert_eq!(
+            picks,
+            vec![
+                PauseChoice::Resume,
+                PauseChoice::Save,
+                PauseChoice::Options,
+                PauseChoice::MainMenu,
+                PauseChoice::Quit,
+            ]
+        );
+        // closing the menu or anything out of range goes back to the game
+        assert_eq!(pause_choice(None), PauseChoice::Resume);
+        assert_eq!(pause_choice(Some(5)), PauseChoice::Resume);
+        assert_eq!(pause_choice(Some(usize::MAX)), PauseChoice::Resume);
+    }
 
-    let mut tcod = Tcod {
-        root: root,
-        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
-        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
-        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
-        mouse: Default::default(),
-    };
+    #[test]
+    fn autosaves_come_at_the_interval() {
+        let due: Vec<u32> = (0..=250)
+            .filter(|&turn| should_autosave(turn, 100))
+            .collect();
+        assert_eq!(due, vec![100, 200]);
+        let due: Vec<u32> = (0..=20).filter(|&turn| should_autosave(turn, 7)).collect();
+        assert_eq!(due, vec![7, 14]);
+        // an interval of 0 turns them off
+        assert!((0..1000).all(|turn| !should_autosave(turn, 0)));
+    }
+
+    #[test]
+    fn need_history_samples_now_and_then_and_keeps_the_latest() {
+        let mut game = one_room_game();
+        game.need_history = NeedHistory::default();
+        tick_turns(&mut game, HISTORY_SAMPLE_INTERVAL * 2 + 5);
+        assert_eq!(game.need_history.samples.len(), 2);
+
+        // once full, each new sample pushes out the oldest
+        let mut history = NeedHistory::default();
+        let mut stats = game.objects[PLAYER].stats.unwrap();
+        for hunger in 0..HISTORY_CAPACITY as i32 + 5 {
+            stats.hunger = hunger;
+            history.record(&stats);
+        }
+        assert_eq!(history.samples.len(), HISTORY_CAPACITY);
+        let hunger = |sample: &[i32; 8]| sample[0];
+        assert_eq!(hunger(history.samples.front().unwrap()), 5);
+        assert_eq!(
+            hunger(history.samples.back().unwrap()),
+            HISTORY_CAPACITY as i32 + 4
+        );
+    }
+
+    #[test]
+    fn held_keys_repeat_after_a_delay() {
+        let right = Key {
+            code: KeyCode::Right,
+            ..Default::default()
+        };
+        let mut repeat = KeyRepeat::default();
+        assert!(repeat.tick().is_none());
+
+        repeat.press(right);
+        let fired: Vec<i32> = (1..=12).filter(|_| repeat.tick().is_some()).collect();
+        assert_eq!(fired, vec![6, 8, 10, 12]);
+        assert!((1..KEY_REPEAT_DELAY).all(|frames| !should_repeat(frames)));
+
+        // letting go of another key doesn't stop it, letting go of this one does
+        repeat.release(KeyCode::Left);
+        assert!(repeat.tick().is_none());
+        assert!(repeat.tick().is_some());
+        repeat.release(KeyCode::Right);
+        assert!((0..20).all(|_| repeat.tick().is_none()));
+    }
 
-    main_menu(&mut tcod);
+    #[test]
+    fn particles_drift_and_expire() {
+        let particle = |lifetime| Particle {
+            x: 5.0,
+            y: 5.0,
+            dx: 0.5,
+            dy: -1.0,
+            char: '*',
+            color: colors::WHITE,
+            lifetime,
+        };
+        let mut particles = vec![particle(1), particle(3)];
+        age_particles(&mut particles);
+        assert_eq!(particles.len(), 1);
+        assert_eq!(particles[0].lifetime, 2);
+        assert_eq!(particles[0].pos(), (6, 4));
+
+        age_particles(&mut particles);
+        assert_eq!(particles[0].pos(), (6, 3));
+        age_particles(&mut particles);
+        assert!(particles.is_empty());
+    }
 }